@@ -0,0 +1,26 @@
+//! Feed arbitrary bytes through the ingress manager, as if they arrived over bluetooth
+
+#![no_main]
+
+use std::cell::RefCell;
+
+use libfuzzer_sys::fuzz_target;
+use mwatch_kernel_host::Simulator;
+
+thread_local! {
+    // each simulator leaks its application ram, so reuse one rather than run out of memory
+    static SIM: RefCell<Simulator> = RefCell::new(Simulator::default());
+}
+
+fuzz_target!(|data: &[u8]| {
+    SIM.with(|sim| {
+        let mut sim = sim.borrow_mut();
+        sim.imgr.reset();
+        // split the input like the serial DMA would, processing between each write
+        for chunk in data.chunks(64) {
+            sim.write(chunk);
+            sim.tick();
+        }
+        sim.replies();
+    });
+});
@@ -0,0 +1,19 @@
+//! Parse arbitrary strings as syscalls
+
+#![no_main]
+
+use core::str::FromStr;
+
+use libfuzzer_sys::fuzz_target;
+use mwatch_kernel::system::syscall::Syscall;
+
+fuzz_target!(|s: &str| {
+    let _ = Syscall::from_str(s);
+    let _ = Syscall::date_from_str(s);
+    let _ = Syscall::time_from_str(s);
+    let _ = Syscall::date_time_from_str(s);
+    let _ = Syscall::utc_offset_from_str(s);
+    let _ = Syscall::quiet_hours_from_str(s);
+    let _ = Syscall::alarm_from_str(s);
+    let _ = Syscall::timer_from_str(s);
+});
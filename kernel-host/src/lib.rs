@@ -0,0 +1,12 @@
+//! Host simulator
+//!
+//! A `std` implementation of the kernel's [`Host`](mwatch_kernel::system::Host) trait, allowing the kernel to be
+//! driven headlessly on a desktop machine. Useful for testing the display manager, states and the ingress protocol
+//! without the STM32L4 board.
+
+pub mod simulator;
+pub mod snapshot;
+pub mod system;
+
+pub use simulator::Simulator;
+pub use system::{SimBattery, SimClock, SimDisplay, SimHost, SimStats, SimStorage, SimUptime};
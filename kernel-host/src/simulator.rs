@@ -0,0 +1,118 @@
+//! Simulator
+//!
+//! Ties the kernel managers together the same way the hardware tasks do, without interrupts
+
+use mwatch_kernel::{
+    application::{
+        application_manager::{ApplicationManager, Ram},
+        display_manager::{DisplayManager, Signal},
+        states::{ScopedState, State},
+        Table,
+    },
+    ingress::{frame::FrameDecoder, ingress_manager::IngressManager},
+    system::{input::InputEvent, Display, System},
+};
+
+use crate::system::{abi, SimBattery, SimClock, SimDisplay, SimHost, SimStats, SimStorage, SimUptime};
+use time::Duration;
+
+pub const APPLICATION_RAM_BYTES: usize = 16 * 1024;
+/// The time between systicks, matching the 10Hz systick of the hardware
+pub const TICK: Duration = Duration::milliseconds(100);
+
+/// A headless watch
+pub struct Simulator {
+    pub system: System<SimHost>,
+    pub display: SimDisplay,
+    pub dm: DisplayManager,
+    pub imgr: IngressManager,
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self::new(SimClock::default(), SimBattery::default())
+    }
+}
+
+impl Simulator {
+    /// Create a simulator with the provided clock and battery
+    pub fn new(clock: SimClock, bms: SimBattery) -> Self {
+        Self::with_storage(clock, bms, SimStorage::default())
+    }
+
+    /// Create a simulator restoring its notifications and settings from `storage`, as if the watch had been reset
+    pub fn with_storage(clock: SimClock, bms: SimBattery, storage: SimStorage) -> Self {
+        // the kernel expects these to live forever, like the statics on the hardware
+        let ram: &'static mut [u8] = Box::leak(vec![0u8; APPLICATION_RAM_BYTES].into_boxed_slice());
+        let table: &'static mut Table = Box::leak(Box::new(Table {
+            draw_pixel: abi::draw_pixel,
+            print: abi::print,
+        }));
+        let am = ApplicationManager::new(Ram::new(ram), table);
+
+        Self {
+            system: System::new(clock, SimUptime::default(), bms, SimStats::default(), am, storage),
+            display: SimDisplay::default(),
+            dm: DisplayManager::default(),
+            imgr: IngressManager::new(),
+        }
+    }
+
+    /// Push bytes into the ingress manager, as the serial interrupt would, returning how many were accepted
+    pub fn write(&mut self, data: &[u8]) -> usize {
+        self.imgr.write(data)
+    }
+
+    /// Run one systick: advance the uptime by a [`TICK`], process any ingress data, ring any alarms that are due,
+    /// save any changes to storage, then render a fresh frame
+    pub fn tick(&mut self) {
+        self.system.uptime.advance(TICK);
+        self.imgr.process(&mut self.system);
+        self.system.process();
+        self.system.sync();
+        self.render();
+    }
+
+    /// Clear the display and let the display manager render the current state
+    pub fn render(&mut self) {
+        self.display.clear();
+        self.dm.process(&mut self.system, &mut self.display);
+        self.system.stats.frames += 1;
+    }
+
+    /// Render a single state on a blank display, bypassing the display manager
+    pub fn render_state<S: State>(&mut self, state: &mut S) -> Option<Signal> {
+        self.display.clear();
+        state.render(&mut self.system, &mut self.display.framebuffer())
+    }
+
+    /// Render the preview of a scoped state on a blank display
+    pub fn preview_state<S: ScopedState>(&mut self, state: &mut S) -> Option<Signal> {
+        self.display.clear();
+        state.preview(&mut self.system, &mut self.display.framebuffer())
+    }
+
+    /// Drain the frames the kernel has queued for the connected device, returning their type and payload
+    pub fn replies(&mut self) -> Vec<(u8, Vec<u8>)> {
+        let mut bytes = [0u8; 64];
+        let mut decoder = FrameDecoder::new();
+        let mut frames = Vec::new();
+        loop {
+            let len = self.system.em.read(&mut bytes);
+            if len == 0 {
+                break;
+            }
+            for &byte in &bytes[..len] {
+                if let Ok(true) = decoder.decode(byte) {
+                    frames.push((decoder.frame_type(), decoder.payload().to_vec()));
+                }
+            }
+        }
+        frames
+    }
+
+    /// Deliver an input event to the display manager
+    pub fn input(&mut self, input: InputEvent) {
+        self.dm.service_input(&mut self.system, input);
+    }
+}
@@ -0,0 +1,129 @@
+//! Snapshot
+//!
+//! Golden image testing of rendered frames. The [`SimDisplay`] contents are exported as PNG (or PPM) and compared
+//! against images checked into `tests/snapshots`. Set `MWATCH_UPDATE_SNAPSHOTS=1` to (re)generate the golden images.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::system::SimDisplay;
+
+/// Environment variable which, when set, overwrites the golden images with the current output
+pub const UPDATE_ENV: &str = "MWATCH_UPDATE_SNAPSHOTS";
+
+/// Directory the golden images are stored in
+pub fn snapshot_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("snapshots")
+}
+
+/// Expand a Rgb565 colour into 8 bit per channel rgb
+pub fn rgb565_to_rgb888(colour: u16) -> [u8; 3] {
+    let r = ((colour >> 11) & 0x1F) as u8;
+    let g = ((colour >> 5) & 0x3F) as u8;
+    let b = (colour & 0x1F) as u8;
+    [(r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2)]
+}
+
+/// The display contents as packed rgb888
+pub fn to_rgb888(display: &SimDisplay) -> Vec<u8> {
+    display
+        .fb()
+        .chunks(2)
+        .flat_map(|px| rgb565_to_rgb888(u16::from_be_bytes([px[0], px[1]])))
+        .collect()
+}
+
+/// Export the display as a binary (P6) PPM image
+pub fn to_ppm(display: &SimDisplay) -> Vec<u8> {
+    let mut ppm = format!("P6\n{} {}\n255\n", display.width(), display.height()).into_bytes();
+    ppm.extend(to_rgb888(display));
+    ppm
+}
+
+/// Export the display as a PNG image
+pub fn write_png(display: &SimDisplay, path: &Path) -> std::io::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, display.width() as u32, display.height() as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(std::io::Error::other)?;
+    writer
+        .write_image_data(&to_rgb888(display))
+        .map_err(std::io::Error::other)?;
+    writer.finish().map_err(std::io::Error::other)?;
+    Ok(())
+}
+
+/// Write the display to `path` as a PPM image
+pub fn write_ppm(display: &SimDisplay, path: &Path) -> std::io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(&to_ppm(display))
+}
+
+/// Read a PNG image, returning the dimensions and packed rgb888 data
+pub fn read_png(path: &Path) -> std::io::Result<(u32, u32, Vec<u8>)> {
+    let decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    let mut reader = decoder.read_info().map_err(std::io::Error::other)?;
+    let mut data = vec![0u8; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).map_err(std::io::Error::other)?;
+    if info.color_type != png::ColorType::Rgb || info.bit_depth != png::BitDepth::Eight {
+        return Err(std::io::Error::other("snapshots must be 8 bit rgb"));
+    }
+    data.truncate(info.buffer_size());
+    Ok((info.width, info.height, data))
+}
+
+/// Compare the display against the golden image `name`, panicking on any difference
+///
+/// On mismatch, or when the golden image is missing, the actual output is written next to the golden image as
+/// `<name>.actual.png`.
+pub fn assert_snapshot(name: &str, display: &SimDisplay) {
+    let dir = snapshot_dir();
+    let golden = dir.join(format!("{}.png", name));
+
+    if std::env::var_os(UPDATE_ENV).is_some() {
+        std::fs::create_dir_all(&dir).unwrap();
+        write_png(display, &golden).unwrap();
+        return;
+    }
+    if !golden.exists() {
+        let output = dir.join(format!("{}.actual.png", name));
+        std::fs::create_dir_all(&dir).unwrap();
+        write_png(display, &output).unwrap();
+        panic!(
+            "Snapshot '{}' is missing, see {} (set {}=1 to create it)",
+            name,
+            output.display(),
+            UPDATE_ENV
+        );
+    }
+
+    let (width, height, expected) = read_png(&golden)
+        .unwrap_or_else(|e| panic!("Failed to read snapshot {}: {}", golden.display(), e));
+    let actual = to_rgb888(display);
+    let size_matches = (width, height) == (display.width() as u32, display.height() as u32);
+    let differing = if size_matches {
+        expected
+            .chunks(3)
+            .zip(actual.chunks(3))
+            .filter(|(e, a)| e != a)
+            .count()
+    } else {
+        actual.len() / 3
+    };
+
+    if differing > 0 {
+        let output = dir.join(format!("{}.actual.png", name));
+        write_png(display, &output).unwrap();
+        panic!(
+            "Snapshot '{}' differs in {} pixels, see {} (set {}=1 to accept)",
+            name,
+            differing,
+            output.display(),
+            UPDATE_ENV
+        );
+    }
+}
@@ -0,0 +1,286 @@
+//! System
+//!
+//! Fake implementations of the hardware the kernel expects the [`Host`] to provide
+
+use core::fmt::Write;
+use heapless::String;
+use mwatch_kernel::{
+    application::FrameBuffer,
+    system::{
+        bms::{BatteryManagement, State},
+        storage::{Key, Storage},
+        Clock, Display, Host, Statistics, Uptime,
+    },
+};
+use std::collections::BTreeMap;
+use time::{Date, Duration, Month, PrimitiveDateTime, Time};
+
+pub const DISPLAY_WIDTH: u8 = 128;
+pub const DISPLAY_HEIGHT: u8 = 128;
+/// Rgb565, two bytes per pixel
+pub const BYTES_PER_PIXEL: usize = 2;
+
+pub struct SimHost;
+
+impl Host for SimHost {
+    type BatteryManager = SimBattery;
+    type TimeProvider = SimClock;
+    type UptimeProvider = SimUptime;
+    type Statistics = SimStats;
+    type Display = SimDisplay;
+    type Storage = SimStorage;
+}
+
+pub mod abi {
+    use embedded_graphics::draw_target::DrawTarget;
+    use mwatch_kernel::application::Context;
+
+    /// Draws a pixel into the framebuffer held by the context
+    ///
+    /// # Safety
+    ///
+    /// `context` must point to a valid [`Context`] with a valid framebuffer.
+    pub unsafe extern "C" fn draw_pixel(context: *mut Context, x: u8, y: u8, colour: u16) -> i32 {
+        let ctx = &mut *context;
+        let fb = &mut *ctx.framebuffer;
+        fb.draw_iter([embedded_graphics::Pixel(
+            embedded_graphics::prelude::Point::new(x as i32, y as i32),
+            embedded_graphics::pixelcolor::raw::RawU16::from(colour).into(),
+        )])
+        .ok();
+        0
+    }
+
+    /// Prints a string from the application to stdout
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for reads of `len` bytes.
+    pub unsafe extern "C" fn print(_context: *mut Context, ptr: *const u8, len: usize) -> i32 {
+        println!(
+            "[APP] - {}",
+            String::from_utf8_lossy(core::slice::from_raw_parts(ptr, len))
+        );
+        0
+    }
+}
+
+/// A fake real time clock, which only moves when told to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimClock {
+    now: PrimitiveDateTime,
+}
+
+impl Default for SimClock {
+    /// Midday on the first of January 2019
+    fn default() -> Self {
+        Self::new(
+            Date::from_calendar_date(2019, Month::January, 1).unwrap(),
+            Time::from_hms(12, 0, 0).unwrap(),
+        )
+    }
+}
+
+impl SimClock {
+    pub fn new(date: Date, time: Time) -> Self {
+        Self {
+            now: PrimitiveDateTime::new(date, time),
+        }
+    }
+
+    /// Move the clock forward by `duration`
+    pub fn advance(&mut self, duration: Duration) {
+        self.now += duration;
+    }
+}
+
+impl Clock for SimClock {
+    fn get_time(&self) -> Time {
+        self.now.time()
+    }
+
+    fn set_time(&mut self, t: &Time) {
+        self.now = self.now.replace_time(*t);
+    }
+
+    fn get_date(&self) -> Date {
+        self.now.date()
+    }
+
+    fn set_date(&mut self, d: &Date) {
+        self.now = self.now.replace_date(*d);
+    }
+}
+
+/// A millisecond counter, only moving when advanced
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SimUptime {
+    pub millis: u64,
+}
+
+impl SimUptime {
+    /// Move the uptime forward by `duration`
+    pub fn advance(&mut self, duration: Duration) {
+        self.millis += duration.whole_milliseconds() as u64;
+    }
+}
+
+impl Uptime for SimUptime {
+    fn millis(&self) -> u64 {
+        self.millis
+    }
+}
+
+/// A scriptable battery
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimBattery {
+    pub state: State,
+    pub soc: u16,
+}
+
+impl Default for SimBattery {
+    fn default() -> Self {
+        Self {
+            state: State::Draining,
+            soc: 100,
+        }
+    }
+}
+
+impl BatteryManagement for SimBattery {
+    fn state(&self) -> State {
+        self.state
+    }
+
+    fn soc(&mut self) -> u16 {
+        self.soc
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SimStats {
+    pub frames: u32,
+    pub rx_dropped: u32,
+}
+
+impl Statistics for SimStats {
+    type Statistics = std::vec::IntoIter<String<128>>;
+
+    fn stats(&self) -> Self::Statistics {
+        let mut frames = String::new();
+        write!(frames, "FRAMES: {}", self.frames).unwrap();
+        let mut dropped = String::new();
+        write!(dropped, "RX DROPPED: {}", self.rx_dropped).unwrap();
+        vec![frames, dropped].into_iter()
+    }
+
+    fn ingress_dropped(&mut self, total: u32) {
+        self.rx_dropped = total;
+    }
+}
+
+/// Storage backed by RAM, clone it to simulate a reset
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SimStorage {
+    pub records: BTreeMap<Key, Vec<u8>>,
+    /// Number of records written, including removals
+    pub writes: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SimStorageError {
+    /// The record doesn't fit in the buffer it was read into
+    BufferTooSmall,
+}
+
+impl Storage for SimStorage {
+    type Error = SimStorageError;
+    type Keys = std::vec::IntoIter<Key>;
+
+    fn keys(&mut self) -> Result<Self::Keys, Self::Error> {
+        Ok(self.records.keys().copied().collect::<Vec<_>>().into_iter())
+    }
+
+    fn read(&mut self, key: Key, buffer: &mut [u8]) -> Result<Option<usize>, Self::Error> {
+        match self.records.get(&key) {
+            Some(record) => {
+                let out = buffer.get_mut(..record.len()).ok_or(SimStorageError::BufferTooSmall)?;
+                out.copy_from_slice(record);
+                Ok(Some(record.len()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn write(&mut self, key: Key, data: &[u8]) -> Result<(), Self::Error> {
+        self.writes += 1;
+        self.records.insert(key, data.to_vec());
+        Ok(())
+    }
+
+    fn remove(&mut self, key: Key) -> Result<(), Self::Error> {
+        self.writes += 1;
+        self.records.remove(&key);
+        Ok(())
+    }
+}
+
+/// An in memory display
+pub struct SimDisplay {
+    buffer: Vec<u8>,
+    width: u8,
+    height: u8,
+}
+
+impl Default for SimDisplay {
+    fn default() -> Self {
+        Self::new(DISPLAY_WIDTH, DISPLAY_HEIGHT)
+    }
+}
+
+impl SimDisplay {
+    pub fn new(width: u8, height: u8) -> Self {
+        Self {
+            buffer: vec![0u8; width as usize * height as usize * BYTES_PER_PIXEL],
+            width,
+            height,
+        }
+    }
+
+    /// Blank the display
+    pub fn clear(&mut self) {
+        self.buffer.iter_mut().for_each(|b| *b = 0);
+    }
+
+    /// The raw Rgb565 (big endian) contents of the display
+    pub fn fb(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    pub fn width(&self) -> u8 {
+        self.width
+    }
+
+    pub fn height(&self) -> u8 {
+        self.height
+    }
+
+    /// The raw colour of the pixel at `x`, `y`
+    pub fn pixel(&self, x: u8, y: u8) -> u16 {
+        let idx = (x as usize + y as usize * self.width as usize) * BYTES_PER_PIXEL;
+        u16::from_be_bytes([self.buffer[idx], self.buffer[idx + 1]])
+    }
+}
+
+impl Display for SimDisplay {
+    fn framebuffer(&mut self) -> FrameBuffer {
+        unsafe {
+            FrameBuffer::new(
+                self.buffer.as_mut_ptr(),
+                self.buffer.len(),
+                self.width,
+                self.height,
+            )
+        }
+    }
+}
@@ -0,0 +1,347 @@
+use mwatch_kernel::{
+    egress::egress_manager::ACTION,
+    application::{
+        display_manager::Signal,
+        states::{alarms::AlarmState, notifications::NotificationState, ScopedState, State},
+        toast::TOAST_MILLIS,
+    },
+    system::{
+        alarm::{Alarm, Alert, Days, SNOOZE},
+        input::{InputEvent, InputManager, LEFT, LEFT_RIGHT, MAX_PIN_IDX, NONE, RIGHT},
+        IDLE_TIMEOUT_MS,
+    },
+};
+use mwatch_kernel_host::{simulator::TICK, Simulator};
+use time::{Duration, Time};
+
+fn notify(sim: &mut Simulator, title: &str) {
+    let mut data = vec![2, b'N'];
+    for section in ["Messenger", title, "Hello world"] {
+        data.push(31);
+        data.extend_from_slice(section.as_bytes());
+    }
+    data.push(3);
+    sim.write(&data);
+    sim.tick();
+}
+
+/// A high priority notification
+fn alert(sim: &mut Simulator, title: &str) {
+    let mut data = vec![2, b'N', 31, 0x16, b'2', 31, 0x12];
+    data.extend_from_slice(title.as_bytes());
+    data.push(3);
+    sim.write(&data);
+    sim.tick();
+}
+
+/// Sample each raw input vector in turn, as the touch controller would, passing any events to `state`
+fn touch<S: State>(sim: &mut Simulator, im: &mut InputManager, state: &mut S, vectors: &[u8]) {
+    for vector in vectors {
+        for pin in 0..=MAX_PIN_IDX {
+            im.update_input(vector & (1 << pin) != 0);
+        }
+        if let Ok(input) = im.output(0) {
+            state.input(&mut sim.system, input);
+        }
+    }
+}
+
+fn lit_pixels(sim: &Simulator) -> usize {
+    sim.display.fb().chunks(2).filter(|px| px != &[0, 0]).count()
+}
+
+#[test]
+fn renders_every_state() {
+    let mut sim = Simulator::default();
+    // cycle through the whole carousel, including the scoped state previews
+    for _ in 0..8 {
+        sim.tick();
+        assert!(lit_pixels(&sim) > 0, "state rendered nothing");
+        sim.input(InputEvent::Right);
+    }
+}
+
+#[test]
+fn idle_clock_hides_status() {
+    let mut sim = Simulator::default();
+    sim.tick();
+    let active = lit_pixels(&sim);
+
+    sim.system.uptime.advance(Duration::milliseconds(IDLE_TIMEOUT_MS as i64));
+    sim.tick();
+    assert!(sim.system.is_idle());
+    assert!(lit_pixels(&sim) < active);
+}
+
+#[test]
+fn notifications_read_dismiss_and_clear() {
+    let mut sim = Simulator::default();
+    for title in ["a", "b", "c"] {
+        notify(&mut sim, title);
+    }
+    let mut state = NotificationState::default();
+    state.start(&mut sim.system);
+    sim.render_state(&mut state);
+
+    // opening a notification marks it as read, dismissing it removes it
+    state.input(&mut sim.system, InputEvent::Right);
+    state.input(&mut sim.system, InputEvent::Middle);
+    assert_eq!(sim.system.nm.unread(), 2);
+    state.input(&mut sim.system, InputEvent::Dual);
+    let titles: Vec<&str> = sim.system.nm.iter().map(|n| n.title()).collect();
+    assert_eq!(titles, ["a", "c"]);
+
+    // back in the menu, clearing everything needs confirming
+    sim.render_state(&mut state);
+    state.input(&mut sim.system, InputEvent::Dual);
+    state.input(&mut sim.system, InputEvent::Left);
+    state.input(&mut sim.system, InputEvent::Middle);
+    assert_eq!(sim.system.nm.len(), 2);
+    state.input(&mut sim.system, InputEvent::Dual);
+    state.input(&mut sim.system, InputEvent::Dual);
+    assert!(sim.system.nm.is_empty());
+}
+
+#[test]
+fn notifications_clear_with_dual_touches() {
+    let mut sim = Simulator::default();
+    for title in ["a", "b"] {
+        notify(&mut sim, title);
+    }
+    let mut state = NotificationState::default();
+    state.start(&mut sim.system);
+    sim.render_state(&mut state);
+
+    // a dual press is rarely touched or released with both buttons at once
+    let mut im = InputManager::new();
+    touch(&mut sim, &mut im, &mut state, &[LEFT, LEFT_RIGHT, RIGHT, NONE]);
+    // a stray touch whilst confirming doesn't cancel
+    touch(&mut sim, &mut im, &mut state, &[RIGHT, NONE]);
+    assert_eq!(sim.system.nm.len(), 2);
+    touch(&mut sim, &mut im, &mut state, &[RIGHT, LEFT_RIGHT, LEFT, NONE]);
+    assert!(sim.system.nm.is_empty());
+}
+
+#[test]
+fn high_priority_notification_toast() {
+    let mut sim = Simulator::default();
+    notify(&mut sim, "normal");
+    let clock = sim.display.fb().to_vec();
+
+    // the toast wakes the display and is drawn over the clock
+    sim.system.uptime.advance(Duration::milliseconds(IDLE_TIMEOUT_MS as i64));
+    alert(&mut sim, "urgent");
+    assert!(!sim.system.is_idle());
+    assert_ne!(sim.display.fb(), &clock[..]);
+
+    // input dismisses it rather than reaching the clock
+    sim.input(InputEvent::Right);
+    sim.tick();
+    assert_eq!(sim.display.fb(), &clock[..]);
+
+    // a middle press opens the notification
+    alert(&mut sim, "urgent");
+    sim.input(InputEvent::Middle);
+    assert!(sim.system.nm.get_newest(0).unwrap().is_read());
+    sim.tick();
+    sim.input(InputEvent::Dual);
+    assert_eq!(sim.system.nm.len(), 2);
+}
+
+#[test]
+fn toast_stops_the_current_state() {
+    let mut sim = Simulator::default();
+    // the alarms state is two to the left of the clock
+    sim.input(InputEvent::Left);
+    sim.input(InputEvent::Left);
+    sim.tick();
+    let preview = sim.display.fb().to_vec();
+    sim.input(InputEvent::Middle);
+    sim.tick();
+    assert_ne!(sim.display.fb(), &preview[..]);
+
+    // opening the notification from the toast leaves the alarms state stopped
+    alert(&mut sim, "urgent");
+    sim.input(InputEvent::Middle);
+    sim.input(InputEvent::Multi);
+    sim.input(InputEvent::Left);
+    sim.input(InputEvent::Left);
+    sim.tick();
+    assert_eq!(sim.display.fb(), &preview[..]);
+}
+
+#[test]
+fn long_middle_stops_the_current_state() {
+    let mut sim = Simulator::default();
+    sim.tick();
+    let clock = sim.display.fb().to_vec();
+    sim.input(InputEvent::Left);
+    sim.input(InputEvent::Left);
+    sim.tick();
+    let preview = sim.display.fb().to_vec();
+    sim.input(InputEvent::Middle);
+
+    // going home leaves the alarms state stopped, as a multi press would
+    sim.input(InputEvent::LongMiddle);
+    sim.tick();
+    assert_eq!(sim.display.fb(), &clock[..]);
+    sim.input(InputEvent::Left);
+    sim.input(InputEvent::Left);
+    sim.tick();
+    assert_eq!(sim.display.fb(), &preview[..]);
+}
+
+#[test]
+fn toast_expires() {
+    let mut sim = Simulator::default();
+    sim.tick();
+    let clock = sim.display.fb().to_vec();
+    alert(&mut sim, "urgent");
+    for _ in 0..TOAST_MILLIS / TICK.whole_milliseconds() as u64 {
+        sim.tick();
+    }
+    assert_eq!(sim.display.fb(), &clock[..]);
+}
+
+#[test]
+fn notification_action_is_sent() {
+    let mut sim = Simulator::default();
+    sim.write(b"\x02N\x1fMessenger\x1fScott\x1fPub?\x1f\x17Yes\x1eNo\x1f\x18key-1\x03");
+    sim.tick();
+    sim.replies();
+    let mut state = NotificationState::default();
+    state.start(&mut sim.system);
+    state.input(&mut sim.system, InputEvent::Middle);
+    sim.render_state(&mut state);
+
+    // the body fits, so moving right selects the actions, stopping at the last
+    for _ in 0..3 {
+        state.input(&mut sim.system, InputEvent::Right);
+    }
+    state.input(&mut sim.system, InputEvent::Left);
+    state.input(&mut sim.system, InputEvent::Right);
+    state.input(&mut sim.system, InputEvent::Middle);
+    assert_eq!(sim.replies(), [(ACTION, b"\x01key-1".to_vec())]);
+    assert!(sim.system.nm.is_empty());
+}
+
+#[test]
+fn notification_without_action_selected() {
+    let mut sim = Simulator::default();
+    sim.write(b"\x02N\x1fMessenger\x1fScott\x1fPub?\x1f\x17Yes\x1eNo\x1f\x18key-1\x03");
+    sim.tick();
+    sim.replies();
+    let mut state = NotificationState::default();
+    state.start(&mut sim.system);
+    state.input(&mut sim.system, InputEvent::Middle);
+    sim.render_state(&mut state);
+    state.input(&mut sim.system, InputEvent::Right);
+    state.input(&mut sim.system, InputEvent::Left);
+    state.input(&mut sim.system, InputEvent::Middle);
+    assert!(sim.replies().is_empty());
+    assert_eq!(sim.system.nm.len(), 1);
+}
+
+#[test]
+fn notification_without_key_has_no_actions() {
+    let mut sim = Simulator::default();
+    sim.write(b"\x02N\x1fMessenger\x1fScott\x1fPub?\x1f\x17Yes\x1eNo\x03");
+    sim.tick();
+    sim.replies();
+    let mut state = NotificationState::default();
+    state.start(&mut sim.system);
+    state.input(&mut sim.system, InputEvent::Middle);
+    sim.render_state(&mut state);
+    state.input(&mut sim.system, InputEvent::Right);
+    state.input(&mut sim.system, InputEvent::Middle);
+    assert!(sim.replies().is_empty());
+    assert_eq!(sim.system.nm.len(), 1);
+}
+
+#[test]
+fn alarm_rings_snoozes_and_stops() {
+    let mut sim = Simulator::default();
+    let time = Time::from_hms(12, 1, 0).unwrap();
+    sim.system.alarms.set_alarm(0, Some(Alarm::new(time, Days::NONE))).unwrap();
+
+    // the alert wakes the display and takes over input
+    sim.system.uptime.advance(Duration::milliseconds(IDLE_TIMEOUT_MS as i64));
+    sim.system.clock.advance(Duration::minutes(1));
+    sim.tick();
+    assert!(!sim.system.is_idle());
+    assert_eq!(sim.system.alarms.ringing(), Some(Alert::Alarm(time)));
+    let alert = sim.display.fb().to_vec();
+    sim.input(InputEvent::Left);
+    assert_eq!(sim.system.alarms.ringing(), None);
+    sim.tick();
+    assert_ne!(sim.display.fb(), &alert[..]);
+
+    sim.system.clock.advance(SNOOZE);
+    sim.tick();
+    assert_eq!(sim.system.alarms.ringing(), Some(Alert::Alarm(time)));
+    sim.input(InputEvent::Middle);
+    sim.system.clock.advance(SNOOZE);
+    sim.tick();
+    assert_eq!(sim.system.alarms.ringing(), None);
+}
+
+#[test]
+fn alarm_state_sets_alarm_and_timer() {
+    let mut sim = Simulator::default();
+    let mut state = AlarmState::default();
+    state.start(&mut sim.system);
+
+    // 06:59 on weekdays: back an hour, back a minute, then forward twice through the repeats
+    state.input(&mut sim.system, InputEvent::Middle);
+    state.input(&mut sim.system, InputEvent::Left);
+    state.input(&mut sim.system, InputEvent::Middle);
+    state.input(&mut sim.system, InputEvent::Left);
+    state.input(&mut sim.system, InputEvent::Middle);
+    state.input(&mut sim.system, InputEvent::Right);
+    state.input(&mut sim.system, InputEvent::Right);
+    state.input(&mut sim.system, InputEvent::Middle);
+    let alarm = sim.system.alarms.alarm(0).unwrap();
+    assert_eq!((alarm.time(), alarm.days), (Time::from_hms(6, 59, 0).unwrap(), Days::WEEKDAYS));
+
+    // dual press turns it off
+    state.input(&mut sim.system, InputEvent::Dual);
+    assert!(!sim.system.alarms.alarm(0).unwrap().enabled);
+
+    // the timer is after the alarms, start a 6 minute timer
+    state.input(&mut sim.system, InputEvent::Left);
+    state.input(&mut sim.system, InputEvent::Middle);
+    state.input(&mut sim.system, InputEvent::Right);
+    state.input(&mut sim.system, InputEvent::Middle);
+    assert_eq!(sim.system.alarms.timers().map(|t| t.duration()).collect::<Vec<_>>(), [Duration::minutes(6)]);
+    state.input(&mut sim.system, InputEvent::Dual);
+    assert_eq!(sim.system.alarms.timers().count(), 0);
+
+    assert_eq!(state.input(&mut sim.system, InputEvent::Multi), Some(Signal::Home));
+}
+
+#[test]
+fn stopwatch_counts_by_uptime() {
+    let mut sim = Simulator::default();
+    // the stopwatch is before the clock
+    sim.input(InputEvent::Left);
+    sim.input(InputEvent::Middle);
+    sim.tick();
+    let started = sim.display.fb().to_vec();
+    for _ in 0..10 {
+        sim.tick();
+    }
+    assert_ne!(sim.display.fb(), &started[..]);
+
+    // stopped, it no longer changes
+    sim.input(InputEvent::Middle);
+    sim.tick();
+    let stopped = sim.display.fb().to_vec();
+    for _ in 0..10 {
+        sim.tick();
+    }
+    assert_eq!(sim.display.fb(), &stopped[..]);
+
+    sim.input(InputEvent::LeftMiddle);
+    sim.tick();
+    assert_ne!(sim.display.fb(), &stopped[..]);
+}
@@ -0,0 +1,613 @@
+use mwatch_kernel::{
+    application::header::{Header, ABI_VERSION, HEADER_LEN},
+    egress::egress_manager::{Code, ACK, NAK, PROGRESS, VALUE},
+    ingress::{
+        escape,
+        frame::{encode, MAX_FRAME_LEN, MAX_PAYLOAD},
+        ingress_manager::{FlowControl, HIGH_WATERMARK, LOW_WATERMARK},
+    },
+    system::{
+        alarm::{Alert, Days},
+        filter::MAX_MUTED,
+        notification::Priority,
+        Clock,
+    },
+};
+use crc::crc32::checksum_ieee;
+use mwatch_kernel_host::Simulator;
+use time::{Date, Duration, Month, PrimitiveDateTime, Time};
+
+const STX: u8 = 2;
+const ETX: u8 = 3;
+const PAYLOAD: u8 = 31;
+
+fn packet<S: AsRef<[u8]>>(ty: u8, sections: &[S]) -> Vec<u8> {
+    let mut data = vec![STX, ty];
+    for section in sections {
+        data.push(PAYLOAD);
+        data.extend_from_slice(section.as_ref());
+    }
+    data.push(ETX);
+    data
+}
+
+fn frame<S: AsRef<[u8]>>(ty: u8, seq: u8, sections: &[S]) -> Vec<u8> {
+    let packet = packet(ty, sections);
+    // the frame payload is the legacy packet without the STX, type and ETX
+    let mut out = vec![0u8; MAX_FRAME_LEN];
+    let len = encode(ty, seq, &packet[2..packet.len() - 1], &mut out).unwrap();
+    out.truncate(len);
+    out
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+fn escaped(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0u8; data.len() * 2];
+    let len = escape::encode(data, &mut out).unwrap();
+    out.truncate(len);
+    out
+}
+
+/// An application binary with a header for the running kernel, followed by `code`
+fn application(code: &[u8]) -> Vec<u8> {
+    let len = HEADER_LEN + code.len();
+    let entry = HEADER_LEN as u32 | 1;
+    let mut app = Header::new("Test", entry, entry, entry, len as u32).to_bytes().to_vec();
+    app.extend_from_slice(code);
+    app
+}
+
+/// Frames for each chunk of an application upload, starting from `offset`
+fn chunks(app: &[u8], offset: usize, chunk_size: usize, seq: u8) -> Vec<Vec<u8>> {
+    app[offset..]
+        .chunks(chunk_size)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let header = format!("{:X}:{:X}", offset + i * chunk_size, checksum_ieee(chunk));
+            frame(b'C', seq.wrapping_add(i as u8), &[&header, &hex(chunk)])
+        })
+        .collect()
+}
+
+#[test]
+fn ingress_syscall() {
+    let mut sim = Simulator::default();
+    sim.write(&packet(b'S', &["T00:00:00"]));
+    sim.tick();
+
+    assert_eq!(sim.system.clock.get_time(), Time::MIDNIGHT);
+}
+
+#[test]
+fn ingress_date_syscall() {
+    let mut sim = Simulator::default();
+    sim.write(&packet(b'S', &["D0/12/02/2019"]));
+    sim.tick();
+
+    assert_eq!(
+        sim.system.clock.get_date(),
+        Date::from_calendar_date(2019, Month::February, 12).unwrap()
+    );
+}
+
+#[test]
+fn ingress_iso_date_time_syscall() {
+    let mut sim = Simulator::default();
+    sim.write(&packet(b'S', &["I2020-02-29T23:59:58"]));
+    sim.write(&packet(b'S', &["GT"]));
+    sim.tick();
+
+    assert_eq!(
+        sim.system.clock.get_date_time(),
+        PrimitiveDateTime::new(
+            Date::from_calendar_date(2020, Month::February, 29).unwrap(),
+            Time::from_hms(23, 59, 58).unwrap()
+        )
+    );
+    assert_eq!(sim.replies()[1], (VALUE, b"GT\x1f2020-02-29T23:59:58+00:00".to_vec()));
+}
+
+#[test]
+fn ingress_utc_offset_syscalls() {
+    let mut sim = Simulator::default();
+    // the local time, an hour behind UTC
+    sim.write(&packet(b'S', &["I2020-03-01T00:30:00-01:00"]));
+    sim.tick();
+    let utc = PrimitiveDateTime::new(
+        Date::from_calendar_date(2020, Month::March, 1).unwrap(),
+        Time::from_hms(1, 30, 0).unwrap(),
+    );
+    assert_eq!(sim.system.clock.get_date_time(), utc);
+    assert_eq!(sim.system.local_date_time(), utc - Duration::hours(1));
+
+    // travelling changes the local time, but not the clock
+    sim.write(&packet(b'S', &["Z+05:30"]));
+    sim.write(&packet(b'S', &["GT"]));
+    sim.tick();
+    assert_eq!(sim.system.clock.get_date_time(), utc);
+    assert_eq!(sim.replies().last(), Some(&(ACK, vec![b'S', 0, 0])));
+    sim.write(&packet(b'S', &["GT"]));
+    sim.tick();
+    assert_eq!(sim.replies()[0], (VALUE, b"GT\x1f2020-03-01T07:00:00+05:30".to_vec()));
+
+    // without an offset the time is local
+    sim.write(&packet(b'S', &["I2020-03-01T12:00:00"]));
+    sim.tick();
+    assert_eq!(sim.system.clock.get_time(), Time::from_hms(6, 30, 0).unwrap());
+}
+
+#[test]
+fn ingress_getter_syscalls() {
+    let mut sim = Simulator::default();
+    for getter in ["GT", "GB", "GV", "GA", "GS"] {
+        sim.write(&packet(b'S', &[getter]));
+    }
+    sim.tick();
+
+    let values: Vec<String> = sim
+        .replies()
+        .into_iter()
+        .filter(|(ty, _)| *ty == VALUE)
+        .map(|(_, payload)| String::from_utf8(payload).unwrap().replace('\x1f', "|"))
+        .collect();
+    assert_eq!(
+        values,
+        [
+            "GT|2019-01-01T12:00:00+00:00".to_string(),
+            "GB|100|Draining".to_string(),
+            format!("GV|{}", mwatch_kernel::VERSION),
+            "GA|0|0|0|".to_string(),
+            "GS|FRAMES: 0|RX DROPPED: 0".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn ingress_notification() {
+    let mut sim = Simulator::default();
+    sim.write(&packet(b'N', &["Messenger", "Scott", "Hello world"]));
+    sim.tick();
+
+    assert_eq!(sim.system.nm.len(), 1);
+    let n = sim.system.nm.get(0).unwrap();
+    assert_eq!(n.title(), "Scott");
+    assert_eq!(n.body(), "Hello world");
+}
+
+#[test]
+fn ingress_notification_fields() {
+    let mut sim = Simulator::default();
+    sim.write(&frame(b'N', 0, &["\x15com.slack", "Slack", "\x14Scott", "Team", "\x162", "Standup"]));
+    sim.tick();
+
+    let n = sim.system.nm.get(0).unwrap();
+    assert_eq!((n.source(), n.title(), n.body()), ("Slack", "Team", "Standup"));
+    assert_eq!(n.sender(), Some("Scott"));
+    assert_eq!(n.app_id(), Some("com.slack"));
+    assert_eq!(n.priority(), Priority::High);
+}
+
+#[test]
+fn ingress_notification_filter() {
+    let mut sim = Simulator::default();
+    let alerted = |sim: &mut Simulator, source: &str| {
+        sim.write(&packet(b'N', &[source, "Title", "Body", "\x162"]));
+        sim.imgr.process(&mut sim.system);
+        sim.system.nm.take_alert().is_some()
+    };
+    sim.write(&packet(b'S', &["F+Slack"]));
+    assert!(!alerted(&mut sim, "Slack"));
+    assert!(alerted(&mut sim, "Messenger"));
+
+    // the simulator clock is midday
+    sim.write(&packet(b'S', &["Q11:00:00-13:00:00"]));
+    assert!(!alerted(&mut sim, "Messenger"));
+    sim.write(&packet(b'S', &["Q"]));
+    sim.write(&packet(b'S', &["F-Slack"]));
+    assert!(alerted(&mut sim, "Slack"));
+    // suppressed notifications are still stored
+    assert_eq!(sim.system.nm.len(), 4);
+
+    for i in 0..=MAX_MUTED {
+        sim.write(&packet(b'S', &[format!("F+{}", i)]));
+    }
+    sim.imgr.process(&mut sim.system);
+    let replies = sim.replies();
+    assert_eq!(replies.last(), Some(&(NAK, vec![b'S', 0, Code::NoMemory as u8])));
+    assert!(replies[..replies.len() - 1].iter().all(|(ty, _)| *ty == ACK));
+}
+
+#[test]
+fn ingress_alarm_syscalls() {
+    let mut sim = Simulator::default();
+    sim.write(&packet(b'S', &["A1=07:30:00/12345"]));
+    sim.write(&packet(b'S', &["C00:00:30"]));
+    sim.tick();
+    let alarm = sim.system.alarms.alarm(1).unwrap();
+    assert_eq!((alarm.time(), alarm.days), (Time::from_hms(7, 30, 0).unwrap(), Days::WEEKDAYS));
+    assert_eq!(sim.system.alarms.timers().count(), 1);
+
+    sim.system.clock.advance(Duration::seconds(30));
+    sim.tick();
+    assert_eq!(sim.system.alarms.ringing(), Some(Alert::Timer(Duration::seconds(30))));
+
+    sim.write(&packet(b'S', &["A1-"]));
+    sim.write(&packet(b'S', &["C00:01:00"]));
+    sim.write(&packet(b'S', &["C"]));
+    sim.write(&packet(b'S', &["A9=07:30:00"]));
+    sim.tick();
+    assert_eq!(sim.system.alarms.alarm(1), None);
+    assert_eq!(sim.system.alarms.timers().count(), 0);
+    let replies = sim.replies();
+    assert_eq!(replies.last(), Some(&(NAK, vec![b'S', 0, Code::ParseError as u8])));
+}
+
+#[test]
+fn ingress_split_across_writes() {
+    let mut sim = Simulator::default();
+    let data = packet(b'S', &["T13:14:15"]);
+    let (first, second) = data.split_at(4);
+    sim.write(first);
+    sim.tick();
+    sim.write(second);
+    sim.tick();
+
+    assert_eq!(sim.system.clock.get_time(), Time::from_hms(13, 14, 15).unwrap());
+}
+
+#[test]
+fn ingress_frame_notification() {
+    let mut sim = Simulator::default();
+    sim.write(&frame(b'N', 0, &["Messenger", "Scott", "Hello world"]));
+    sim.tick();
+
+    assert_eq!(sim.system.nm.len(), 1);
+    let n = sim.system.nm.get(0).unwrap();
+    assert_eq!(n.body(), "Hello world");
+}
+
+#[test]
+fn ingress_frame_corruption_dropped() {
+    let mut sim = Simulator::default();
+    let mut data = frame(b'N', 0, &["Messenger", "Scott", "Hello world"]);
+    let idx = data.len() - 5;
+    data[idx] = b'X';
+    sim.write(&data);
+    sim.tick();
+    assert_eq!(sim.system.nm.len(), 0);
+
+    // the stream recovers for the next frame
+    sim.write(&frame(b'S', 1, &["T01:02:03"]));
+    sim.tick();
+    assert_eq!(sim.system.clock.get_time(), Time::from_hms(1, 2, 3).unwrap());
+}
+
+#[test]
+fn ingress_frame_duplicate_dropped() {
+    let mut sim = Simulator::default();
+    let data = frame(b'N', 5, &["Messenger", "Scott", "Hello world"]);
+    sim.write(&data);
+    sim.write(&data);
+    sim.tick();
+    assert_eq!(sim.system.nm.len(), 1);
+}
+
+#[test]
+fn ingress_frame_and_legacy_interleaved() {
+    let mut sim = Simulator::default();
+    sim.write(&frame(b'S', 0, &["T01:02:03"]));
+    sim.write(&packet(b'N', &["Messenger", "Scott", "Hello world"]));
+    sim.write(&frame(b'N', 1, &["Slack", "Team", "Standup"]));
+    sim.tick();
+
+    assert_eq!(sim.system.clock.get_time(), Time::from_hms(1, 2, 3).unwrap());
+    assert_eq!(sim.system.nm.len(), 2);
+}
+
+#[test]
+fn ingress_replies_ack() {
+    let mut sim = Simulator::default();
+    sim.write(&packet(b'S', &["T00:00:00"]));
+    sim.write(&frame(b'N', 9, &["Messenger", "Scott", "Hello world"]));
+    sim.tick();
+
+    assert_eq!(
+        sim.replies(),
+        vec![(ACK, vec![b'S', 0, Code::Ok as u8]), (ACK, vec![b'N', 9, Code::Ok as u8])]
+    );
+}
+
+#[test]
+fn ingress_replies_nak() {
+    let mut sim = Simulator::default();
+    sim.write(&packet(b'S', &["X00:00:00"]));
+    sim.write(&packet(b'S', &["T99:00:00"]));
+    sim.write(&packet(b'Q', &["?"]));
+    sim.write(&frame(b'A', 2, &["00000000", "00"]));
+    sim.tick();
+
+    assert_eq!(
+        sim.replies(),
+        vec![
+            (NAK, vec![b'S', 0, Code::UnknownSyscall as u8]),
+            (NAK, vec![b'S', 0, Code::ParseError as u8]),
+            (NAK, vec![b'Q', 0, Code::UnknownType as u8]),
+            (NAK, vec![b'A', 2, Code::ChecksumFailed as u8]),
+        ]
+    );
+}
+
+#[test]
+fn ingress_replies_corrupted_frame() {
+    let mut sim = Simulator::default();
+    let mut data = frame(b'S', 4, &["T01:02:03"]);
+    let idx = data.len() - 3;
+    data[idx] = b'9';
+    sim.write(&data);
+    // a retransmission of an already accepted frame is acknowledged again
+    let data = frame(b'S', 5, &["T01:02:03"]);
+    sim.write(&data);
+    sim.write(&data);
+    sim.tick();
+
+    assert_eq!(
+        sim.replies(),
+        vec![
+            (NAK, vec![b'S', 4, Code::FrameCorrupted as u8]),
+            (ACK, vec![b'S', 5, Code::Ok as u8]),
+            (ACK, vec![b'S', 5, Code::Ok as u8]),
+        ]
+    );
+}
+
+#[test]
+fn ingress_replays_nak_for_retransmission() {
+    let mut sim = Simulator::default();
+    // a rejected frame is rejected again, not acknowledged
+    let data = frame(b'S', 6, &["T99:00:00"]);
+    sim.write(&data);
+    sim.write(&data);
+    // the type and sequence number of a frame with an unknown version are never read
+    let mut data = frame(b'S', 7, &["T01:02:03"]);
+    data[1] = 9;
+    sim.write(&data);
+    sim.tick();
+
+    assert_eq!(
+        sim.replies(),
+        vec![
+            (NAK, vec![b'S', 6, Code::ParseError as u8]),
+            (NAK, vec![b'S', 6, Code::ParseError as u8]),
+            (NAK, vec![0, 0, Code::UnsupportedVersion as u8]),
+        ]
+    );
+}
+
+#[test]
+fn ingress_chunked_upload_resumes() {
+    let code: Vec<u8> = (0..(1000 - HEADER_LEN) as u32).map(|i| (i * 7) as u8).collect();
+    let app = application(&code);
+    let begin = format!("{:X}:{:X}", app.len(), checksum_ieee(&app));
+    let mut sim = Simulator::default();
+    sim.write(&frame(b'U', 0, &[&begin]));
+    sim.tick();
+    assert_eq!(
+        sim.replies(),
+        vec![(ACK, vec![b'U', 0, 0]), (PROGRESS, vec![0, 0, 0, 0, 0xE8, 0x03, 0, 0])]
+    );
+
+    // the link drops during the third chunk
+    let frames = chunks(&app, 0, 200, 1);
+    for data in [&frames[0][..], &frames[1][..], &frames[2][..100]] {
+        sim.write(data);
+        sim.tick();
+    }
+    let progress = sim.system.am.progress().unwrap();
+    assert_eq!((progress.received, progress.total), (400, 1000));
+    sim.replies();
+
+    // on reconnect the upload is resumed from where it got to
+    sim.imgr.reset();
+    sim.write(&frame(b'U', 10, &[&begin]));
+    sim.tick();
+    assert_eq!(sim.replies()[1], (PROGRESS, vec![0x90, 0x01, 0, 0, 0xE8, 0x03, 0, 0]));
+    for chunk in chunks(&app, 400, 200, 11) {
+        sim.write(&chunk);
+        sim.tick();
+    }
+    assert!(sim.system.am.progress().is_none());
+    assert!(sim.system.am.status().is_loaded);
+    assert_eq!(sim.system.am.program(), &app[..]);
+}
+
+#[test]
+fn ingress_chunk_rejected() {
+    let app = [0x55u8; 64];
+    let mut sim = Simulator::default();
+    sim.write(&packet(b'C', &["0:0", "00"]));
+    sim.write(&packet(b'U', &[&format!("{:X}:{:X}", app.len(), checksum_ieee(&app))]));
+    sim.write(&packet(b'C', &["20:0", &hex(&app[32..])]));
+    sim.write(&packet(b'C', &["0:1234", &hex(&app[..32])]));
+    sim.tick();
+
+    let replies = sim.replies();
+    assert_eq!(replies[0], (NAK, vec![b'C', 0, Code::NoUpload as u8]));
+    assert_eq!(replies[3], (NAK, vec![b'C', 0, Code::OutOfOrder as u8]));
+    assert_eq!(replies[4], (NAK, vec![b'C', 0, Code::ChecksumFailed as u8]));
+}
+
+#[test]
+fn ingress_escaped_application() {
+    // every byte value, including the protocol's control bytes
+    let app = application(&(0..=255u8).collect::<Vec<_>>());
+    let cs = checksum_ieee(&app).to_be_bytes();
+    let mut sim = Simulator::default();
+    sim.write(&packet(b'a', &[escaped(&cs), escaped(&app)]));
+    sim.tick();
+
+    assert_eq!(sim.replies(), vec![(ACK, vec![b'a', 0, Code::Ok as u8])]);
+    assert_eq!(sim.system.am.program(), &app[..]);
+}
+
+#[test]
+fn ingress_incompatible_application() {
+    let mut app = application(&[0u8; 64]);
+    app[4..6].copy_from_slice(&(ABI_VERSION + 1).to_le_bytes());
+    let cs = checksum_ieee(&app).to_be_bytes();
+    let mut sim = Simulator::default();
+    sim.write(&packet(b'a', &[escaped(&cs), escaped(&app)]));
+    sim.write(&packet(b'a', &[escaped(&checksum_ieee(&app[4..]).to_be_bytes()), escaped(&app[4..])]));
+    sim.tick();
+
+    assert_eq!(
+        sim.replies(),
+        vec![
+            (NAK, vec![b'a', 0, Code::IncompatibleAbi as u8]),
+            (NAK, vec![b'a', 0, Code::InvalidHeader as u8]),
+        ]
+    );
+    assert!(!sim.system.am.status().is_loaded);
+}
+
+#[test]
+fn ingress_escaped_chunks() {
+    let code: Vec<u8> = (0..(600 - HEADER_LEN) as u32).map(|i| (i % 7) as u8).collect();
+    let app = application(&code);
+    let mut sim = Simulator::default();
+    sim.write(&frame(b'U', 0, &[format!("{:X}:{:X}", app.len(), checksum_ieee(&app))]));
+    sim.tick();
+    for (i, chunk) in app.chunks(200).enumerate() {
+        let header = format!("{:X}:{:X}", i * 200, checksum_ieee(chunk));
+        sim.write(&frame(b'c', i as u8 + 1, &[header.into_bytes(), escaped(chunk)]));
+        sim.tick();
+    }
+
+    let replies = sim.replies();
+    assert_eq!(replies.last(), Some(&(ACK, vec![b'c', 3, Code::Ok as u8])));
+    assert!(sim.system.am.status().is_loaded);
+    assert_eq!(sim.system.am.program(), &app[..]);
+}
+
+#[test]
+fn ingress_overflow_is_counted() {
+    let mut sim = Simulator::default();
+    // the ring buffer holds 511 bytes
+    assert_eq!(sim.write(&[0u8; 600]), 511);
+    assert_eq!(sim.imgr.dropped(), 89);
+    sim.tick();
+    assert_eq!(sim.system.stats.rx_dropped, 89);
+
+    // nothing is lost once there is room again
+    let data = packet(b'S', &["T13:14:15"]);
+    assert_eq!(sim.write(&data), data.len());
+    sim.tick();
+    assert_eq!(sim.system.clock.get_time(), Time::from_hms(13, 14, 15).unwrap());
+}
+
+#[test]
+fn ingress_flow_control() {
+    let mut sim = Simulator::default();
+    assert_eq!(sim.write(&[0u8; HIGH_WATERMARK - 1]), HIGH_WATERMARK - 1);
+    assert_eq!(sim.imgr.flow_control(), FlowControl::Ready);
+    sim.write(&[0u8; 1]);
+    assert_eq!(sim.imgr.flow_control(), FlowControl::Throttle);
+    // stays throttled until processed down to the low watermark
+    sim.write(&[0u8; LOW_WATERMARK / 2]);
+    assert_eq!(sim.imgr.flow_control(), FlowControl::Throttle);
+    sim.tick();
+    assert_eq!(sim.imgr.flow_control(), FlowControl::Ready);
+    assert_eq!(sim.imgr.dropped(), 0);
+}
+
+#[test]
+fn ingress_oversize_notification_truncated() {
+    let mut sim = Simulator::default();
+    let body = "a".repeat(600);
+    for chunk in packet(b'N', &["Messenger", "Scott", &body]).chunks(256) {
+        sim.write(chunk);
+        sim.tick();
+    }
+
+    assert_eq!(sim.replies(), vec![(ACK, vec![b'N', 0, Code::Ok as u8])]);
+    let n = sim.system.nm.get(0).unwrap();
+    assert_eq!(n.title(), "Scott");
+    // the separators are stored too
+    assert_eq!(n.body().len(), 512 - "MessengerScott".len() - 3);
+}
+
+#[test]
+fn ingress_oversize_syscall_rejected() {
+    let mut sim = Simulator::default();
+    let time = format!("T{}", "0".repeat(600));
+    for chunk in packet(b'S', &[&time]).chunks(256) {
+        sim.write(chunk);
+        sim.tick();
+    }
+
+    assert_eq!(sim.replies(), vec![(NAK, vec![b'S', 0, Code::NoMemory as u8])]);
+}
+
+/// xorshift32, good enough to generate junk deterministically
+struct Rng(u32);
+
+impl Rng {
+    fn next(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+
+    fn below(&mut self, n: u32) -> u32 {
+        self.next() % n
+    }
+}
+
+#[test]
+fn ingress_random_streams() {
+    const TYPES: &[u8] = b"NAaCcUQ";
+    let mut rng = Rng(0x1234_5678);
+    let mut sim = Simulator::default();
+    for _ in 0..500 {
+        // loosely structured packets, so the stream gets past the type byte
+        let mut data = vec![];
+        if rng.below(4) != 0 {
+            data.push(STX);
+            data.push(TYPES[rng.below(TYPES.len() as u32) as usize]);
+        }
+        for _ in 0..rng.below(4) {
+            data.push(PAYLOAD);
+            let len = rng.below(700);
+            data.extend((0..len).map(|_| rng.next() as u8));
+        }
+        if rng.below(4) != 0 {
+            data.push(ETX);
+        }
+        if rng.below(8) == 0 {
+            let ty = TYPES[rng.below(TYPES.len() as u32) as usize];
+            let mut out = vec![0u8; MAX_FRAME_LEN];
+            data.truncate(MAX_PAYLOAD);
+            let len = encode(ty, rng.next() as u8, &data, &mut out).unwrap();
+            out.truncate(len);
+            data = out;
+            if rng.below(2) == 0 {
+                data.truncate(rng.below(data.len() as u32) as usize);
+            }
+        }
+
+        for chunk in data.chunks(256) {
+            sim.write(chunk);
+            sim.tick();
+        }
+        sim.replies();
+    }
+
+    // the stream recovers once the junk stops
+    sim.imgr.reset();
+    sim.write(&packet(b'S', &["T13:14:15"]));
+    sim.tick();
+    assert_eq!(sim.system.clock.get_time(), Time::from_hms(13, 14, 15).unwrap());
+}
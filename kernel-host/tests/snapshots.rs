@@ -0,0 +1,303 @@
+use mwatch_kernel::{
+    application::{
+        header::{Header, HEADER_LEN, ICON_SIZE},
+        states::{
+            alarms::AlarmState, app::AppState, clock::ClockState, info::InfoState, mwatch::MWState,
+            notifications::NotificationState, stopwatch::StopwatchState, uop::UopState, ScopedState, State,
+        },
+    },
+    system::{
+        alarm::{Alarm, Days},
+        bms::State as BmsState,
+        Clock,
+        input::InputEvent,
+        IDLE_TIMEOUT_MS,
+    },
+};
+use mwatch_kernel_host::{snapshot::assert_snapshot, SimBattery, SimClock, Simulator};
+use time::{Date, Duration, Month, Time, UtcOffset};
+
+const STX: u8 = 2;
+const ETX: u8 = 3;
+const PAYLOAD: u8 = 31;
+
+fn simulator() -> Simulator {
+    Simulator::new(
+        SimClock::new(
+            Date::from_calendar_date(2019, Month::December, 2).unwrap(),
+            Time::from_hms(12, 21, 11).unwrap(),
+        ),
+        SimBattery {
+            state: BmsState::Charging,
+            soc: 87,
+        },
+    )
+}
+
+fn notify(sim: &mut Simulator, source: &str, title: &str, body: &str) {
+    let mut data = vec![STX, b'N'];
+    for section in [source, title, body] {
+        data.push(PAYLOAD);
+        data.extend_from_slice(section.as_bytes());
+    }
+    data.push(ETX);
+    sim.write(&data);
+    sim.imgr.process(&mut sim.system);
+}
+
+#[test]
+fn clock() {
+    let mut sim = simulator();
+    sim.render_state(&mut ClockState::default());
+    assert_snapshot("clock", &sim.display);
+}
+
+#[test]
+fn clock_utc_offset() {
+    let mut sim = simulator();
+    // 02:21 UTC is the afternoon before in Honolulu
+    sim.system.utc_offset = UtcOffset::from_hms(-10, 0, 0).unwrap();
+    sim.system.clock.advance(Duration::hours(-10));
+    sim.render_state(&mut ClockState::default());
+    assert_snapshot("clock_utc_offset", &sim.display);
+}
+
+#[test]
+fn clock_idle() {
+    let mut sim = simulator();
+    sim.system.uptime.advance(Duration::milliseconds(IDLE_TIMEOUT_MS as i64));
+    sim.render_state(&mut ClockState::default());
+    assert_snapshot("clock_idle", &sim.display);
+}
+
+#[test]
+fn info() {
+    let mut sim = simulator();
+    sim.render_state(&mut InfoState);
+    assert_snapshot("info", &sim.display);
+}
+
+#[test]
+fn mwatch() {
+    let mut sim = simulator();
+    sim.render_state(&mut MWState::default());
+    assert_snapshot("mwatch", &sim.display);
+}
+
+#[test]
+fn uop() {
+    let mut sim = simulator();
+    sim.render_state(&mut UopState::default());
+    assert_snapshot("uop", &sim.display);
+}
+
+#[test]
+fn app_preview() {
+    let mut sim = simulator();
+    sim.preview_state(&mut AppState::default());
+    assert_snapshot("app_preview", &sim.display);
+}
+
+#[test]
+fn app_preview_loading() {
+    let mut sim = simulator();
+    sim.system.am.begin_upload(1000, 0).unwrap();
+    sim.system.am.begin_chunk(0).unwrap();
+    for _ in 0..400 {
+        sim.system.am.write_ram_byte(0).unwrap();
+    }
+    sim.system.am.end_chunk(crc::crc32::checksum_ieee(&[0u8; 400])).unwrap();
+    sim.preview_state(&mut AppState::default());
+    assert_snapshot("app_preview_loading", &sim.display);
+}
+
+#[test]
+fn app_preview_loaded() {
+    let entry = HEADER_LEN as u32 | 1;
+    let mut header = Header::new("Snake", entry, entry, entry, 512);
+    // a hollow square
+    for y in 0..ICON_SIZE {
+        let row: u16 = if (2..ICON_SIZE - 2).contains(&y) { 0xC003 } else { 0xFFFF };
+        header.icon[y * 2..y * 2 + 2].copy_from_slice(&row.to_be_bytes());
+    }
+    let mut app = header.to_bytes().to_vec();
+    app.resize(256, 0);
+
+    let mut sim = simulator();
+    sim.system.am.begin_upload(app.len(), crc::crc32::checksum_ieee(&app)).unwrap();
+    sim.system.am.begin_chunk(0).unwrap();
+    for &byte in &app {
+        sim.system.am.write_ram_byte(byte).unwrap();
+    }
+    sim.system.am.end_chunk(crc::crc32::checksum_ieee(&app)).unwrap();
+    sim.preview_state(&mut AppState::default());
+    assert_snapshot("app_preview_loaded", &sim.display);
+}
+
+#[test]
+fn notifications_preview() {
+    let mut sim = simulator();
+    sim.preview_state(&mut NotificationState::default());
+    assert_snapshot("notifications_preview", &sim.display);
+}
+
+#[test]
+fn notifications_empty() {
+    let mut sim = simulator();
+    let mut state = NotificationState::default();
+    state.start(&mut sim.system);
+    sim.render_state(&mut state);
+    assert_snapshot("notifications_empty", &sim.display);
+}
+
+#[test]
+fn notifications_menu() {
+    let mut sim = simulator();
+    notify(&mut sim, "Messenger", "Scott", "Hello world");
+    sim.system.clock.advance(Duration::minutes(5));
+    notify(&mut sim, "Slack", "Team", "Standup in 5");
+    sim.system.clock.advance(Duration::minutes(1));
+    let mut state = NotificationState::default();
+    state.start(&mut sim.system);
+    // read the first, leaving the second unread
+    state.input(&mut sim.system, InputEvent::Middle);
+    state.input(&mut sim.system, InputEvent::Middle);
+    state.input(&mut sim.system, InputEvent::Right);
+    sim.render_state(&mut state);
+    assert_snapshot("notifications_menu", &sim.display);
+}
+
+#[test]
+fn notifications_confirm_clear() {
+    let mut sim = simulator();
+    notify(&mut sim, "Messenger", "Scott", "Hello world");
+    let mut state = NotificationState::default();
+    state.start(&mut sim.system);
+    state.input(&mut sim.system, InputEvent::Dual);
+    sim.render_state(&mut state);
+    assert_snapshot("notifications_confirm_clear", &sim.display);
+}
+
+#[test]
+fn notifications_body() {
+    let mut sim = simulator();
+    notify(
+        &mut sim,
+        "Messenger",
+        "Scott",
+        "The quick brown fox jumps over the lazy dog, several times, until the text no longer fits on a single line.",
+    );
+    sim.system.clock.advance(Duration::hours(2));
+    let mut state = NotificationState::default();
+    state.start(&mut sim.system);
+    state.input(&mut sim.system, InputEvent::Middle);
+    sim.render_state(&mut state);
+    assert_snapshot("notifications_body", &sim.display);
+}
+
+#[test]
+fn notification_toast() {
+    let mut sim = simulator();
+    notify(&mut sim, "Messenger", "Scott", "Are you coming to the pub tonight? We're leaving at 7");
+    // high priority, with tagged title, body and priority fields
+    sim.write(b"\x02N\x1f\x12Boss\x1f\x13Can you call me back? It's about the quarterly report\x1f\x162\x03");
+    sim.tick();
+    assert_snapshot("notification_toast", &sim.display);
+}
+
+#[test]
+fn notifications_body_actions() {
+    let mut sim = simulator();
+    sim.write(b"\x02N\x1fMessenger\x1fScott\x1fPub tonight?\x1f\x17Yes\x1eNo\x1eLater\x1f\x18key-1\x03");
+    sim.tick();
+    let mut state = NotificationState::default();
+    state.start(&mut sim.system);
+    state.input(&mut sim.system, InputEvent::Middle);
+    sim.render_state(&mut state);
+    state.input(&mut sim.system, InputEvent::Right);
+    state.input(&mut sim.system, InputEvent::Right);
+    sim.render_state(&mut state);
+    assert_snapshot("notifications_body_actions", &sim.display);
+}
+
+#[test]
+fn notifications_body_unicode() {
+    let mut sim = simulator();
+    notify(&mut sim, "Messenger", "Zoë", "Ça va? Rendez-vous au café à 18h 😀👍");
+    let mut state = NotificationState::default();
+    state.start(&mut sim.system);
+    state.input(&mut sim.system, InputEvent::Middle);
+    sim.render_state(&mut state);
+    assert_snapshot("notifications_body_unicode", &sim.display);
+}
+
+#[test]
+fn notifications_body_scrolled() {
+    let mut sim = simulator();
+    let body = (1..=15).map(|i| format!("Line {}", i)).collect::<Vec<_>>().join("\n");
+    notify(&mut sim, "Messenger", "Scott", &body);
+    let mut state = NotificationState::default();
+    state.start(&mut sim.system);
+    state.input(&mut sim.system, InputEvent::Middle);
+    sim.render_state(&mut state);
+    // scrolling stops once the last line is at the bottom of the display
+    for _ in 0..10 {
+        state.input(&mut sim.system, InputEvent::Right);
+    }
+    sim.render_state(&mut state);
+    assert_snapshot("notifications_body_scrolled", &sim.display);
+}
+
+#[test]
+fn alarms_menu() {
+    let mut sim = simulator();
+    let alarm = Alarm::new(Time::from_hms(7, 30, 0).unwrap(), Days::WEEKDAYS);
+    sim.system.alarms.set_alarm(0, Some(alarm)).unwrap();
+    let mut alarm = Alarm::new(Time::from_hms(22, 15, 0).unwrap(), Days::NONE);
+    alarm.enabled = false;
+    sim.system.alarms.set_alarm(2, Some(alarm)).unwrap();
+    let now = sim.system.clock.get_date_time();
+    sim.system.alarms.start_timer(now, Duration::minutes(5)).unwrap();
+    sim.system.clock.advance(Duration::seconds(61));
+    let mut state = AlarmState::default();
+    state.start(&mut sim.system);
+    state.input(&mut sim.system, InputEvent::Right);
+    sim.render_state(&mut state);
+    assert_snapshot("alarms_menu", &sim.display);
+}
+
+#[test]
+fn alarms_edit() {
+    let mut sim = simulator();
+    let mut state = AlarmState::default();
+    state.start(&mut sim.system);
+    state.input(&mut sim.system, InputEvent::Middle);
+    state.input(&mut sim.system, InputEvent::Middle);
+    state.input(&mut sim.system, InputEvent::Right);
+    sim.render_state(&mut state);
+    assert_snapshot("alarms_edit", &sim.display);
+}
+
+#[test]
+fn alarm_alert() {
+    let mut sim = simulator();
+    let alarm = Alarm::new(Time::from_hms(12, 22, 0).unwrap(), Days::NONE);
+    sim.system.alarms.set_alarm(0, Some(alarm)).unwrap();
+    sim.system.clock.advance(Duration::minutes(1));
+    sim.tick();
+    assert_snapshot("alarm_alert", &sim.display);
+}
+
+#[test]
+fn stopwatch() {
+    let mut sim = simulator();
+    let mut state = StopwatchState::default();
+    state.input(&mut sim.system, InputEvent::Middle);
+    for millis in [31_250, 29_870, 32_010] {
+        sim.system.uptime.advance(Duration::milliseconds(millis));
+        state.input(&mut sim.system, InputEvent::Dual);
+    }
+    sim.system.uptime.advance(Duration::milliseconds(4_560));
+    sim.render_state(&mut state);
+    assert_snapshot("stopwatch", &sim.display);
+}
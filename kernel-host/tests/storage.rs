@@ -0,0 +1,114 @@
+use mwatch_kernel::system::storage::{Key, VERSION};
+use mwatch_kernel_host::{SimBattery, SimClock, SimStorage, Simulator};
+use time::UtcOffset;
+
+const STX: u8 = 2;
+const ETX: u8 = 3;
+const PAYLOAD: u8 = 31;
+
+fn packet(ty: u8, sections: &[&str]) -> Vec<u8> {
+    let mut data = vec![STX, ty];
+    for section in sections {
+        data.push(PAYLOAD);
+        data.extend_from_slice(section.as_bytes());
+    }
+    data.push(ETX);
+    data
+}
+
+/// Reset the watch, keeping only what was saved to storage
+fn reset(sim: &Simulator) -> Simulator {
+    Simulator::with_storage(SimClock::default(), SimBattery::default(), sim.system.storage.clone())
+}
+
+fn titles(sim: &Simulator) -> Vec<&str> {
+    sim.system.nm.iter().map(|n| n.title()).collect()
+}
+
+#[test]
+fn storage_survives_reset() {
+    let mut sim = Simulator::default();
+    for title in ["a", "b", "c", "d", "e"] {
+        sim.write(&packet(b'N', &["Messenger", title, "Hello"]));
+    }
+    sim.write(&packet(b'S', &["F+Slack"]));
+    sim.write(&packet(b'S', &["Q22:00:00-07:00:00"]));
+    sim.write(&packet(b'S', &["Z-03:30"]));
+    sim.write(&packet(b'S', &["A2=06:45:00/67"]));
+    sim.tick();
+    let id = sim.system.nm.get(1).unwrap().id();
+    sim.system.nm.mark_read(id);
+    sim.tick();
+
+    let restored = reset(&sim);
+    assert_eq!(titles(&restored), ["b", "c", "d", "e"]);
+    assert!(restored.system.nm.find(id).unwrap().is_read());
+    assert_eq!(restored.system.nm.unread(), 3);
+    assert!(restored.system.nm.filter.is_muted("Slack"));
+    assert_eq!(restored.system.nm.filter.quiet_hours(), sim.system.nm.filter.quiet_hours());
+    assert_eq!(restored.system.utc_offset, UtcOffset::from_hms(-3, -30, 0).unwrap());
+    assert_eq!(restored.system.alarms.alarm(2), sim.system.alarms.alarm(2));
+    assert!(restored.system.alarms.alarm(2).is_some());
+    assert_eq!(
+        restored.system.nm.iter().map(|n| n.timestamp()).collect::<Vec<_>>(),
+        sim.system.nm.iter().map(|n| n.timestamp()).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn storage_only_writes_changes() {
+    let mut sim = Simulator::default();
+    sim.write(&packet(b'N', &["Messenger", "a", "Hello"]));
+    sim.write(&packet(b'N', &["Messenger", "b", "Hello"]));
+    sim.tick();
+    // the settings and two notifications
+    assert_eq!(sim.system.storage.writes, 3);
+    sim.tick();
+    assert_eq!(sim.system.storage.writes, 3);
+
+    // dismissing only removes its record
+    let (a, b) = (sim.system.nm.get(0).unwrap().id(), sim.system.nm.get(1).unwrap().id());
+    sim.system.nm.dismiss(a);
+    sim.tick();
+    assert_eq!(sim.system.storage.writes, 4);
+    assert!(!sim.system.storage.records.contains_key(&Key::Notification(a)));
+    assert!(sim.system.storage.records.contains_key(&Key::Notification(b)));
+
+    // restoring doesn't write anything back
+    let mut restored = reset(&sim);
+    restored.tick();
+    assert_eq!(restored.system.storage.writes, 4);
+    assert_eq!(titles(&restored), ["b"]);
+}
+
+#[test]
+fn storage_keeps_the_newest_notifications() {
+    let mut sim = Simulator::default();
+    for title in ["a", "b", "c", "d", "e", "f"] {
+        sim.write(&packet(b'N', &["Messenger", title, "Hello"]));
+        // evicted notifications are removed as newer ones are saved
+        sim.tick();
+    }
+    let mut storage = sim.system.storage.clone();
+    assert_eq!(storage.records.keys().filter(|key| matches!(key, Key::Notification(_))).count(), 4);
+
+    // as if reset before the evicted notifications were removed
+    let newest = sim.system.nm.get(3).unwrap().id();
+    let record = storage.records[&Key::Notification(newest)].clone();
+    storage.records.insert(Key::Notification(0), record);
+    let restored = Simulator::with_storage(SimClock::default(), SimBattery::default(), storage);
+    assert_eq!(titles(&restored), ["c", "d", "e", "f"]);
+    assert!(!restored.system.storage.records.contains_key(&Key::Notification(0)));
+}
+
+#[test]
+fn storage_ignores_bad_records() {
+    let mut storage = SimStorage::default();
+    storage.records.insert(Key::Settings, vec![VERSION + 1, 0, 0, 0, 0, 0, 0, 0, 0]);
+    storage.records.insert(Key::Notification(0), vec![VERSION, 1, 2, 3]);
+    let sim = Simulator::with_storage(SimClock::default(), SimBattery::default(), storage);
+    assert!(sim.system.nm.is_empty());
+    assert_eq!(sim.system.nm.filter.muted().count(), 0);
+    // unreadable notifications are removed, as nothing would overwrite them
+    assert!(!sim.system.storage.records.contains_key(&Key::Notification(0)));
+}
@@ -1,6 +1,7 @@
 MEMORY
 {
-  FLASH (rx): ORIGIN = 0x8000000, LENGTH = 256K 
+  /* the stm32l432kc has 256K of flash, the last 16K holds the kernel's storage, see `system::InternalFlash` */
+  FLASH (rx): ORIGIN = 0x8000000, LENGTH = 240K
   RAM (rwx) : ORIGIN = 0x20000000, LENGTH = 16K
   APPDATA (rwx) : ORIGIN = 0x20004000, LENGTH = 16K
   FRAMEBUFFER (rwx) : ORIGIN = 0x20008000, LENGTH = 32K
@@ -21,7 +21,7 @@ use crate::{
     tsc::TscManager,
     types::{hal, BluetoothConnectedPin, LoggerType},
 };
-use mwatch_kernel::{application, ingress, system::{input::InputEvent, System}};
+use mwatch_kernel::{application, ingress, system::{flash::FlashStorage, input::InputEvent, System, Uptime}};
 use system::KernelHost;
 
 use crate::hal::{
@@ -55,10 +55,10 @@ use crate::application::{
     application_manager::{ApplicationManager, Ram},
     display_manager::DisplayManager,
 };
-use crate::ingress::ingress_manager::IngressManager;
+use crate::ingress::ingress_manager::{FlowControl, IngressManager};
 
 use crate::system::{
-    CPU_USAGE_POLL_HZ, DMA_HALF_BYTES, I2C_KHZ, SPI_MHZ, SYSTICK_HZ, SYS_CLK_HZ, TSC_HZ,
+    CPU_USAGE_POLL_HZ, DMA_HALF_BYTES, EGRESS_BYTES_PER_TICK, I2C_KHZ, SPI_MHZ, SYSTICK_HZ, SYS_CLK_HZ, TSC_HZ,
 };
 use mwatch_kernel::system::input::InputManager;
 
@@ -76,6 +76,7 @@ const APP: () = {
         TSC_MGR: TscManager,
         DMNG: DisplayManager,
         USART2_RX: hal::serial::Rx<hal::stm32l4::stm32l4x2::USART2>,
+        USART2_TX: hal::serial::Tx<hal::stm32l4::stm32l4x2::USART2>,
         DISPLAY: DisplayWrapper,
         BT_CONN: BluetoothConnectedPin,
         SYSTEM: System<KernelHost>,
@@ -89,9 +90,9 @@ const APP: () = {
         #[init(0)]
         TSC_EVENTS: u32,
         #[init(0)]
-        IDLE_COUNT: u32,
-        #[init(0)]
         LAST_BATT_PERCENT: u16,
+        #[init(false)]
+        BT_CONNECTED: bool,
         #[init(None)]
         LOGGER: Option<LoggerType>,
         #[init([0u8; 32 * 1024])]
@@ -223,7 +224,7 @@ const APP: () = {
         delay.delay_ms(100_u8); // allow module to reset
         hm11.send_with_delay(Command::Test, &mut delay)
             .expect("HM11 - Module did not responde after reboot");
-        let (_, rx) = hm11.release();
+        let (tx, rx) = hm11.release();
 
         channels.6.listen(Event::HalfTransfer);
         channels.6.listen(Event::TransferComplete);
@@ -343,7 +344,15 @@ const APP: () = {
         let dmng = DisplayManager::default();
         let mut stats = Stats::default();
         stats.tsc_threshold = tsc_mgr.threshold();
-        let system = System::new(system::RtcWrapper(rtc), bms, stats, amgr);
+        let system = System::new(
+            system::RtcWrapper(rtc),
+            system::CycleUptime::new(),
+            bms,
+            stats,
+            amgr,
+            FlashStorage::new(system::InternalFlash::new(flash).expect("Storage overlaps the program"))
+                .expect("Failed to open the flash storage"),
+        );
 
         // To preload a path, add include the path here
         // let app = include_bytes!(/* PATH */);
@@ -361,6 +370,7 @@ const APP: () = {
         init::LateResources {
             CB: rx.circ_read(channels.6, buffer),
             USART2_RX: rx,
+            USART2_TX: tx,
             IMNG: imgr,
             DISPLAY: display,
             SYSTEM: system,
@@ -394,25 +404,40 @@ const APP: () = {
 
     /// The main thread of the watch, this is called `SYSTICK_HZ` times a second, to perform
     /// housekeeping operations
-    #[task(binds = TIM2, resources = [IMNG, SYSTEM, SYSTICK, IDLE_COUNT], spawn = [display_manager])]
+    #[task(binds = TIM2, resources = [IMNG, SYSTEM, SYSTICK, USART2_TX, BT_CONN, BT_CONNECTED], spawn = [display_manager])]
     fn systemtick(cx: systemtick::Context) {
         let mut system = cx.resources.SYSTEM;
         let mut mgr = cx.resources.IMNG;
-        let mut idle = cx.resources.IDLE_COUNT;
+        let tx = cx.resources.USART2_TX;
+
+        let connected = cx.resources.BT_CONN.is_high().unwrap_or(false);
+        if *cx.resources.BT_CONNECTED && !connected {
+            info!("Bluetooth disconnected, dropping partial packets");
+            mgr.lock(|m| m.reset());
+        }
+        *cx.resources.BT_CONNECTED = connected;
 
         cx.spawn.display_manager().unwrap_or_else(|_err| {
             error!("Failed to spawn display manager");
         });
 
-        system.lock(|system| {
+        let mut buffer = [0u8; EGRESS_BYTES_PER_TICK];
+        let len = system.lock(|system| {
+            system.uptime.update();
             system.bms.process();
-            system.stats.idle_count = idle.lock(|val| {
-                let value = *val;
-                *val += 1; // append to idle count
-                value
-            });
-            mgr.lock(|m| m.process(system));
+            mgr.lock(|m| ingress_process(m, system));
+            system.process();
+            system.sync();
+
+            // take some of the replies generated whilst processing, the rest are sent on later ticks
+            system.em.read(&mut buffer)
         });
+        // sent without the system locked, so the display and input aren't held up by the serial port
+        for byte in &buffer[..len] {
+            nb::block!(tx.write(*byte)).unwrap_or_else(|err| {
+                error!("Failed to write to serial: {:?}", err);
+            });
+        }
         cx.resources
             .SYSTICK
             .wait()
@@ -478,15 +503,14 @@ const APP: () = {
 
     /// When a TSC aquisition completes, the result is processed by the input manager
     /// If the result is a valid output, the input handler task is spawned to act upon it
-    #[task(binds = TSC, resources = [TSC_EVENTS, INPUT_MGR, IDLE_COUNT, TSC_MGR], priority = 3, spawn = [input_handler])]
+    #[task(binds = TSC, resources = [TSC_EVENTS, INPUT_MGR, TSC_MGR], priority = 3, spawn = [input_handler])]
     fn tsc_result(cx: tsc_result::Context) {
         *cx.resources.TSC_EVENTS += 1;
         let input_mgr = cx.resources.INPUT_MGR;
         input_mgr.update_input(cx.resources.TSC_MGR.result(input_mgr.current_pin() as u8));
 
-        match input_mgr.output() {
+        match input_mgr.output(system::CycleUptime.millis()) {
             Ok(input) => {
-                *cx.resources.IDLE_COUNT = 0; // we are no longer idle
                 info!("Output => {:?}", input);
                 match cx.spawn.input_handler(input) {
                     Ok(_) => {}
@@ -507,7 +531,7 @@ const APP: () = {
 
     /// Handles the intermediate state where the DMA has data in it but
     /// not enough to trigger a half or full dma complete
-    #[task(binds = USART2, resources = [CB, IMNG, USART2_RX], priority = 3)]
+    #[task(binds = USART2, resources = [CB, IMNG, USART2_RX], priority = 3, spawn = [process_ingress])]
     fn serial_partial_dma(cx: serial_partial_dma::Context) {
         let mgr = cx.resources.IMNG;
         // If the idle flag is set then we take what we have and push
@@ -518,35 +542,49 @@ const APP: () = {
                 .partial_peek(|buf, _half| {
                     let len = buf.len();
                     if len > 0 {
-                        mgr.write(buf);
+                        ingress_write(mgr, buf);
                     }
                     Ok((len, ()))
                 })
                 .unwrap_or_else(|err| {
                     error!("Failed to partial peek into circular buffer {:?}", err);
                 });
+            // already pending if this fails, which will pick this data up too
+            cx.spawn.process_ingress().ok();
         }
     }
 
     /// Handles a full or hal full dma buffer of serial data,
     /// and writes it into the MessageManager rb
-    #[task(binds = DMA1_CH6, resources = [CB, IMNG], priority = 3)]
+    #[task(binds = DMA1_CH6, resources = [CB, IMNG], priority = 3, spawn = [process_ingress])]
     fn serial_full_dma(cx: serial_full_dma::Context) {
         let mgr = cx.resources.IMNG;
         cx.resources
             .CB
             .peek(|buf, _half| {
-                mgr.write(buf);
+                ingress_write(mgr, buf);
             })
             .unwrap_or_else(|err| {
                 error!("Failed to full peek into circular buffer {:?}", err);
             });
+        // already pending if this fails, which will pick this data up too
+        cx.spawn.process_ingress().ok();
     }
 
     /*
         Software tasks
     */
 
+    /// Processes received data as it arrives, so the ingress ring buffer doesn't fill between systicks
+    #[task(resources = [IMNG, SYSTEM])]
+    fn process_ingress(cx: process_ingress::Context) {
+        let mut mgr = cx.resources.IMNG;
+        let mut system = cx.resources.SYSTEM;
+        system.lock(|system| {
+            mgr.lock(|m| ingress_process(m, system));
+        });
+    }
+
     /// Task that services the display manager
     #[task(resources = [DISPLAY, SYSTEM, BT_CONN, DMNG])]
     fn display_manager(cx: display_manager::Context) {
@@ -608,6 +646,38 @@ fn HardFault(ef: &ExceptionFrame) -> ! {
     panic!("{:#?}", ef);
 }
 
+/// Push serial data into the ingress manager, logging anything that didn't fit
+fn ingress_write(mgr: &mut IngressManager, buf: &[u8]) {
+    let flow = mgr.flow_control();
+    let accepted = mgr.write(buf);
+    if accepted < buf.len() {
+        error!("Ingress ring buffer full, dropped {} bytes", buf.len() - accepted);
+    }
+    if mgr.flow_control() != flow {
+        flow_control(mgr.flow_control());
+    }
+}
+
+/// Process the ingress ring buffer, releasing the sender once it has drained
+fn ingress_process(mgr: &mut IngressManager, system: &mut System<KernelHost>) {
+    let flow = mgr.flow_control();
+    mgr.process(system);
+    if mgr.flow_control() != flow {
+        flow_control(mgr.flow_control());
+    }
+}
+
+/// Called whenever the ingress flow control signal changes.
+///
+/// The HM-11 has no handshake lines wired to the MCU, so there is no way to pause the phone yet;
+/// this is where the transport should do so once there is.
+fn flow_control(flow: FlowControl) {
+    match flow {
+        FlowControl::Throttle => warn!("Ingress ring buffer filling up, the sender should pause"),
+        FlowControl::Ready => info!("Ingress ring buffer drained, the sender may resume"),
+    }
+}
+
 #[cfg(feature = "rtt")]
 mod rtt {
     use rtt_target::{rprintln, rtt_init_print};
@@ -4,16 +4,28 @@
 
 use crate::{
     bms::BatteryManagement,
-    types::{BatteryManagementInterface, ChargeStatusPin, Ssd1351, StandbyStatusPin},
+    types::{hal, BatteryManagementInterface, ChargeStatusPin, Ssd1351, StandbyStatusPin},
 };
 use core::fmt::Write;
+use core::ptr;
+use core::sync::atomic::{AtomicU32, Ordering};
 use embedded_graphics::{pixelcolor::Rgb565, prelude::OriginDimensions};
 use heapless::String;
-use mwatch_kernel::system::Host;
-use stm32l4xx_hal::{prelude::_stm32l4_hal_datetime_U32Ext, rtc::Rtc};
+use cortex_m::peripheral::DWT;
+use mwatch_kernel::system::{
+    flash::{Flash, FlashStorage, WRITE_SIZE},
+    Host, Uptime,
+};
+use stm32l4xx_hal::{
+    prelude::_stm32l4_hal_datetime_U32Ext,
+    rtc::Rtc,
+    stm32::{flash, FLASH},
+};
 use time::{Date, Time};
 
 pub const DMA_HALF_BYTES: usize = 64;
+/// Replies sent each systick, ~5.5ms of blocking writes at 115200 baud; anything more waits for the next tick
+pub const EGRESS_BYTES_PER_TICK: usize = 64;
 
 pub const CPU_USAGE_POLL_HZ: u32 = 1; // hz
 pub const SYSTICK_HZ: u32 = 10; // hz
@@ -23,16 +35,16 @@ pub const SYS_CLK_HZ: u32 = 80_000_000;
 pub const SPI_MHZ: u32 = SYS_CLK_HZ / 20_000_000;
 pub const I2C_KHZ: u32 = 100;
 
-pub const IDLE_TIMEOUT_SECONDS: u32 = 15;
-
 pub struct KernelHost;
 
 impl Host for KernelHost {
     type BatteryManager =
         BatteryManagement<BatteryManagementInterface, ChargeStatusPin, StandbyStatusPin>;
     type TimeProvider = RtcWrapper;
+    type UptimeProvider = CycleUptime;
     type Statistics = Stats;
     type Display = DisplayWrapper;
+    type Storage = FlashStorage<InternalFlash>;
 }
 
 pub mod abi {
@@ -96,7 +108,7 @@ impl mwatch_kernel::system::Clock for RtcWrapper {
 
     fn set_date(&mut self, d: &Date) {
         let d = stm32l4xx_hal::datetime::Date::new(
-            0.day(),
+            (d.weekday().number_from_monday() as u32).day(),
             (d.day() as u32).date(),
             (d.month() as u32).month(),
             (d.year() as u32).year(),
@@ -105,6 +117,50 @@ impl mwatch_kernel::system::Clock for RtcWrapper {
     }
 }
 
+/// The number of times the cycle counter has wrapped
+static WRAPS: AtomicU32 = AtomicU32::new(0);
+/// The cycle count at the last update
+static LAST: AtomicU32 = AtomicU32::new(0);
+
+/// Uptime counted by the DWT cycle counter
+///
+/// The counter wraps every ~53 seconds at [`SYS_CLK_HZ`], so [`CycleUptime::update`] must be called more often
+/// than that to keep track of the wraps. The count is kept in statics so that interrupts can read the uptime
+/// without locking the system.
+#[derive(Debug, Clone, Copy)]
+pub struct CycleUptime;
+
+impl CycleUptime {
+    /// Start counting, the cycle counter must already be enabled
+    pub fn new() -> Self {
+        LAST.store(DWT::get_cycle_count(), Ordering::Relaxed);
+        Self
+    }
+
+    /// Count a wrap of the cycle counter since the last update
+    pub fn update(&self) {
+        cortex_m::interrupt::free(|_| {
+            let now = DWT::get_cycle_count();
+            if now < LAST.load(Ordering::Relaxed) {
+                WRAPS.fetch_add(1, Ordering::Relaxed);
+            }
+            LAST.store(now, Ordering::Relaxed);
+        })
+    }
+}
+
+impl Uptime for CycleUptime {
+    fn millis(&self) -> u64 {
+        let cycles = cortex_m::interrupt::free(|_| {
+            let now = DWT::get_cycle_count();
+            // a wrap not yet counted by an update
+            let wraps = WRAPS.load(Ordering::Relaxed) + (now < LAST.load(Ordering::Relaxed)) as u32;
+            (u64::from(wraps) << 32) | u64::from(now)
+        });
+        cycles / (SYS_CLK_HZ as u64 / 1000)
+    }
+}
+
 impl mwatch_kernel::system::bms::BatteryManagement
     for BatteryManagement<BatteryManagementInterface, ChargeStatusPin, StandbyStatusPin>
 {
@@ -127,8 +183,8 @@ impl mwatch_kernel::system::Statistics for Stats {
         }
     }
 
-    fn is_idle(&mut self) -> bool {
-        (self.idle_count / SYSTICK_HZ) > IDLE_TIMEOUT_SECONDS
+    fn ingress_dropped(&mut self, total: u32) {
+        self.rx_dropped = total;
     }
 }
 
@@ -136,8 +192,8 @@ impl mwatch_kernel::system::Statistics for Stats {
 pub struct Stats {
     pub cpu_usage: f32,
     pub tsc_events: u32,
-    pub idle_count: u32,
     pub tsc_threshold: u16,
+    pub rx_dropped: u32,
 }
 
 impl Default for Stats {
@@ -145,8 +201,8 @@ impl Default for Stats {
         Self {
             cpu_usage: 0.0,
             tsc_events: 0,
-            idle_count: 0,
             tsc_threshold: 0,
+            rx_dropped: 0,
         }
     }
 }
@@ -166,6 +222,7 @@ impl Iterator for StatsIter {
             0 => write!(buffer, "CPU_USAGE: {:.02}%", self.stats.cpu_usage).unwrap(),
             1 => write!(buffer, "TSC EVENTS: {}/s", self.stats.tsc_events).unwrap(),
             2 => write!(buffer, "TSC THRES: {}", self.stats.tsc_threshold).unwrap(),
+            3 => write!(buffer, "RX DROPPED: {}", self.stats.rx_dropped).unwrap(),
             _ => return None,
         }
         self.index += 1;
@@ -173,6 +230,144 @@ impl Iterator for StatsIter {
     }
 }
 
+/// The size of the kernel's storage, the last pages of flash which `memory.x` keeps out of the program
+const STORAGE_LEN: usize = 16 * 1024;
+const FLASH_START: usize = 0x0800_0000;
+/// Where the part records the size of its flash, in KB
+const FLASH_SIZE_DATA: usize = 0x1FFF_75E0;
+const FLASH_PAGE_SIZE: usize = 2048;
+/// The SR error flags: OPERR, PROGERR, WRPERR, PGAERR, SIZERR, PGSERR, MISERR, FASTERR, RDERR and OPTVERR
+const FLASH_SR_ERRORS: u32 = 0xC3FA;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FlashError {
+    /// Outside the storage region
+    OutOfBounds,
+    /// The program reaches into the storage region, `memory.x` doesn't match the part
+    Overlap,
+    /// The flash controller failed, with these SR error flags set
+    Status(u32),
+}
+
+/// The last pages of the internal flash, holding the kernel's [`FlashStorage`]
+pub struct InternalFlash {
+    /// The address of the storage region, at the end of the part's flash
+    start: usize,
+}
+
+impl InternalFlash {
+    /// Take the flash controller, once its ACR has been used to set the clocks
+    ///
+    /// The storage region is placed at the end of the part's flash, failing if the program reaches into it.
+    pub fn new(_flash: hal::flash::Parts) -> Result<Self, FlashError> {
+        extern "C" {
+            static __sidata: u32;
+            static __sdata: u32;
+            static __edata: u32;
+        }
+        // NOTE(unsafe) a factory programmed value, and the addresses of symbols from the linker script
+        let (size, program_end) = unsafe {
+            let size = ptr::read_volatile(FLASH_SIZE_DATA as *const u16) as usize * 1024;
+            // the initial values of .data are the last of the program
+            let data = &__edata as *const u32 as usize - &__sdata as *const u32 as usize;
+            (size, &__sidata as *const u32 as usize + data)
+        };
+        let start = FLASH_START + size - STORAGE_LEN;
+        if program_end > start {
+            return Err(FlashError::Overlap);
+        }
+        Ok(Self { start })
+    }
+
+    fn address(&self, offset: usize, len: usize) -> Result<usize, FlashError> {
+        match offset.checked_add(len) {
+            Some(end) if end <= STORAGE_LEN => Ok(self.start + offset),
+            _ => Err(FlashError::OutOfBounds),
+        }
+    }
+
+    fn regs(&self) -> &flash::RegisterBlock {
+        // NOTE(unsafe) this proxy owns the flash controller, the HAL only used the ACR while setting the clocks
+        unsafe { &*FLASH::ptr() }
+    }
+
+    /// Unlock the flash for a program or erase, locking it again afterwards
+    fn unlocked(&mut self, f: impl FnOnce(&flash::RegisterBlock) -> Result<(), FlashError>) -> Result<(), FlashError> {
+        let regs = self.regs();
+        // clear the flags left by an earlier operation
+        regs.sr.write(|w| unsafe { w.bits(FLASH_SR_ERRORS | 1) });
+        if regs.cr.read().lock().bit_is_set() {
+            regs.keyr.write(|w| unsafe { w.keyr().bits(0x4567_0123) });
+            regs.keyr.write(|w| unsafe { w.keyr().bits(0xCDEF_89AB) });
+        }
+        let result = f(regs);
+        regs.cr.modify(|_, w| w.pg().clear_bit().per().clear_bit().lock().set_bit());
+        result
+    }
+}
+
+/// Wait for a program or erase to finish
+fn flash_wait(regs: &flash::RegisterBlock) -> Result<(), FlashError> {
+    while regs.sr.read().bsy().bit_is_set() {}
+    match regs.sr.read().bits() & FLASH_SR_ERRORS {
+        0 => Ok(()),
+        errors => Err(FlashError::Status(errors)),
+    }
+}
+
+impl Flash for InternalFlash {
+    type Error = FlashError;
+
+    const PAGE_SIZE: usize = FLASH_PAGE_SIZE;
+
+    fn capacity(&self) -> usize {
+        STORAGE_LEN
+    }
+
+    fn read(&mut self, offset: usize, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        let address = self.address(offset, buffer.len())?;
+        // NOTE(unsafe) flash is memory mapped, and this region isn't part of the program
+        buffer.copy_from_slice(unsafe { core::slice::from_raw_parts(address as *const u8, buffer.len()) });
+        Ok(())
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), Self::Error> {
+        let address = self.address(offset, data.len())?;
+        self.unlocked(|regs| {
+            regs.cr.modify(|_, w| w.pg().set_bit());
+            for (i, double) in data.chunks_exact(WRITE_SIZE).enumerate() {
+                let word = address as *mut u32;
+                // NOTE(unsafe) flash is programmed a double word at a time, each word written in turn
+                unsafe {
+                    let word = word.add(i * 2);
+                    ptr::write_volatile(word, u32::from_le_bytes([double[0], double[1], double[2], double[3]]));
+                    ptr::write_volatile(word.add(1), u32::from_le_bytes([double[4], double[5], double[6], double[7]]));
+                }
+                flash_wait(regs)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn erase(&mut self, offset: usize) -> Result<(), Self::Error> {
+        let address = self.address(offset, FLASH_PAGE_SIZE)?;
+        let page = ((address - FLASH_START) / FLASH_PAGE_SIZE) as u8;
+        self.unlocked(|regs| {
+            regs.cr.modify(|_, w| unsafe { w.per().set_bit().pnb().bits(page) });
+            regs.cr.modify(|_, w| w.start().set_bit());
+            flash_wait(regs)
+        })?;
+        // the data cache may still hold the page as it was
+        let acr = &self.regs().acr;
+        if acr.read().dcen().bit_is_set() {
+            acr.modify(|_, w| w.dcen().clear_bit());
+            acr.modify(|_, w| w.dcrst().set_bit());
+            acr.modify(|_, w| w.dcrst().clear_bit().dcen().set_bit());
+        }
+        Ok(())
+    }
+}
+
 pub struct DisplayWrapper(pub Ssd1351);
 
 impl mwatch_kernel::system::Display for DisplayWrapper {
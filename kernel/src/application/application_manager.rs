@@ -2,18 +2,19 @@
 //!
 //! Handles loading and running of custom applications
 //!
-//! - Load information from the binary
+//! - Load information from the binary, checking its [`Header`] matches the kernel's ABI
 //! - Setup input callbacks from the kernel which then are passed to the application
 //! - Start executing
 //! 
 //! Due to the abstract nature of the `ApplicationManager` it is possible to run more than one simultaneously
 //! provided you have the available RAM
 
-use crc::crc32::checksum_ieee;
+use crc::crc32::{self, checksum_ieee, IEEE_TABLE};
 
 use crate::system::{input::InputEvent};
 
 use super::{ServiceFn, InputFn, SetupFn, Context, Table, FrameBuffer};
+use super::header::Header;
 
 /// Application manager
 pub struct ApplicationManager {
@@ -23,6 +24,8 @@ pub struct ApplicationManager {
     service_fn: Option<ServiceFn>,
     input_fn: Option<InputFn>,
     status: Status,
+    upload: Option<Upload>,
+    header: Option<Header>,
     os_table_ptr: &'static mut Table
 }
 
@@ -39,7 +42,46 @@ pub enum Error {
     /// The FFI function pointer for input is invalid
     InvalidInputFn,
     /// The application doesnt fit in memory
-    NoMemory
+    NoMemory,
+    /// A chunk was sent before the chunks preceding it
+    OutOfOrder,
+    /// No upload is in progress
+    NoUpload,
+    /// The application does not begin with a valid header
+    InvalidHeader,
+    /// The application was built for a different version of the kernel's ABI
+    IncompatibleAbi,
+}
+
+/// The progress of a chunked upload
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Progress {
+    /// Bytes received and verified so far
+    pub received: usize,
+    /// Total size of the application
+    pub total: usize,
+}
+
+impl Progress {
+    /// Percentage of the application received
+    pub fn percent(&self) -> u8 {
+        (self.received * 100).checked_div(self.total).unwrap_or(100) as u8
+    }
+}
+
+/// State of a chunked upload
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Upload {
+    total: usize,
+    checksum: u32,
+    /// Everything before this offset has passed its chunk checksum
+    committed: usize,
+    /// Offset the current chunk was sent from, before `committed` if it is being resent
+    chunk_start: usize,
+    /// Bytes of the current chunk that were already committed, these are checked but not written over committed ram
+    skip: usize,
+    /// crc32 of the already committed bytes of the current chunk
+    skipped_crc: u32,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -66,25 +108,32 @@ impl ApplicationManager {
     /// Create a new application manager from a chunk of ram
     pub fn new(ram: Ram, os_table_ptr: &'static mut Table) -> Self {
         Self {
-            ram: ram,
+            ram,
             target_cs: [0u8; 4],
             target_cs_idx: 0,
             service_fn: None,
             input_fn: None,
             status: Status::default(),
+            upload: None,
+            header: None,
             os_table_ptr,
         }
     }
 
     /// Write a byte into the managers internal ram
     pub fn write_ram_byte(&mut self, byte: u8) -> Result<(), Error> {
+        if let Some(upload) = self.upload.as_mut().filter(|upload| upload.skip > 0) {
+            upload.skip -= 1;
+            upload.skipped_crc = crc32::update(upload.skipped_crc, &IEEE_TABLE, &[byte]);
+            return Ok(());
+        }
         self.ram.write(byte)?;
         Ok(())
     }
 
     /// Write a checksum byte into the manager internal cs buffer
     pub fn write_checksum_byte(&mut self, byte: u8) -> Result<(), Error> {
-        if self.target_cs_idx > self.target_cs.len() {
+        if self.target_cs_idx >= self.target_cs.len() {
             Err(Error::NoMemory)
         } else {
             self.target_cs[self.target_cs_idx] = byte;
@@ -93,29 +142,121 @@ impl ApplicationManager {
         }
     }
 
-    /// Verify the contents of ram using a crc against the checksum
+    /// Verify the contents of ram using a crc against the checksum, then check its header
     pub fn verify(&mut self) -> Result<(), Error> {
        let ram_cs = self.ram.cs();
        let digest = ApplicationManager::digest_from_bytes(&self.target_cs);
         info!("Current Ram Digest: {}, stored ram Digest: {}", ram_cs, digest);
-        if digest == ram_cs {
-            self.status.is_loaded = true;
-            Ok(())
-        } else {
+        if digest != ram_cs {
             error!("Application checksum failed!");
-            Err(Error::ChecksumFailed)
+            return Err(Error::ChecksumFailed);
         }
+        let header = Header::parse(self.ram.as_slice(), self.ram.capacity())?;
+        info!("Loaded application {}", header.name());
+        self.header = Some(header);
+        self.status.is_loaded = true;
+        self.status.ram_used = header.ram as usize;
+        Ok(())
+    }
+
+    /// The header of the loaded application
+    pub fn header(&self) -> Option<&Header> {
+        self.header.as_ref()
+    }
+
+    /// Begin a chunked upload of an application `total` bytes long, with the crc32 `checksum` of the whole application
+    ///
+    /// If the same application is already part way through uploading the upload is resumed, otherwise
+    /// the running application is killed. Returns the offset the next chunk should be sent from.
+    pub fn begin_upload(&mut self, total: usize, checksum: u32) -> Result<usize, Error> {
+        if total > self.ram.capacity() {
+            return Err(Error::NoMemory);
+        }
+        if let Some(upload) = self.upload.as_mut() {
+            if upload.total == total && upload.checksum == checksum {
+                info!("Resuming upload from {} of {} bytes", upload.committed, total);
+                upload.chunk_start = upload.committed;
+                upload.skip = 0;
+                self.ram.seek(upload.committed)?;
+                return Ok(upload.committed);
+            }
+        }
+        self.kill()?;
+        self.target_cs = checksum.to_be_bytes();
+        self.target_cs_idx = self.target_cs.len();
+        self.upload = Some(Upload {
+            total,
+            checksum,
+            committed: 0,
+            chunk_start: 0,
+            skip: 0,
+            skipped_crc: 0,
+        });
+        Ok(0)
+    }
+
+    /// Prepare to receive a chunk of the current upload, starting at `offset`
+    ///
+    /// Chunks may be resent, but not skipped. The part of a resent chunk that was already committed is checked
+    /// but not written, so it can't corrupt what was accepted, and only the bytes past it are written.
+    pub fn begin_chunk(&mut self, offset: usize) -> Result<(), Error> {
+        let upload = self.upload.as_mut().ok_or(Error::NoUpload)?;
+        if offset > upload.committed || offset > upload.total {
+            return Err(Error::OutOfOrder);
+        }
+        upload.chunk_start = offset;
+        upload.skip = upload.committed - offset;
+        upload.skipped_crc = 0;
+        self.ram.seek(upload.committed)
+    }
+
+    /// Check the chunk written since [`ApplicationManager::begin_chunk`] against its crc32 `checksum`
+    ///
+    /// A bad chunk is discarded, as is the final chunk if the whole application fails to verify once it arrives,
+    /// so either can be resent.
+    pub fn end_chunk(&mut self, checksum: u32) -> Result<(), Error> {
+        let mut upload = self.upload.ok_or(Error::NoUpload)?;
+        let written = self.ram.len();
+        if written == upload.committed && upload.chunk_start < upload.committed {
+            info!("Chunk was already committed, ignoring it");
+            return Ok(());
+        }
+        let new = &self.ram.as_slice()[upload.committed..];
+        if written > upload.total || crc32::update(upload.skipped_crc, &IEEE_TABLE, new) != checksum {
+            error!("Chunk at {} failed its checksum", upload.chunk_start);
+            self.ram.seek(upload.committed)?;
+            return Err(Error::ChecksumFailed);
+        }
+        if written == upload.total {
+            if let Err(e) = self.verify() {
+                self.ram.seek(upload.committed)?;
+                return Err(e);
+            }
+            self.upload = None;
+            return Ok(());
+        }
+        upload.committed = written;
+        upload.chunk_start = written;
+        self.upload = Some(upload);
+        Ok(())
+    }
+
+    /// The progress of the current chunked upload, if any
+    pub fn progress(&self) -> Option<Progress> {
+        self.upload.map(|upload| Progress {
+            received: upload.committed,
+            total: upload.total,
+        })
     }
 
     /// Reconstruct a CRC32 from four bytes
     fn digest_from_bytes(bytes: &[u8]) -> u32 {
         assert_eq!(bytes.len(), 4);
         // bytes arrive in reversed order                
-        let digest = ((u32::from(bytes[0])) << 24)
+        ((u32::from(bytes[0])) << 24)
             | ((u32::from(bytes[1])) << 16)
             | ((u32::from(bytes[2])) << 8)
-            | (u32::from(bytes[3]));
-        digest
+            | (u32::from(bytes[3]))
     }
 
     /// Run the application
@@ -123,9 +264,12 @@ impl ApplicationManager {
         if !self.status.is_loaded {
             return Err(Error::NoApplication);
         }
-        let setup_ptr = Self::fn_ptr_from_slice(&self.ram.as_ref()[..4]);
-        let service_ptr = Self::fn_ptr_from_slice(&self.ram.as_ref()[4..8]);
-        let input_ptr = Self::fn_ptr_from_slice(&self.ram.as_ref()[8..12]);
+        // the ram could have been written since it was verified
+        let header = Header::parse(self.ram.as_slice(), self.ram.capacity())?;
+        let base = self.ram.as_ref().as_ptr() as usize;
+        let setup_ptr = (base + header.setup as usize) as *const ();
+        let service_ptr = (base + header.service as usize) as *const ();
+        let input_ptr = (base + header.input as usize) as *const ();
         let _result = unsafe {
             let setup: SetupFn = ::core::mem::transmute(setup_ptr);
             let service: ServiceFn = ::core::mem::transmute(service_ptr);
@@ -139,11 +283,12 @@ impl ApplicationManager {
     }
 
 
-    /// Gives processing time to the application
-    pub fn service(&mut self, display: &mut FrameBuffer) -> Result<(), Error> {
+    /// Gives processing time to the application, `now` being the uptime in milliseconds
+    pub fn service(&mut self, display: &mut FrameBuffer, now: u64) -> Result<(), Error> {
        if let Some(service_fn) = self.service_fn {
         let mut ctx = Context {
-            framebuffer: display
+            framebuffer: display,
+            millis: now,
         };
         self.status.service_result = unsafe { service_fn(&mut ctx) };
         Ok(())
@@ -153,10 +298,11 @@ impl ApplicationManager {
     }
 
     /// Gives processing time to input handlers of the function
-    pub fn service_input(&mut self, input: InputEvent) -> Result<(), Error> {
+    pub fn service_input(&mut self, input: InputEvent, now: u64) -> Result<(), Error> {
        if let Some(input_fn) = self.input_fn {
         let mut ctx = Context {
             framebuffer: core::ptr::null_mut(),
+            millis: now,
         };
         let _ = unsafe { input_fn(&mut ctx, input) };
         Ok(())
@@ -174,8 +320,11 @@ impl ApplicationManager {
     pub fn kill(&mut self) -> Result<(), Error> {
         self.ram.reset();
         self.target_cs_idx = 0;
+        self.upload = None;
         self.status.is_loaded = false;
         self.status.is_running = false;
+        self.status.ram_used = 0;
+        self.header = None;
         self.input_fn = None;
         self.service_fn = None;
         Ok(())
@@ -186,16 +335,6 @@ impl ApplicationManager {
         self.status
     }
 
-    /// convert 4 byte slice into a const ptr
-    fn fn_ptr_from_slice(bytes: &[u8]) -> *const () {
-        assert!(bytes.len() == 4);
-        let addr = ((u32::from(bytes[3])) << 24)
-            | ((u32::from(bytes[2])) << 16)
-            | ((u32::from(bytes[1])) << 8)
-            | (u32::from(bytes[0]));
-        addr as *const ()
-    }
-
     pub fn program(&self) -> &[u8] {
         self.ram.as_slice()
     }
@@ -222,7 +361,7 @@ impl Ram {
 
     /// Write a byte into Ram
     pub fn write(&mut self, byte: u8) -> Result<(), Error> {
-        if self.ram_idx > self.ram.len() {
+        if self.ram_idx >= self.ram.len() {
             Err(Error::NoMemory)
         } else {
             self.ram[self.ram_idx] = byte;
@@ -231,6 +370,29 @@ impl Ram {
         }
     }
 
+    /// Move the write position to `offset`, discarding anything written after it
+    pub fn seek(&mut self, offset: usize) -> Result<(), Error> {
+        if offset > self.ram.len() {
+            return Err(Error::NoMemory);
+        }
+        self.ram_idx = offset;
+        Ok(())
+    }
+
+    /// Number of bytes written
+    pub fn len(&self) -> usize {
+        self.ram_idx
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ram_idx == 0
+    }
+
+    /// Total size of the ram
+    pub fn capacity(&self) -> usize {
+        self.ram.len()
+    }
+
     /// ieee crc32 of the ram buffer
     pub fn cs(&self) -> u32 {
         checksum_ieee(&self.ram[..self.ram_idx])
@@ -238,8 +400,8 @@ impl Ram {
 
     /// Reset ram
     pub fn reset(&mut self) {
-        self.ram_idx = 0;
         self.wipe();
+        self.ram_idx = 0;
     }
 
     /// Zero the internal buffer
@@ -249,21 +411,146 @@ impl Ram {
         }
     }
 
-    /// Get an immutable reference to the internal ram buffer
-    pub fn as_ref(&self) -> &[u8] {
-        &self.ram
-    }
-
     pub fn as_slice(&self) -> &[u8] {
         &self.ram[..self.ram_idx]
     }
 }
 
+impl AsRef<[u8]> for Ram {
+    /// Get an immutable reference to the internal ram buffer
+    fn as_ref(&self) -> &[u8] {
+        self.ram
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::application::header::{ABI_VERSION, HEADER_LEN};
+    use std::boxed::Box;
+    use std::vec;
+
+    unsafe extern "C" fn draw_pixel(_: *mut Context, _: u8, _: u8, _: u16) -> i32 { 0 }
+    unsafe extern "C" fn print(_: *mut Context, _: *const u8, _: usize) -> i32 { 0 }
+
+    fn manager(size: usize) -> ApplicationManager {
+        let ram = Box::leak(vec![0u8; size].into_boxed_slice());
+        let table = Box::leak(Box::new(Table { draw_pixel, print }));
+        ApplicationManager::new(Ram::new(ram), table)
+    }
+
+    /// An application `len` bytes long, its code filled with `fill`
+    fn application(len: usize, fill: u8) -> std::vec::Vec<u8> {
+        let entry = HEADER_LEN as u32 | 1;
+        let mut app = Header::new("Test", entry, entry, entry, len as u32).to_bytes().to_vec();
+        app.resize(len, fill);
+        app
+    }
+
+    fn write_chunk(am: &mut ApplicationManager, offset: usize, data: &[u8], checksum: u32) -> Result<(), Error> {
+        am.begin_chunk(offset)?;
+        for &byte in data {
+            am.write_ram_byte(byte)?;
+        }
+        am.end_chunk(checksum)
+    }
+
     #[test]
     fn checksum_parsing_works() {
         assert_eq!(ApplicationManager::digest_from_bytes(&[35, 98, 167, 98]), 0x2362A762);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn chunked_upload_resumes() {
+        let app = application(100, 7);
+        let mut am = manager(128);
+        assert_eq!(am.begin_upload(app.len(), checksum_ieee(&app)), Ok(0));
+        write_chunk(&mut am, 0, &app[..40], checksum_ieee(&app[..40])).unwrap();
+
+        // the link drops part way through the next chunk
+        am.begin_chunk(40).unwrap();
+        am.write_ram_byte(app[40]).unwrap();
+
+        assert_eq!(am.begin_upload(app.len(), checksum_ieee(&app)), Ok(40));
+        assert_eq!(am.progress(), Some(Progress { received: 40, total: 100 }));
+        write_chunk(&mut am, 40, &app[40..], checksum_ieee(&app[40..])).unwrap();
+        assert_eq!(am.progress(), None);
+        assert!(am.status().is_loaded);
+        assert_eq!(am.program(), &app[..]);
+        assert_eq!(am.header().unwrap().name(), "Test");
+        assert_eq!(am.status().ram_used, 100);
+    }
+
+    #[test]
+    fn chunked_upload_rejects_bad_chunks() {
+        let app = application(96, 3);
+        let mut am = manager(128);
+        am.begin_upload(app.len(), checksum_ieee(&app)).unwrap();
+        assert_eq!(write_chunk(&mut am, 0, &app[..48], 0xDEADBEEF), Err(Error::ChecksumFailed));
+        assert_eq!(am.progress().unwrap().received, 0);
+        assert_eq!(write_chunk(&mut am, 48, &app[48..], checksum_ieee(&app[48..])), Err(Error::OutOfOrder));
+        assert_eq!(am.begin_upload(256, 0), Err(Error::NoMemory));
+    }
+
+    #[test]
+    fn resent_chunk_is_not_written() {
+        let app = application(96, 5);
+        let mut am = manager(128);
+        am.begin_upload(app.len(), checksum_ieee(&app)).unwrap();
+        write_chunk(&mut am, 0, &app[..80], checksum_ieee(&app[..80])).unwrap();
+
+        // a corrupt resend of an accepted chunk leaves it untouched
+        assert_eq!(write_chunk(&mut am, 0, &[0xFF; 80], 0xDEADBEEF), Ok(()));
+        assert_eq!(am.progress().unwrap().received, 80);
+        write_chunk(&mut am, 80, &app[80..], checksum_ieee(&app[80..])).unwrap();
+        assert!(am.status().is_loaded);
+        assert_eq!(am.program(), &app[..]);
+    }
+
+    #[test]
+    fn overlapping_chunk_writes_the_rest() {
+        let app = application(96, 6);
+        let mut am = manager(128);
+        am.begin_upload(app.len(), checksum_ieee(&app)).unwrap();
+        write_chunk(&mut am, 0, &app[..40], checksum_ieee(&app[..40])).unwrap();
+
+        // the ACK was lost, so the sender resends from an earlier offset
+        write_chunk(&mut am, 32, &app[32..64], checksum_ieee(&app[32..64])).unwrap();
+        assert_eq!(am.progress().unwrap().received, 64);
+        let mut corrupt = app[48..].to_vec();
+        corrupt[0] = 0xFF;
+        assert_eq!(write_chunk(&mut am, 48, &corrupt, checksum_ieee(&app[48..])), Err(Error::ChecksumFailed));
+        assert_eq!(am.progress().unwrap().received, 64);
+        write_chunk(&mut am, 48, &app[48..], checksum_ieee(&app[48..])).unwrap();
+        assert!(am.status().is_loaded);
+        assert_eq!(am.program(), &app[..]);
+    }
+
+    #[test]
+    fn failed_verify_keeps_the_upload() {
+        let app = application(96, 4);
+        let mut am = manager(128);
+        am.begin_upload(app.len(), checksum_ieee(&app)).unwrap();
+        write_chunk(&mut am, 0, &app[..64], checksum_ieee(&app[..64])).unwrap();
+
+        // the final chunk passes its own checksum but not the application's
+        let tail = [0xFF; 32];
+        assert_eq!(write_chunk(&mut am, 64, &tail, checksum_ieee(&tail)), Err(Error::ChecksumFailed));
+        assert!(!am.status().is_loaded);
+        assert_eq!(am.progress(), Some(Progress { received: 64, total: 96 }));
+        write_chunk(&mut am, 64, &app[64..], checksum_ieee(&app[64..])).unwrap();
+        assert!(am.status().is_loaded);
+        assert_eq!(am.program(), &app[..]);
+    }
+
+    #[test]
+    fn incompatible_application_rejected() {
+        let mut app = application(100, 7);
+        app[4..6].copy_from_slice(&(ABI_VERSION + 1).to_le_bytes());
+        let mut am = manager(128);
+        am.begin_upload(app.len(), checksum_ieee(&app)).unwrap();
+        assert_eq!(write_chunk(&mut am, 0, &app, checksum_ieee(&app)), Err(Error::IncompatibleAbi));
+        assert!(!am.status().is_loaded);
+        assert_eq!(am.execute(), Err(Error::NoApplication));
+    }
+}
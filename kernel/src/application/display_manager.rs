@@ -10,9 +10,13 @@ use crate::{application::{
         uop::UopState,
         mwatch::MWState,
         notifications::NotificationState,
+        alarms::AlarmState,
+        alert::AlertState,
+        stopwatch::StopwatchState,
     },
-    states::prelude::*
-}, system::{input::InputEvent, System, Host, Display}};
+    states::prelude::*,
+    toast::Toast,
+}, system::{input::InputEvent, System, Host, Display, Uptime}};
 
 use super::FrameBuffer;
 
@@ -26,7 +30,16 @@ pub enum Signal {
     Home
 }
 
-const MAX_STATES: i8 = 6;
+/// The index of each state, in the order they are cycled through
+const CLOCK: i8 = 0;
+const APP: i8 = 1;
+const NOTIFICATIONS: i8 = 2;
+const MWATCH: i8 = 3;
+const UOP: i8 = 4;
+const INFO: i8 = 5;
+const ALARMS: i8 = 6;
+const STOPWATCH: i8 = 7;
+const MAX_STATES: i8 = 8;
 
 /// The display manager
 pub struct DisplayManager 
@@ -38,6 +51,12 @@ pub struct DisplayManager
     uop_state: UopState,
     mwatch_state: MWState,
     notification_state: NotificationState,
+    alarm_state: AlarmState,
+    stopwatch_state: StopwatchState,
+    /// Shown full screen, in place of the current state, whilst an alarm or timer is ringing
+    alert_state: AlertState,
+    /// Shown over the current state when a high priority notification arrives
+    toast: Option<Toast>,
 }
 
 impl Default for DisplayManager {
@@ -47,11 +66,15 @@ impl Default for DisplayManager {
         Self {
             state_idx: 0,
             clock_state: ClockState::default(),
-            info_state: InfoState::default(),
+            info_state: InfoState,
             app_state: AppState::default(),
             uop_state: UopState::default(),
             mwatch_state: MWState::default(),
             notification_state: NotificationState::default(),
+            alarm_state: AlarmState::default(),
+            stopwatch_state: StopwatchState::default(),
+            alert_state: AlertState,
+            toast: None,
         }
     }
 }
@@ -62,54 +85,102 @@ impl DisplayManager
     /// Services the current application
     pub fn process<H: Host>(&mut self, system: &mut System<H>, display: &mut H::Display) {
         let display = &mut display.framebuffer();
+        if system.alarms.ringing().is_some() {
+            self.alert_state.render(system, display);
+            return;
+        }
+        if let Some(id) = system.nm.take_alert() {
+            // make sure the alert is seen, even if the display has been dimmed
+            system.wake();
+            self.toast = Some(Toast::new(id, system.uptime.millis()));
+        }
         let signal = match self.state_idx {
-            0 => {
+            CLOCK => {
                 DisplayManager::static_state_render(&mut self.clock_state, system, display)
             },
-            1 => {
+            APP => {
                 DisplayManager::scoped_state_render(&mut self.app_state, system, display)
             },
-            2 => {
+            NOTIFICATIONS => {
                 DisplayManager::scoped_state_render(&mut self.notification_state, system, display)
             },
-            3 => {
+            MWATCH => {
                 DisplayManager::static_state_render(&mut self.mwatch_state, system, display)
             },
-            4 => {
+            UOP => {
                 DisplayManager::static_state_render(&mut self.uop_state, system, display)
             },
-            5 => {
+            INFO => {
                 DisplayManager::static_state_render(&mut self.info_state, system, display)
             },
+            ALARMS => {
+                DisplayManager::scoped_state_render(&mut self.alarm_state, system, display)
+            },
+            STOPWATCH => {
+                DisplayManager::static_state_render(&mut self.stopwatch_state, system, display)
+            },
             _ => panic!("Unhandled state")
         };
 
+        if let Some(toast) = self.toast.as_mut() {
+            if !toast.render(system, display) {
+                self.toast = None;
+            }
+        }
+
         if let Some(signal) = signal {
             self.handle_exit(signal);
         } 
     }
 
     /// Services input to the current application
+    ///
+    /// Whilst an alarm or timer is ringing, input goes to the alert. Whilst a toast is shown, input dismisses it
+    /// instead. A middle press opens the notification. Otherwise a long middle press returns to the clock.
     pub fn service_input(&mut self, system: &mut System<impl Host>, input: InputEvent) {
+        system.wake();
+        if system.alarms.ringing().is_some() {
+            self.alert_state.input(system, input);
+            return;
+        }
+        if let Some(toast) = self.toast.take() {
+            if input == InputEvent::Middle {
+                self.leave(system);
+                self.state_idx = NOTIFICATIONS;
+                self.notification_state.open(system, toast.id());
+            }
+            return;
+        }
+        if input == InputEvent::LongMiddle {
+            self.leave(system);
+            self.handle_exit(Signal::Home);
+            return;
+        }
         let signal = match self.state_idx {
-            0 => {
+            CLOCK => {
                 DisplayManager::static_state_input(&mut self.clock_state, system, input)
             },
-            1 => {
+            APP => {
                 DisplayManager::scoped_state_input(&mut self.app_state, system, input)
             }
-            2 => {
+            NOTIFICATIONS => {
                 DisplayManager::scoped_state_input(&mut self.notification_state, system, input)
             },
-            3  => {
+            MWATCH => {
                 DisplayManager::static_state_input(&mut self.mwatch_state, system, input)
             },
-            4  => {
+            UOP => {
                 DisplayManager::static_state_input(&mut self.uop_state, system, input)
             },
-            5 => {
+            INFO => {
                 DisplayManager::static_state_input(&mut self.info_state, system, input)
             },
+            ALARMS => {
+                DisplayManager::scoped_state_input(&mut self.alarm_state, system, input)
+            },
+            STOPWATCH => {
+                DisplayManager::static_state_input(&mut self.stopwatch_state, system, input)
+            },
             _ => panic!("Unhandled state")
         };
 
@@ -123,7 +194,17 @@ impl DisplayManager
         match code {
             Signal::Next => self.next(),
             Signal::Previous => self.prev(),
-            Signal::Home => self.state_idx = 0,
+            Signal::Home => self.state_idx = CLOCK,
+        }
+    }
+
+    /// Leave the current state as a multi press would, pausing a running app or stopping a running scoped state
+    fn leave(&mut self, system: &mut System<impl Host>) {
+        match self.state_idx {
+            APP if self.app_state.is_running(system) => system.am.pause(),
+            NOTIFICATIONS => self.notification_state.stop(system),
+            ALARMS => self.alarm_state.stop(system),
+            _ => {}
         }
     }
 
@@ -0,0 +1,42 @@
+//! Font
+//!
+//! The ISO 8859-1 (latin-1) 6x12 font, so accented characters can be displayed. Anything outside of latin-1,
+//! i.e emoji, is drawn with a fallback glyph instead.
+
+use embedded_graphics::mono_font::{iso_8859_1, mapping::GlyphMapping, MonoFont};
+
+/// The glyph drawn for characters the font doesn't contain (¤)
+pub const FALLBACK: char = '\u{a4}';
+
+/// [`iso_8859_1::FONT_6X12`] with [`FALLBACK`] as the replacement glyph
+pub const FONT_6X12: MonoFont<'static> = MonoFont {
+    glyph_mapping: &Latin1,
+    ..iso_8859_1::FONT_6X12
+};
+
+/// The glyph layout of the latin-1 font images, printable ascii followed by `0xA0..=0xFF`
+struct Latin1;
+
+impl GlyphMapping for Latin1 {
+    fn index(&self, c: char) -> usize {
+        match c {
+            ' '..='~' => c as usize - ' ' as usize,
+            '\u{a0}'..='\u{ff}' => 96 + c as usize - 0xA0,
+            _ => self.index(FALLBACK),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn font_glyph_mapping() {
+        assert_eq!(Latin1.index(' '), 0);
+        assert_eq!(Latin1.index('~'), 94);
+        assert_eq!(Latin1.index('\u{a0}'), 96);
+        assert_eq!(Latin1.index('é'), 96 + 0xE9 - 0xA0);
+        assert_eq!(Latin1.index('😀'), Latin1.index(FALLBACK));
+    }
+}
@@ -0,0 +1,196 @@
+//! Application header
+//!
+//! Every application binary begins with a header describing it, so the kernel can check the binary was built
+//! for it before jumping into it. All fields are little endian.
+//!
+//! | Offset | Size | Field                                                  |
+//! |--------|------|--------------------------------------------------------|
+//! | 0      | 4    | Magic, `MWAP`                                          |
+//! | 4      | 2    | ABI version, must equal [`ABI_VERSION`]                |
+//! | 6      | 2    | Reserved, must be zero                                 |
+//! | 8      | 4    | Offset of the setup function                           |
+//! | 12     | 4    | Offset of the service function                         |
+//! | 16     | 4    | Offset of the input function                           |
+//! | 20     | 4    | RAM required by the application, including the binary  |
+//! | 24     | 16   | Name, UTF-8 padded with zeros                          |
+//! | 40     | 32   | 16x16 icon, one bit per pixel, rows top to bottom      |
+//!
+//! Entry offsets are relative to the start of the binary and keep the thumb bit of the function address.
+
+use core::str;
+
+use super::application_manager::Error;
+
+/// Identifies an application binary
+pub const MAGIC: [u8; 4] = *b"MWAP";
+/// The version of the application interface, bumped whenever [`Context`](super::Context) or
+/// [`Table`](super::Table) change
+pub const ABI_VERSION: u16 = 1;
+/// The length of the header in bytes
+pub const HEADER_LEN: usize = 72;
+/// The maximum length of an application name in bytes
+pub const NAME_LEN: usize = 16;
+/// The width and height of an application icon
+pub const ICON_SIZE: usize = 16;
+const ICON_LEN: usize = ICON_SIZE * ICON_SIZE / 8;
+
+const NAME_OFFSET: usize = 24;
+const ICON_OFFSET: usize = NAME_OFFSET + NAME_LEN;
+
+/// The header at the start of every application binary
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Header {
+    pub abi_version: u16,
+    /// Offset of the setup function
+    pub setup: u32,
+    /// Offset of the service function
+    pub service: u32,
+    /// Offset of the input function
+    pub input: u32,
+    /// RAM required by the application, including the binary
+    pub ram: u32,
+    name: [u8; NAME_LEN],
+    pub icon: [u8; ICON_LEN],
+}
+
+impl Header {
+    /// Create a header for the current ABI, `name` is truncated to [`NAME_LEN`] bytes
+    pub fn new(name: &str, setup: u32, service: u32, input: u32, ram: u32) -> Self {
+        let mut end = name.len().min(NAME_LEN);
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        let mut buffer = [0u8; NAME_LEN];
+        buffer[..end].copy_from_slice(&name.as_bytes()[..end]);
+        Self {
+            abi_version: ABI_VERSION,
+            setup,
+            service,
+            input,
+            ram,
+            name: buffer,
+            icon: [0u8; ICON_LEN],
+        }
+    }
+
+    /// Parse and validate the header at the start of `program`
+    ///
+    /// The entry offsets must lie within the program, and the application must fit in `capacity` bytes of RAM.
+    pub fn parse(program: &[u8], capacity: usize) -> Result<Self, Error> {
+        if program.len() < HEADER_LEN || program[..4] != MAGIC {
+            return Err(Error::InvalidHeader);
+        }
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes([program[offset], program[offset + 1], program[offset + 2], program[offset + 3]])
+        };
+        let abi_version = u16::from_le_bytes([program[4], program[5]]);
+        if abi_version != ABI_VERSION {
+            error!("Application ABI version {} does not match the kernel's {}", abi_version, ABI_VERSION);
+            return Err(Error::IncompatibleAbi);
+        }
+        let mut name = [0u8; NAME_LEN];
+        name.copy_from_slice(&program[NAME_OFFSET..ICON_OFFSET]);
+        let mut icon = [0u8; ICON_LEN];
+        icon.copy_from_slice(&program[ICON_OFFSET..HEADER_LEN]);
+        let header = Self {
+            abi_version,
+            setup: u32_at(8),
+            service: u32_at(12),
+            input: u32_at(16),
+            ram: u32_at(20),
+            name,
+            icon,
+        };
+
+        // the thumb bit aside, entry points must be within the code following the header
+        let entry = |offset: u32| (HEADER_LEN..program.len()).contains(&(offset as usize & !1));
+        if program[6..8] != [0, 0] || !entry(header.setup) || str::from_utf8(header.name_bytes()).is_err() {
+            return Err(Error::InvalidHeader);
+        }
+        if !entry(header.service) {
+            return Err(Error::InvalidServiceFn);
+        }
+        if !entry(header.input) {
+            return Err(Error::InvalidInputFn);
+        }
+        if (header.ram as usize) < program.len() || header.ram as usize > capacity {
+            return Err(Error::NoMemory);
+        }
+        Ok(header)
+    }
+
+    /// The name of the application
+    pub fn name(&self) -> &str {
+        str::from_utf8(self.name_bytes()).unwrap_or_default()
+    }
+
+    /// Whether the icon pixel at `x`, `y` is set
+    pub fn icon_pixel(&self, x: usize, y: usize) -> bool {
+        let bit = y * ICON_SIZE + x;
+        self.icon[bit / 8] & (0x80 >> (bit % 8)) != 0
+    }
+
+    /// Serialize the header, as placed at the start of a binary
+    pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0u8; HEADER_LEN];
+        bytes[..4].copy_from_slice(&MAGIC);
+        bytes[4..6].copy_from_slice(&self.abi_version.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.setup.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.service.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.input.to_le_bytes());
+        bytes[20..24].copy_from_slice(&self.ram.to_le_bytes());
+        bytes[NAME_OFFSET..ICON_OFFSET].copy_from_slice(&self.name);
+        bytes[ICON_OFFSET..].copy_from_slice(&self.icon);
+        bytes
+    }
+
+    /// The name without its padding
+    fn name_bytes(&self) -> &[u8] {
+        let len = self.name.iter().position(|&b| b == 0).unwrap_or(NAME_LEN);
+        &self.name[..len]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::vec::Vec;
+
+    fn program(header: &Header, len: usize) -> Vec<u8> {
+        let mut program = header.to_bytes().to_vec();
+        program.resize(len, 0);
+        program
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let mut header = Header::new("Snake", 73, 81, 97, 512);
+        header.icon[0] = 0x80;
+        let parsed = Header::parse(&program(&header, 128), 1024).unwrap();
+        assert_eq!(parsed, header);
+        assert_eq!(parsed.name(), "Snake");
+        assert!(parsed.icon_pixel(0, 0));
+        assert!(!parsed.icon_pixel(1, 0));
+        assert_eq!(Header::new("A very long application name", 0, 0, 0, 0).name(), "A very long appl");
+    }
+
+    #[test]
+    fn header_validation() {
+        let header = Header::new("App", 73, 81, 97, 512);
+        let mut bytes = program(&header, 128);
+        assert_eq!(Header::parse(&bytes[..HEADER_LEN - 1], 1024), Err(Error::InvalidHeader));
+        assert_eq!(Header::parse(&bytes, 256), Err(Error::NoMemory));
+        bytes[7] = 1;
+        assert_eq!(Header::parse(&bytes, 1024), Err(Error::InvalidHeader));
+        bytes[7] = 0;
+        bytes[4] = ABI_VERSION as u8 + 1;
+        assert_eq!(Header::parse(&bytes, 1024), Err(Error::IncompatibleAbi));
+        bytes[0] = b'X';
+        assert_eq!(Header::parse(&bytes, 1024), Err(Error::InvalidHeader));
+
+        let outside = Header { service: 128, ..header };
+        assert_eq!(Header::parse(&program(&outside, 128), 1024), Err(Error::InvalidServiceFn));
+        let inside_header = Header { input: 8, ..header };
+        assert_eq!(Header::parse(&program(&inside_header, 128), 1024), Err(Error::InvalidInputFn));
+    }
+}
@@ -0,0 +1,176 @@
+//! Layout
+//!
+//! Word wrapping of text for monospaced fonts. Lines are broken at spaces where possible, words longer than a
+//! line are broken at a character boundary, and a `\n` always starts a new line.
+
+use embedded_graphics::mono_font::{MonoFont, MonoTextStyle};
+use embedded_graphics::prelude::*;
+use embedded_graphics::text::{Baseline, Text};
+
+/// Text wrapped to fit a width in pixels
+#[derive(Copy, Clone)]
+pub struct TextLayout<'a> {
+    text: &'a str,
+    font: &'a MonoFont<'a>,
+    columns: usize,
+}
+
+impl<'a> TextLayout<'a> {
+    /// Wrap `text` to fit within `width` pixels when drawn with `font`
+    pub fn new(text: &'a str, font: &'a MonoFont<'a>, width: u32) -> Self {
+        let advance = font.character_size.width + font.character_spacing;
+        Self {
+            text,
+            font,
+            columns: (width / advance.max(1)) as usize,
+        }
+    }
+
+    /// The maximum number of characters on a line
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// The wrapped lines
+    pub fn lines(&self) -> Lines<'a> {
+        Lines::new(self.text, self.columns)
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.lines().count()
+    }
+
+    pub fn line_height(&self) -> u32 {
+        self.font.character_size.height
+    }
+
+    /// How many whole lines fit within `height` pixels
+    pub fn visible_lines(&self, height: u32) -> usize {
+        (height / self.line_height().max(1)) as usize
+    }
+
+    /// The number of lines the text can be scrolled by, until its last line is at the bottom of `height` pixels
+    pub fn max_scroll(&self, height: u32) -> usize {
+        self.line_count().saturating_sub(self.visible_lines(height))
+    }
+
+    /// Draw the text with its top left at `origin`, skipping the first `scroll` lines
+    pub fn draw<D>(&self, target: &mut D, style: MonoTextStyle<'_, D::Color>, origin: Point, scroll: usize) -> Result<(), D::Error>
+    where
+        D: DrawTarget,
+    {
+        let height = self.line_height() as i32;
+        let bottom = target.bounding_box().bottom_right().map(|p| p.y).unwrap_or(0);
+        for (idx, line) in self.lines().skip(scroll).enumerate() {
+            let position = origin + Point::new(0, idx as i32 * height);
+            if position.y > bottom {
+                break;
+            }
+            Text::with_baseline(line, position, style, Baseline::Top).draw(target)?;
+        }
+        Ok(())
+    }
+}
+
+/// The first `columns` characters of `text`
+pub fn truncate(text: &str, columns: usize) -> &str {
+    match text.char_indices().nth(columns) {
+        Some((idx, _)) => &text[..idx],
+        None => text,
+    }
+}
+
+/// Iterator over wrapped lines, see [`TextLayout::lines`]
+#[derive(Clone)]
+pub struct Lines<'a> {
+    rest: &'a str,
+    columns: usize,
+}
+
+impl<'a> Lines<'a> {
+    /// Wrap `text` to lines of at most `columns` characters
+    pub fn new(text: &'a str, columns: usize) -> Self {
+        Self {
+            rest: text,
+            // a line must hold at least one character to make progress
+            columns: columns.max(1),
+        }
+    }
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let rest = self.rest;
+        let mut last_space = None;
+        for (count, (idx, c)) in rest.char_indices().enumerate() {
+            if c == '\n' {
+                self.rest = &rest[idx + 1..];
+                return Some(rest[..idx].trim_end_matches(' '));
+            }
+            if count == self.columns {
+                // the line is full, break at the last space or mid word if there isn't one
+                let end = match (c, last_space) {
+                    (' ', _) => idx,
+                    (_, Some(space)) => space,
+                    (_, None) => idx,
+                };
+                self.rest = rest[end..].trim_start_matches(' ');
+                return Some(rest[..end].trim_end_matches(' '));
+            }
+            if c == ' ' {
+                last_space = Some(idx);
+            }
+        }
+        self.rest = "";
+        Some(rest.trim_end_matches(' '))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embedded_graphics::mono_font::ascii::FONT_6X12;
+
+    fn wrap(text: &str, columns: usize) -> std::vec::Vec<&str> {
+        Lines::new(text, columns).collect()
+    }
+
+    #[test]
+    fn layout_wraps_on_words() {
+        assert_eq!(wrap("The quick brown fox jumps", 10), ["The quick", "brown fox", "jumps"]);
+        assert_eq!(wrap("exactly ten", 7), ["exactly", "ten"]);
+        assert_eq!(wrap("a  b", 1), ["a", "b"]);
+    }
+
+    #[test]
+    fn layout_breaks_long_words() {
+        assert_eq!(wrap("ééééé😀 a", 2), ["éé", "éé", "é😀", "a"]);
+    }
+
+    #[test]
+    fn layout_newlines() {
+        assert_eq!(wrap("one\n\ntwo \nthree", 10), ["one", "", "two", "three"]);
+    }
+
+    #[test]
+    fn layout_truncate() {
+        assert_eq!(truncate("héllo", 2), "hé");
+        assert_eq!(truncate("hi", 5), "hi");
+    }
+
+    #[test]
+    fn layout_scroll() {
+        // 128px wide is 21 columns of 6px characters
+        let text = "word ".repeat(50);
+        let layout = TextLayout::new(&text, &FONT_6X12, 128);
+        assert_eq!((layout.columns(), layout.line_count()), (21, 13));
+        assert_eq!(layout.visible_lines(128), 10);
+        assert_eq!(layout.max_scroll(128), 3);
+        assert_eq!(TextLayout::new("short", &FONT_6X12, 128).max_scroll(128), 0);
+    }
+}
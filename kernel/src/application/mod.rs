@@ -5,7 +5,11 @@ use crate::system::input::InputEvent;
 
 pub mod application_manager;
 pub mod display_manager;
+pub mod font;
+pub mod header;
+pub mod layout;
 pub mod states;
+pub mod toast;
 
 /// The FFI function signature for initialising an application.
 pub type SetupFn = unsafe extern "C" fn(*mut Table) -> i32;
@@ -18,6 +22,8 @@ pub type InputFn = unsafe extern "C" fn(*mut Context, InputEvent) -> i32;
 /// The context passed to the application interface.
 pub struct Context {
     pub framebuffer: *mut FrameBuffer,
+    /// Milliseconds of uptime, for pacing animations
+    pub millis: u64,
 }
 
 #[repr(C)]
@@ -35,6 +41,10 @@ pub struct FrameBuffer {
 
 impl FrameBuffer {
     /// Create a frame buffer from a pointer
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for writes of `len` bytes for as long as the [`FrameBuffer`] is alive.
     pub unsafe fn new(ptr: *mut u8, len: usize, width: u8, height: u8) -> Self {
         Self {
             ptr,
@@ -65,7 +75,7 @@ impl embedded_graphics::draw_target::DrawTarget for FrameBuffer {
                 let y = pos.y;
                 let color: u16 = RawU16::from(color).into_inner();
                 let slice = unsafe { core::slice::from_raw_parts_mut(self.ptr, self.len) };
-                slice[((x + (y * self.width as i32)) as usize * 2)] = (color >> 8) as u8;
+                slice[(x + (y * self.width as i32)) as usize * 2] = (color >> 8) as u8;
                 slice[(((x + (y * self.width as i32)) as usize) * 2) + 1] = color as u8;
             });
 
@@ -0,0 +1,339 @@
+//! Alarms state
+//!
+//! Lists the alarms, followed by the countdown timer. Left and right select a row, a middle press edits it and a
+//! dual press turns the selected alarm on or off, or cancels the running timers.
+//!
+//! Whilst editing, holding left or right skips a further ten hours or minutes.
+
+use crate::application::FrameBuffer;
+use crate::application::font::FONT_6X12;
+use crate::application::states::clock::seven_segment::SevenSegments;
+use crate::application::states::prelude::*;
+use crate::system::alarm::{Alarm, Days, MAX_ALARMS};
+use crate::system::input::InputEvent;
+use crate::system::{Clock, Host, System};
+
+use core::fmt::Write;
+use heapless::String;
+use time::{Duration, Time};
+
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::raw::RawU16;
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
+use embedded_graphics::text::{Alignment, Baseline, Text};
+
+const CHAR_WIDTH: i32 = 6;
+const CHAR_HEIGHT: i32 = 12;
+const COLOUR: u16 = 0x02D4;
+/// The menu lists each alarm, then the timer
+const ROWS: usize = MAX_ALARMS + 1;
+/// How far a long press changes a number
+const LONG_STEP: i8 = 10;
+/// The repeats cycled through when editing an alarm
+const REPEATS: [Days; 4] = [Days::NONE, Days::EVERY_DAY, Days::WEEKDAYS, Days::WEEKENDS];
+/// Where the digits are drawn when editing
+const DIGITS_Y: i32 = 30;
+const DIGIT_WIDTH: i32 = 16;
+const DIGIT_SPACE: i32 = 5;
+const COLON_WIDTH: i32 = 4 + DIGIT_SPACE;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Field {
+    Hour,
+    Minute,
+    Repeat,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+/// The internal state of the alarms application
+enum InternalState {
+    Menu,
+    /// Editing the alarm at this index
+    Alarm(usize, Field),
+    Timer,
+}
+
+pub struct AlarmState {
+    is_running: bool,
+    state: InternalState,
+    selected: usize,
+    /// The alarm being edited: hour, minute and repeat
+    draft: (u8, u8, Days),
+    /// The length of the next timer
+    timer_minutes: u8,
+}
+
+impl Default for AlarmState {
+    fn default() -> Self {
+        Self {
+            is_running: false,
+            state: InternalState::Menu,
+            selected: 0,
+            draft: (7, 0, Days::NONE),
+            timer_minutes: 5,
+        }
+    }
+}
+
+impl State for AlarmState {
+    fn render(&mut self, system: &mut System<impl Host>, display: &mut FrameBuffer) -> Option<Signal> {
+        let size = display.bounding_box().size;
+        let style = MonoTextStyle::new(&FONT_6X12, RawU16::new(COLOUR).into());
+        let mut buffer: String<24> = String::new();
+        match self.state {
+            InternalState::Menu => {
+                Text::with_baseline(">", Point::new(0, self.selected as i32 * CHAR_HEIGHT), style, Baseline::Top)
+                    .draw(display).ok();
+                for index in 0..MAX_ALARMS {
+                    let y = index as i32 * CHAR_HEIGHT;
+                    buffer.clear();
+                    match system.alarms.alarm(index) {
+                        Some(alarm) => {
+                            let time = alarm.time();
+                            write!(buffer, "{:02}:{:02} {}", time.hour(), time.minute(), repeat(alarm.days)).ok();
+                            let state = if alarm.enabled { "on" } else { "off" };
+                            let position = Point::new(size.width as i32, y + FONT_6X12.baseline as i32);
+                            Text::with_alignment(state, position, style, Alignment::Right).draw(display).ok();
+                        }
+                        None => {
+                            write!(buffer, "--:--").ok();
+                        }
+                    }
+                    Text::with_baseline(&buffer, Point::new(CHAR_WIDTH * 2, y), style, Baseline::Top)
+                        .draw(display).ok();
+                }
+
+                let y = MAX_ALARMS as i32 * CHAR_HEIGHT;
+                Text::with_baseline("Timer", Point::new(CHAR_WIDTH * 2, y), style, Baseline::Top)
+                    .draw(display).ok();
+                buffer.clear();
+                let now = system.clock.get_date_time();
+                match system.alarms.timers().map(|timer| timer.remaining(now)).min() {
+                    Some(remaining) => countdown(&mut buffer, remaining),
+                    None => {
+                        write!(buffer, "{}m", self.timer_minutes).ok();
+                    }
+                }
+                let position = Point::new(size.width as i32, y + FONT_6X12.baseline as i32);
+                Text::with_alignment(&buffer, position, style, Alignment::Right).draw(display).ok();
+            }
+            InternalState::Alarm(index, field) => {
+                let (hour, minute, days) = self.draft;
+                write!(buffer, "Alarm {}", index + 1).ok();
+                Text::with_alignment(&buffer, Point::new(size.width as i32 / 2, CHAR_HEIGHT), style, Alignment::Center)
+                    .draw(display).ok();
+                let x = (size.width as i32 - 4 * DIGIT_WIDTH - 3 * DIGIT_SPACE - COLON_WIDTH) / 2;
+                SevenSegments::new(display, x, DIGITS_Y, COLOUR).pair(hour, minute);
+
+                let repeat = repeat(days);
+                let y = DIGITS_Y + 60;
+                Text::with_alignment(&repeat, Point::new(size.width as i32 / 2, y), style, Alignment::Center)
+                    .draw(display).ok();
+
+                // underline the field being edited
+                let pair = 2 * DIGIT_WIDTH + DIGIT_SPACE;
+                let (left, width, top) = match field {
+                    Field::Hour => (x, pair, DIGITS_Y + 38),
+                    Field::Minute => (x + pair + DIGIT_SPACE + COLON_WIDTH, pair, DIGITS_Y + 38),
+                    Field::Repeat => {
+                        let width = repeat.len() as i32 * CHAR_WIDTH;
+                        ((size.width as i32 - width) / 2, width, y + 3)
+                    }
+                };
+                Rectangle::new(Point::new(left, top), Size::new(width as u32, 2))
+                    .into_styled(PrimitiveStyle::with_fill(Rgb565::from(RawU16::new(COLOUR))))
+                    .draw(display).ok();
+                buttons(display, if field == Field::Repeat { "Save" } else { "Next" });
+            }
+            InternalState::Timer => {
+                Text::with_alignment("Timer", Point::new(size.width as i32 / 2, CHAR_HEIGHT), style, Alignment::Center)
+                    .draw(display).ok();
+                let x = (size.width as i32 - 4 * DIGIT_WIDTH - 3 * DIGIT_SPACE - COLON_WIDTH) / 2;
+                SevenSegments::new(display, x, DIGITS_Y, COLOUR).pair(self.timer_minutes, 0);
+                buttons(display, "Start");
+            }
+        }
+        None
+    }
+
+    /// Handle input for the alarms
+    ///
+    /// Whilst editing, left and right change the selected field and a middle press moves on to the next, saving
+    /// the alarm or starting the timer after the last. A multi press discards the changes.
+    fn input(&mut self, system: &mut System<impl Host>, input: InputEvent) -> Option<Signal> {
+        match self.state {
+            InternalState::Menu => match input {
+                InputEvent::Left => self.selected = (self.selected + ROWS - 1) % ROWS,
+                InputEvent::Right => self.selected = (self.selected + 1) % ROWS,
+                InputEvent::Middle if self.selected < MAX_ALARMS => {
+                    self.draft = match system.alarms.alarm(self.selected) {
+                        Some(alarm) => (alarm.time().hour(), alarm.time().minute(), alarm.days),
+                        None => AlarmState::default().draft,
+                    };
+                    self.state = InternalState::Alarm(self.selected, Field::Hour);
+                }
+                InputEvent::Middle => self.state = InternalState::Timer,
+                InputEvent::Dual if self.selected < MAX_ALARMS => {
+                    if let Some(mut alarm) = system.alarms.alarm(self.selected) {
+                        alarm.enabled = !alarm.enabled;
+                        system.alarms.set_alarm(self.selected, Some(alarm)).ok();
+                    }
+                }
+                InputEvent::Dual => system.alarms.cancel_timers(),
+                InputEvent::Multi => {
+                    self.stop(system);
+                    return Some(Signal::Home);
+                }
+                _ => {}
+            },
+            InternalState::Alarm(index, field) => {
+                let (hour, minute, days) = &mut self.draft;
+                let step = match input {
+                    InputEvent::Left => -1,
+                    InputEvent::Right => 1,
+                    InputEvent::LongLeft if field != Field::Repeat => -LONG_STEP,
+                    InputEvent::LongRight if field != Field::Repeat => LONG_STEP,
+                    InputEvent::Middle => {
+                        self.state = match field {
+                            Field::Hour => InternalState::Alarm(index, Field::Minute),
+                            Field::Minute => InternalState::Alarm(index, Field::Repeat),
+                            Field::Repeat => {
+                                let time = Time::from_hms(*hour, *minute, 0).unwrap_or(Time::MIDNIGHT);
+                                system.alarms.set_alarm(index, Some(Alarm::new(time, *days))).ok();
+                                InternalState::Menu
+                            }
+                        };
+                        return None;
+                    }
+                    InputEvent::Multi => {
+                        self.state = InternalState::Menu;
+                        return None;
+                    }
+                    _ => return None,
+                };
+                match field {
+                    Field::Hour => *hour = wrap(*hour, step, 24),
+                    Field::Minute => *minute = wrap(*minute, step, 60),
+                    Field::Repeat => {
+                        // custom days, set by a syscall, start the cycle again
+                        let current = REPEATS.iter().position(|repeat| repeat == days);
+                        let next = match current {
+                            Some(current) => wrap(current as u8, step, REPEATS.len() as u8),
+                            None => 0,
+                        };
+                        *days = REPEATS[next as usize];
+                    }
+                }
+            }
+            InternalState::Timer => match input {
+                InputEvent::Left => self.timer_minutes = self.timer_minutes.saturating_sub(1).max(1),
+                InputEvent::Right => self.timer_minutes = (self.timer_minutes + 1).min(99),
+                InputEvent::LongLeft => self.timer_minutes = self.timer_minutes.saturating_sub(LONG_STEP as u8).max(1),
+                InputEvent::LongRight => self.timer_minutes = (self.timer_minutes + LONG_STEP as u8).min(99),
+                InputEvent::Middle => {
+                    let now = system.clock.get_date_time();
+                    if let Err(e) = system.alarms.start_timer(now, Duration::minutes(self.timer_minutes as i64)) {
+                        error!("Failed to start the timer: {:?}", e);
+                    }
+                    self.state = InternalState::Menu;
+                }
+                InputEvent::Multi => self.state = InternalState::Menu,
+                _ => {}
+            },
+        }
+        None
+    }
+}
+
+impl ScopedState for AlarmState {
+    /// Render a preview or Icon before launching the whole application
+    fn preview(&mut self, _system: &mut System<impl Host>, display: &mut FrameBuffer) -> Option<Signal> {
+        let size = display.bounding_box().size;
+        let style = MonoTextStyle::new(&FONT_6X12, RawU16::new(COLOUR).into());
+        Text::with_alignment(
+            "Alarms",
+            Point::new(size.width as i32 / 2, size.height as i32 / 2),
+            style,
+            Alignment::Center
+        )
+        .draw(display).ok();
+        None
+    }
+
+    /// Is the alarms app opened?
+    fn is_running(&self, _system: &mut System<impl Host>) -> bool {
+        self.is_running
+    }
+
+    /// Start
+    fn start(&mut self, _system: &mut System<impl Host>) {
+        self.is_running = true;
+    }
+
+    /// Stop
+    fn stop(&mut self, _system: &mut System<impl Host>) {
+        self.is_running = false;
+        self.state = InternalState::Menu;
+    }
+}
+
+/// Draw the labels of the editing buttons, `middle` above the middle button
+fn buttons(display: &mut FrameBuffer, middle: &str) {
+    let size = display.bounding_box().size;
+    let style = MonoTextStyle::new(&FONT_6X12, RawU16::new(COLOUR).into());
+    let y = size.height as i32 - 4;
+    Text::with_alignment("-", Point::new(0, y), style, Alignment::Left).draw(display).ok();
+    Text::with_alignment(middle, Point::new(size.width as i32 / 2, y), style, Alignment::Center)
+        .draw(display).ok();
+    Text::with_alignment("+", Point::new(size.width as i32 - 1, y), style, Alignment::Right)
+        .draw(display).ok();
+}
+
+/// Step `value` forwards or backwards, wrapping within `0..count`
+fn wrap(value: u8, step: i8, count: u8) -> u8 {
+    (value as i16 + step as i16).rem_euclid(count as i16) as u8
+}
+
+/// Describe the days an alarm repeats on, i.e "Weekdays" or "M-W-F--"
+fn repeat(days: Days) -> String<8> {
+    let mut buffer = String::new();
+    let name = match days {
+        Days::NONE => "Once",
+        Days::EVERY_DAY => "Daily",
+        Days::WEEKDAYS => "Weekdays",
+        Days::WEEKENDS => "Weekends",
+        days => {
+            for (bit, letter) in "MTWTFSS".chars().enumerate() {
+                buffer.push(if days.bits() & 1 << bit != 0 { letter } else { '-' }).ok();
+            }
+            return buffer;
+        }
+    };
+    buffer.push_str(name).ok();
+    buffer
+}
+
+/// The time left on a timer, i.e "4:59" or "1:00:00"
+fn countdown(buffer: &mut String<24>, remaining: Duration) {
+    let seconds = remaining.whole_seconds();
+    if seconds < 3600 {
+        write!(buffer, "{}:{:02}", seconds / 60, seconds % 60).ok();
+    } else {
+        write!(buffer, "{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60).ok();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn alarms_repeat_names() {
+        assert_eq!(repeat(Days::WEEKDAYS), "Weekdays");
+        assert_eq!(repeat(Days::from_bits(0b0010101).unwrap()), "M-W-F--");
+        assert_eq!(wrap(0, -1, 24), 23);
+        assert_eq!(wrap(59, 1, 60), 0);
+    }
+}
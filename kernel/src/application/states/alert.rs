@@ -0,0 +1,72 @@
+//! Alert state
+//!
+//! Shown full screen by the display manager whilst an alarm or timer is ringing. A middle press stops it,
+//! left or right snoozes it. The title flashes, timed by the [`Uptime`].
+
+use crate::application::FrameBuffer;
+use crate::application::font::FONT_6X12;
+use crate::application::states::clock::seven_segment::SevenSegments;
+use crate::application::states::prelude::*;
+use crate::system::alarm::Alert;
+use crate::system::input::InputEvent;
+use crate::system::{Clock, Host, System, Uptime};
+
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::raw::RawU16;
+use embedded_graphics::prelude::*;
+use embedded_graphics::text::{Alignment, Text};
+
+const COLOUR: u16 = 0x2C78;
+/// How long the title is shown, then hidden, for when flashing
+const FLASH_MILLIS: u64 = 500;
+
+#[derive(Default)]
+pub struct AlertState;
+
+impl State for AlertState {
+    fn render(&mut self, system: &mut System<impl Host>, display: &mut FrameBuffer) -> Option<Signal> {
+        let alert = system.alarms.ringing()?;
+        let size = display.bounding_box().size;
+        let style = MonoTextStyle::new(&FONT_6X12, RawU16::new(COLOUR).into());
+
+        let (title, left, right) = match alert {
+            Alert::Alarm(time) => ("ALARM", time.hour(), time.minute()),
+            Alert::Timer(duration) => {
+                let seconds = duration.whole_seconds();
+                // minutes and seconds, or hours and minutes for longer timers
+                let (left, right) = if seconds < 3600 {
+                    (seconds / 60, seconds % 60)
+                } else {
+                    (seconds / 3600, seconds / 60 % 60)
+                };
+                ("TIMER", left as u8, right as u8)
+            }
+        };
+        if system.uptime.millis() % (2 * FLASH_MILLIS) < FLASH_MILLIS {
+            Text::with_alignment(title, Point::new(size.width as i32 / 2, 24), style, Alignment::Center)
+                .draw(display).ok();
+        }
+        SevenSegments::new(display, 18, 40, COLOUR).pair(left, right);
+
+        // a label above each button
+        let y = size.height as i32 - 4;
+        Text::with_alignment("Zz", Point::new(0, y), style, Alignment::Left).draw(display).ok();
+        Text::with_alignment("Stop", Point::new(size.width as i32 / 2, y), style, Alignment::Center)
+            .draw(display).ok();
+        Text::with_alignment("Zz", Point::new(size.width as i32 - 1, y), style, Alignment::Right)
+            .draw(display).ok();
+        None
+    }
+
+    fn input(&mut self, system: &mut System<impl Host>, input: InputEvent) -> Option<Signal> {
+        match input {
+            InputEvent::Middle => system.alarms.dismiss(),
+            InputEvent::Left | InputEvent::Right => {
+                // keeps ringing if it can't be snoozed
+                system.alarms.snooze(system.clock.get_date_time()).ok();
+            }
+            _ => {}
+        }
+        None
+    }
+}
@@ -4,17 +4,23 @@
 //!  
 
 use crate::application::FrameBuffer;
+use crate::application::header::ICON_SIZE;
 use crate::application::states::prelude::*;
 use crate::system::Host;
 use crate::system::input::InputEvent;
-use crate::system::System;
+use crate::system::{System, Uptime};
 use core::fmt::Write;
 use embedded_graphics::pixelcolor::raw::RawU16;
 use embedded_graphics::{mono_font::{MonoTextStyle, ascii::FONT_6X10}, pixelcolor::Rgb565, text::{Alignment, Text}};
+use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
 use heapless::String;
 
 use embedded_graphics::prelude::*;
 
+const PROGRESS_BAR_MARGIN: i32 = 16;
+/// Each icon pixel is drawn as a square this many pixels wide
+const ICON_SCALE: i32 = 2;
+
 pub struct AppState {
     buffer: String<256>,
 }
@@ -29,7 +35,8 @@ impl Default for AppState {
 
 impl State for AppState {
     fn render(&mut self, system: &mut System<impl Host>, display: &mut FrameBuffer) -> Option<Signal> {
-        system.am.service(display).unwrap_or_else(|err| {
+        let now = system.uptime.millis();
+        system.am.service(display, now).unwrap_or_else(|err| {
             error!("Failed to render app {:?}", err);
         });
         None
@@ -42,7 +49,8 @@ impl State for AppState {
                 Some(Signal::Home) // signal to dm to go home
             }
             _ => {
-                system.am.service_input(input).unwrap_or_else(|err| {
+                let now = system.uptime.millis();
+                system.am.service_input(input, now).unwrap_or_else(|err| {
                     error!("Failed to service input for app {:?}", err);
                 });
                 None
@@ -55,17 +63,44 @@ impl ScopedState for AppState {
     /// Render a preview or Icon before launching the whole application
     fn preview(&mut self, system: &mut System<impl Host>, display: &mut FrameBuffer) -> Option<Signal> {
         self.buffer.clear();
-        let status = system.am.status();
-        if status.is_loaded {
-            write!(self.buffer, "Open loaded App").unwrap();
+        let progress = system.am.progress();
+        if let Some(progress) = progress {
+            write!(self.buffer, "Loading App {}%", progress.percent()).unwrap();
+        } else if let Some(header) = system.am.header() {
+            write!(self.buffer, "Open {}", header.name()).unwrap();
         } else {
             write!(self.buffer, "No App loaded!").unwrap();
         }
 
         let size = display.bounding_box().size;
-        let style = MonoTextStyle::new(&FONT_6X10, Rgb565::from(RawU16::from(0x02D4)));
+        let colour = Rgb565::from(RawU16::from(0x02D4));
+        let style = MonoTextStyle::new(&FONT_6X10, colour);
         Text::with_alignment(self.buffer.as_str(), Point::new(size.width as i32 / 2, size.height as i32 / 2), style, Alignment::Center).draw(display).ok();
 
+        if let (None, Some(header)) = (progress, system.am.header()) {
+            // icon centred above the text
+            let width = ICON_SIZE as i32 * ICON_SCALE;
+            let origin = Point::new((size.width as i32 - width) / 2, size.height as i32 / 2 - width - 12);
+            let pixel = PrimitiveStyle::with_fill(colour);
+            for y in 0..ICON_SIZE {
+                for x in (0..ICON_SIZE).filter(|&x| header.icon_pixel(x, y)) {
+                    let top_left = origin + Point::new(x as i32, y as i32) * ICON_SCALE;
+                    Rectangle::new(top_left, Size::new_equal(ICON_SCALE as u32)).into_styled(pixel).draw(display).ok();
+                }
+            }
+        }
+
+        if let Some(progress) = progress {
+            // progress bar just below the text
+            let bar = Rectangle::new(Point::new(PROGRESS_BAR_MARGIN, size.height as i32 / 2 + 8), Size::new(size.width - 2 * PROGRESS_BAR_MARGIN as u32, 8));
+            bar.into_styled(PrimitiveStyle::with_stroke(colour, 1)).draw(display).ok();
+            let filled = (bar.size.width * progress.percent() as u32) / 100;
+            Rectangle::new(bar.top_left, Size::new(filled, bar.size.height))
+                .into_styled(PrimitiveStyle::with_fill(colour))
+                .draw(display)
+                .ok();
+        }
+
         None
     }
 
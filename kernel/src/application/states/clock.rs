@@ -4,9 +4,7 @@
 
 use crate::application::FrameBuffer;
 use crate::application::states::prelude::*;
-use crate::system::Clock;
 use crate::system::Host;
-use crate::system::Statistics;
 use crate::system::System;
 
 use crate::system::bms::BatteryManagement;
@@ -19,8 +17,9 @@ use heapless::String;
 use embedded_graphics::prelude::*;
 use embedded_graphics::{
     mono_font::{ascii::FONT_6X12, MonoTextStyle},
-    text::Text,
+    text::{Alignment, Text},
 };
+use time::Weekday;
 
 use seven_segment::SevenSegments;
 
@@ -38,35 +37,27 @@ impl Default for ClockState {
 
 impl State for ClockState {
     fn render(&mut self, system: &mut System<impl Host>, display: &mut FrameBuffer) -> Option<Signal> {
-        let time = system.clock.get_time();
-        let date = system.clock.get_date();
+        let now = system.local_date_time();
+        let (time, date) = (now.time(), now.date());
         let soc = system.bms.soc();
         let bms_state = system.bms.state();
-        let mut clock_digits = SevenSegments::new(display, 18, 48, 0x2C78);
-        write!(self.buffer, "{:02}{:02}", time.hour(), time.minute()).unwrap();
-        for (idx, digit) in self.buffer.as_bytes().iter().enumerate() {
-            clock_digits.digit(digit - b'0');
-            if idx == (self.buffer.len() / 2) - 1 {
-                // put a colon between hours and mins
-                clock_digits.colon();
-            }
-        }
+        SevenSegments::new(display, 18, 48, 0x2C78).pair(time.hour(), time.minute());
 
-        self.buffer.clear(); // reset the buffer
-        if !system.stats.is_idle() {
+        if !system.is_idle() {
             let size = display.bounding_box().size;
             let style = MonoTextStyle::new(&FONT_6X12, RawU16::new(0x2C78).into());
 
             write!(
                 self.buffer,
-                "{:02}/{:02}/{:04}",
-                date.day(), date.month(), date.year()
+                "{} {:02}/{:02}/{:04}",
+                weekday(date.weekday()), date.day(), date.month() as u8, date.year()
             )
             .unwrap();
-            Text::new(
+            Text::with_alignment(
                 self.buffer.as_str(),
-                Point::new(30, size.height as i32 - 12),
+                Point::new(size.width as i32 / 2, size.height as i32 - 12),
                 style,
+                Alignment::Center,
             )
             .draw(display)
             .ok();
@@ -109,7 +100,20 @@ impl State for ClockState {
 
 impl StaticState for ClockState {}
 
-mod seven_segment {
+/// The abbreviated name of the day
+fn weekday(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Monday => "Mon",
+        Weekday::Tuesday => "Tue",
+        Weekday::Wednesday => "Wed",
+        Weekday::Thursday => "Thu",
+        Weekday::Friday => "Fri",
+        Weekday::Saturday => "Sat",
+        Weekday::Sunday => "Sun",
+    }
+}
+
+pub(crate) mod seven_segment {
     use embedded_graphics::{
         pixelcolor::{raw::RawU16, Rgb565},
         prelude::*,
@@ -208,6 +212,15 @@ mod seven_segment {
             self.digit_space();
         }
 
+        /// Draw two numbers, each as two digits, separated by a colon, i.e hours and minutes
+        pub fn pair(&mut self, left: u8, right: u8) {
+            self.digit(left / 10 % 10);
+            self.digit(left % 10);
+            self.colon();
+            self.digit(right / 10 % 10);
+            self.digit(right % 10);
+        }
+
         fn draw_rect(&mut self, x1: i32, y1: i32, x2: i32, y2: i32) {
             let style = PrimitiveStyleBuilder::new()
                 .fill_color(RawU16::new(self.colour).into())
@@ -8,6 +8,9 @@ pub mod app;
 pub mod mwatch;
 pub mod uop;
 pub mod notifications;
+pub mod alarms;
+pub mod alert;
+pub mod stopwatch;
 
 
 
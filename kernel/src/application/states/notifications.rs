@@ -4,28 +4,40 @@
 //!  
 
 use crate::application::FrameBuffer;
+use crate::application::font::FONT_6X12;
+use crate::application::layout::{self, TextLayout};
 use crate::application::states::prelude::*;
 use crate::system::input::InputEvent;
-use crate::system::{System, Host};
+use crate::system::{Clock, System, Host};
+
+use core::fmt::Write;
+use heapless::String;
+use time::PrimitiveDateTime;
 
-use embedded_graphics::mono_font::ascii::FONT_6X12;
 use embedded_graphics::mono_font::MonoTextStyle;
 use embedded_graphics::pixelcolor::raw::RawU16;
+use embedded_graphics::pixelcolor::Rgb565;
 use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
 use embedded_graphics::text::{Text, Alignment, Baseline};
 
-use crate::system::notification::Notification;
+use crate::system::notification::{Id, Notification};
 
-const DISPLAY_HEIGHT: i32 = 128; // TODO
 const CHAR_WIDTH: i32 = 6;
 const CHAR_HEIGHT: i32 = 12;
-const LINE_WIDTH: i32 = DISPLAY_HEIGHT / CHAR_WIDTH;
+/// Drawn next to notifications that haven't been opened
+const UNREAD: &str = "*";
+/// Height of the row of action buttons
+const BUTTON_HEIGHT: i32 = CHAR_HEIGHT + 2;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 /// The internal state of the notification application
 enum InternalState {
     Menu,
-    Body,
+    /// Viewing the notification with this id
+    Body(Id),
+    /// Asking whether to clear every notification
+    ConfirmClear,
 }
 
 pub struct NotificationState {
@@ -38,13 +50,13 @@ pub struct NotificationState {
 impl State for NotificationState {
     /// Render the notification state
     fn render(&mut self, system: &mut System<impl Host>, display: &mut FrameBuffer) -> Option<Signal> {
-        self.menu.update_count(system.nm.idx() as i8);
+        self.menu.update_count(system.nm.len() as i8);
         match self.state {
             InternalState::Menu => {
                 let size = display.bounding_box().size;
                 let style = MonoTextStyle::new(&FONT_6X12, RawU16::from(0x02D4).into());
 
-                if system.nm.idx() > 0 {
+                if !system.nm.is_empty() {
                     // Display a selection indicator
                     Text::with_baseline(
                         ">",
@@ -53,16 +65,31 @@ impl State for NotificationState {
                         Baseline::Top,
                     )
                     .draw(display).ok();
-                    for item in 0..system.nm.idx() {
-                        system.nm.peek_notification(item, |notification| {
-                            Text::with_baseline(
-                                notification.title(),
-                                Point::new(size.width as i32 / 2, item as i32 * CHAR_HEIGHT),
-                                style,
-                                Baseline::Top,
-                            )
-                            .draw(display).ok();
-                        });
+                    let now = system.clock.get_date_time();
+                    // newest first
+                    for (item, notification) in system.nm.iter().rev().enumerate() {
+                        let y = item as i32 * CHAR_HEIGHT;
+                        if !notification.is_read() {
+                            Text::with_baseline(UNREAD, Point::new(CHAR_WIDTH, y), style, Baseline::Top)
+                                .draw(display).ok();
+                        }
+                        let age = age(now, notification.timestamp());
+                        Text::with_alignment(
+                            &age,
+                            Point::new(size.width as i32, y + FONT_6X12.baseline as i32),
+                            style,
+                            Alignment::Right,
+                        )
+                        .draw(display).ok();
+                        // leave a space between the title and its age
+                        let columns = (size.width as i32 / CHAR_WIDTH) - 3 - age.chars().count() as i32;
+                        Text::with_baseline(
+                            layout::truncate(notification.title(), columns.max(0) as usize),
+                            Point::new(CHAR_WIDTH * 2, y),
+                            style,
+                            Baseline::Top,
+                        )
+                        .draw(display).ok();
                     }
                 } else {
                     Text::with_alignment(
@@ -74,27 +101,40 @@ impl State for NotificationState {
                     .draw(display).ok();
                 }
             }
-            InternalState::Body => {
-                system
-                    .nm
-                    .peek_notification(self.menu.selected() as usize, |notification| {
-                        self.body.render(display, &notification);
-                    });
+            InternalState::ConfirmClear => {
+                let size = display.bounding_box().size;
+                let style = MonoTextStyle::new(&FONT_6X12, RawU16::from(0x02D4).into());
+                let centre = Point::new(size.width as i32 / 2, size.height as i32 / 2);
+                Text::with_alignment("Clear all?", centre, style, Alignment::Center).draw(display).ok();
+                Text::with_alignment("Dual to confirm", centre + Point::new(0, CHAR_HEIGHT * 2), style, Alignment::Center)
+                    .draw(display).ok();
             }
+            InternalState::Body(id) => match system.nm.find(id) {
+                Some(notification) => self.body.render(display, notification, system.clock.get_date_time()),
+                None => {
+                    // it was evicted whilst we were reading it
+                    self.state = InternalState::Menu;
+                }
+            },
         }
         None
     }
 
     /// Handle the input for the notification
+    ///
+    /// In the menu a dual press asks to clear all notifications, a second dual press clears them and a middle
+    /// press cancels. Whilst viewing a notification a dual press dismisses it.
+    /// Scrolling past the end of a notification selects its actions, a middle press then sends the action
+    /// to the phone and dismisses the notification.
     fn input(&mut self, system: &mut System<impl Host>, input: InputEvent) -> Option<Signal> {
         if input == InputEvent::Multi {
             self.stop(system);
             return Some(Signal::Home); // signal to dm to go home
         }
-        self.menu.update_count(system.nm.idx() as i8);
+        self.menu.update_count(system.nm.len() as i8);
         match self.state {
             InternalState::Menu => {
-                if system.nm.idx() > 0 {
+                if !system.nm.is_empty() {
                     match input {
                         InputEvent::Left => {
                             self.menu.prev();
@@ -103,14 +143,12 @@ impl State for NotificationState {
                             self.menu.next();
                         }
                         InputEvent::Middle => {
-                            self.state = InternalState::Body;
-                            system.nm.peek_notification(
-                                self.menu.selected() as usize,
-                                |notification| {
-                                    let line_count = notification.body().len() as i32 / LINE_WIDTH;
-                                    self.body = Body::new(line_count - line_count / 2);
-                                },
-                            );
+                            if let Some(id) = system.nm.get_newest(self.menu.selected() as usize).map(|n| n.id()) {
+                                self.open(system, id);
+                            }
+                        }
+                        InputEvent::Dual => {
+                            self.state = InternalState::ConfirmClear;
                         }
                         _ => {}
                     }
@@ -118,15 +156,35 @@ impl State for NotificationState {
                     self.stop(system);
                 }
             }
-            InternalState::Body => match input {
+            InternalState::ConfirmClear => match input {
+                InputEvent::Dual => {
+                    system.nm.clear();
+                    self.state = InternalState::Menu;
+                }
                 InputEvent::Middle => {
                     self.state = InternalState::Menu;
                 }
+                _ => {}
+            },
+            InternalState::Body(id) => match input {
+                InputEvent::Middle => {
+                    // only notifications with a key have actions
+                    let key = system.nm.find(id).and_then(|notification| notification.key());
+                    if let (Some(action), Some(key)) = (self.body.action, key) {
+                        system.em.action(key.as_bytes(), action as u8);
+                        system.nm.dismiss(id);
+                    }
+                    self.state = InternalState::Menu;
+                }
                 InputEvent::Left => {
-                    self.body.down();
+                    self.body.prev();
                 }
                 InputEvent::Right => {
-                    self.body.up();
+                    self.body.next();
+                }
+                InputEvent::Dual => {
+                    system.nm.dismiss(id);
+                    self.state = InternalState::Menu;
                 }
                 _ => {}
             },
@@ -135,13 +193,24 @@ impl State for NotificationState {
     }
 }
 
+impl NotificationState {
+    /// Start the application viewing the notification with `id`, marking it as read
+    pub fn open(&mut self, system: &mut System<impl Host>, id: Id) {
+        if system.nm.mark_read(id) {
+            self.start(system);
+            self.state = InternalState::Body(id);
+            self.body = Body::default();
+        }
+    }
+}
+
 impl Default for NotificationState {
     fn default() -> Self {
         Self {
             is_running: false,
             state: InternalState::Menu,
             menu: Menu::new(),
-            body: Body::new(0),
+            body: Body::default(),
         }
     }
 }
@@ -177,60 +246,103 @@ impl ScopedState for NotificationState {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
 struct Body {
-    scroll_y: i32,
-    max_scroll_y: i32,
+    /// Number of lines scrolled past
+    scroll: usize,
+    /// Updated on render, as that is when the size of the display is known
+    max_scroll: usize,
+    /// Number of actions the notification has, updated on render
+    actions: usize,
+    /// The selected action, if any
+    action: Option<usize>,
 }
 
 impl Body {
-    /// Create a new body, with a maximum vertical scroll
-    pub fn new(max_scroll: i32) -> Self {
-        let max_scroll = if max_scroll < (DISPLAY_HEIGHT / CHAR_HEIGHT) / 2 {
-            // no scroll required if it doesnt go past a page
-            0
-        } else {
-            max_scroll
-        };
-        info!("Creating body with max scroll of {}", -max_scroll);
-        Body {
-            scroll_y: 0,
-            max_scroll_y: -max_scroll,
+    /// Render the notification, below a header of its source and age and above its actions
+    pub fn render(&mut self, display: &mut FrameBuffer, notification: &Notification, now: PrimitiveDateTime) {
+        let size = display.bounding_box().size;
+        let colour: Rgb565 = RawU16::from(0x02D4).into();
+        let style = MonoTextStyle::new(&FONT_6X12, colour);
+        let age = age(now, notification.timestamp());
+        Text::with_alignment(&age, Point::new(size.width as i32, FONT_6X12.baseline as i32), style, Alignment::Right)
+            .draw(display).ok();
+        let columns = (size.width as i32 / CHAR_WIDTH) - 1 - age.chars().count() as i32;
+        Text::with_baseline(
+            layout::truncate(notification.source(), columns.max(0) as usize),
+            Point::zero(),
+            style,
+            Baseline::Top,
+        )
+        .draw(display).ok();
+
+        self.actions = notification.actions().count();
+        let buttons = if self.actions > 0 { BUTTON_HEIGHT } else { 0 };
+        let text = Rectangle::new(
+            Point::new(0, CHAR_HEIGHT),
+            Size::new(size.width, (size.height as i32 - CHAR_HEIGHT - buttons).max(0) as u32),
+        );
+        let layout = TextLayout::new(notification.body(), &FONT_6X12, size.width);
+        self.max_scroll = layout.max_scroll(text.size.height);
+        self.scroll = self.scroll.min(self.max_scroll);
+        layout.draw(&mut display.clipped(&text), style, text.top_left, self.scroll).ok();
+
+        if self.actions > 0 {
+            let width = size.width as i32 / self.actions as i32;
+            let y = size.height as i32 - BUTTON_HEIGHT;
+            for (idx, label) in notification.actions().enumerate() {
+                let button = Rectangle::new(Point::new(idx as i32 * width, y), Size::new(width as u32, BUTTON_HEIGHT as u32));
+                // the selected action is drawn inverted
+                let (fill, text) = if self.action == Some(idx) {
+                    (colour, Rgb565::BLACK)
+                } else {
+                    (Rgb565::BLACK, colour)
+                };
+                button.into_styled(PrimitiveStyle::with_fill(fill)).draw(display).ok();
+                button.into_styled(PrimitiveStyle::with_stroke(colour, 1)).draw(display).ok();
+                Text::with_alignment(
+                    layout::truncate(label, ((width - 2) / CHAR_WIDTH).max(0) as usize),
+                    button.center() + Point::new(0, FONT_6X12.baseline as i32 - CHAR_HEIGHT / 2),
+                    MonoTextStyle::new(&FONT_6X12, text),
+                    Alignment::Center,
+                )
+                .draw(display).ok();
+            }
         }
     }
 
-    /// Render the notification
-    pub fn render(&mut self, display: &mut FrameBuffer, notification: &Notification) {
-        let body = notification.body().as_bytes();
-        for (idx, line) in body[0..body.len()].chunks(LINE_WIDTH as usize).enumerate() {
-            // screen pixels / character width
-            // safe because the protocol guarentees no unicode bytes will be sent
-            let style = MonoTextStyle::new(&FONT_6X12, RawU16::from(0x02D4).into());
-            Text::with_baseline(
-                unsafe { core::str::from_utf8_unchecked(line) },
-                Point::new(0, ((idx as i32) + self.scroll_y) * CHAR_HEIGHT),
-                style,
-                Baseline::Top
-            )
-            .draw(display).ok();
+    /// Scroll the text up, revealing the next line. Once at the end, select the next action
+    fn next(&mut self) {
+        if self.scroll < self.max_scroll {
+            self.scroll += 1;
+        } else if self.actions > 0 {
+            self.action = Some(self.action.map_or(0, |action| (action + 1).min(self.actions - 1)));
         }
     }
 
-    /// Move to the previous line
-    fn up(&mut self) {
-        self.scroll_y -= 1;
-        if self.scroll_y < self.max_scroll_y {
-            self.scroll_y = self.max_scroll_y;
+    /// Select the previous action, or scroll the text down back towards the first line
+    fn prev(&mut self) {
+        match self.action {
+            Some(0) => self.action = None,
+            Some(action) => self.action = Some(action - 1),
+            None => self.scroll = self.scroll.saturating_sub(1),
         }
     }
+}
 
-    /// Move to the next line
-    fn down(&mut self) {
-        self.scroll_y += 1;
-        if self.scroll_y > 0 {
-            self.scroll_y = 0;
-        }
+/// How long ago `then` was, i.e "5m ago"
+fn age(now: PrimitiveDateTime, then: PrimitiveDateTime) -> String<16> {
+    let seconds = (now - then).whole_seconds();
+    let mut buffer = String::new();
+    match seconds {
+        // the clock may have been set backwards since
+        i64::MIN..=59 => write!(buffer, "now"),
+        60..=3599 => write!(buffer, "{}m ago", seconds / 60),
+        3600..=86_399 => write!(buffer, "{}h ago", seconds / 3600),
+        _ => write!(buffer, "{}d ago", seconds / 86_400),
     }
+    .ok();
+    buffer
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -269,8 +381,27 @@ impl Menu {
         self.state_idx
     }
 
-    /// Update the number of elements in the list
+    /// Update the number of elements in the list, keeping the selection within it
     fn update_count(&mut self, item_count: i8) {
         self.item_count = item_count;
+        if self.state_idx >= item_count {
+            self.state_idx = (item_count - 1).max(0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use time::{Date, Duration, Month, Time};
+
+    #[test]
+    fn notification_age() {
+        let then = PrimitiveDateTime::new(Date::from_calendar_date(2019, Month::December, 2).unwrap(), Time::MIDNIGHT);
+        assert_eq!(age(then, then), "now");
+        assert_eq!(age(then - Duration::minutes(5), then), "now");
+        assert_eq!(age(then + Duration::minutes(5), then), "5m ago");
+        assert_eq!(age(then + Duration::hours(3), then), "3h ago");
+        assert_eq!(age(then + Duration::days(40), then), "40d ago");
     }
 }
@@ -0,0 +1,175 @@
+//! Stopwatch state
+//!
+//! Timed by the [`Uptime`], so it keeps counting whilst other states are shown. A middle press starts and stops
+//! it, a dual press records a lap and left + middle resets it.
+
+use crate::application::FrameBuffer;
+use crate::application::font::FONT_6X12;
+use crate::application::states::clock::seven_segment::SevenSegments;
+use crate::application::states::prelude::*;
+use crate::system::input::InputEvent;
+use crate::system::{Host, System, Uptime};
+
+use core::fmt::Write;
+use heapless::{String, Vec};
+
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::raw::RawU16;
+use embedded_graphics::prelude::*;
+use embedded_graphics::text::{Alignment, Baseline, Text};
+
+/// The number of laps kept, older laps are forgotten
+pub const MAX_LAPS: usize = 5;
+const CHAR_HEIGHT: i32 = 12;
+const COLOUR: u16 = 0x2C78;
+const DIGITS_Y: i32 = 8;
+/// Where the list of laps starts
+const LAPS_Y: i32 = 66;
+
+#[derive(Default)]
+pub struct StopwatchState {
+    /// The uptime it was last started at, whilst running
+    started: Option<u64>,
+    /// Milliseconds counted before it was last started
+    elapsed: u64,
+    /// The elapsed time of the last lap
+    lap_start: u64,
+    /// The number and duration of the most recent laps, oldest first
+    laps: Vec<(u32, u64), MAX_LAPS>,
+}
+
+impl StopwatchState {
+    /// The milliseconds counted at `now`
+    pub fn elapsed(&self, now: u64) -> u64 {
+        self.elapsed + self.started.map_or(0, |started| now.saturating_sub(started))
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.started.is_some()
+    }
+
+    /// The number and duration of the most recent laps, oldest first
+    pub fn laps(&self) -> impl Iterator<Item = (u32, u64)> + '_ {
+        self.laps.iter().copied()
+    }
+
+    fn start_stop(&mut self, now: u64) {
+        match self.started {
+            Some(_) => {
+                self.elapsed = self.elapsed(now);
+                self.started = None;
+            }
+            None => self.started = Some(now),
+        }
+    }
+
+    /// Record a lap, only whilst running
+    fn lap(&mut self, now: u64) {
+        if !self.is_running() {
+            return;
+        }
+        let elapsed = self.elapsed(now);
+        let number = self.laps.last().map_or(1, |(number, _)| number + 1);
+        if self.laps.is_full() {
+            self.laps.remove(0);
+        }
+        self.laps.push((number, elapsed - self.lap_start)).ok();
+        self.lap_start = elapsed;
+    }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl State for StopwatchState {
+    fn render(&mut self, system: &mut System<impl Host>, display: &mut FrameBuffer) -> Option<Signal> {
+        let size = display.bounding_box().size;
+        let style = MonoTextStyle::new(&FONT_6X12, RawU16::new(COLOUR).into());
+        let elapsed = self.elapsed(system.uptime.millis());
+        let (minutes, seconds) = (elapsed / 60_000, elapsed / 1000 % 60);
+
+        SevenSegments::new(display, 20, DIGITS_Y, COLOUR).pair((minutes % 60) as u8, seconds as u8);
+        let mut buffer: String<24> = String::new();
+        let y = DIGITS_Y + 35 + CHAR_HEIGHT + 2;
+        if minutes >= 60 {
+            write!(buffer, "{}h", minutes / 60).ok();
+            Text::new(&buffer, Point::new(20, y), style).draw(display).ok();
+            buffer.clear();
+        }
+        write!(buffer, ".{:02}", elapsed / 10 % 100).ok();
+        Text::with_alignment(&buffer, Point::new(size.width as i32 - 20, y), style, Alignment::Right)
+            .draw(display).ok();
+
+        // newest first
+        for (row, (number, lap)) in self.laps.iter().rev().enumerate() {
+            let y = LAPS_Y + row as i32 * CHAR_HEIGHT;
+            buffer.clear();
+            write!(buffer, "Lap {}", number).ok();
+            Text::with_baseline(&buffer, Point::new(0, y), style, Baseline::Top).draw(display).ok();
+            buffer.clear();
+            duration(&mut buffer, *lap);
+            let position = Point::new(size.width as i32, y + FONT_6X12.baseline as i32);
+            Text::with_alignment(&buffer, position, style, Alignment::Right).draw(display).ok();
+        }
+        None
+    }
+
+    fn input(&mut self, system: &mut System<impl Host>, input: InputEvent) -> Option<Signal> {
+        let now = system.uptime.millis();
+        match input {
+            InputEvent::Left => return Some(Signal::Previous),
+            InputEvent::Right => return Some(Signal::Next),
+            InputEvent::Middle => self.start_stop(now),
+            InputEvent::Dual => self.lap(now),
+            InputEvent::LeftMiddle => self.reset(),
+            _ => {}
+        }
+        None
+    }
+}
+
+impl StaticState for StopwatchState {}
+
+/// Format milliseconds as a lap time, i.e "1:05.27"
+fn duration(buffer: &mut String<24>, millis: u64) {
+    let (minutes, seconds, hundredths) = (millis / 60_000, millis / 1000 % 60, millis / 10 % 100);
+    write!(buffer, "{}:{:02}.{:02}", minutes, seconds, hundredths).ok();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stopwatch_laps() {
+        let mut stopwatch = StopwatchState::default();
+        stopwatch.lap(0);
+        assert_eq!(stopwatch.laps().count(), 0);
+        stopwatch.start_stop(1000);
+        stopwatch.lap(3500);
+        // stopped time isn't counted
+        stopwatch.start_stop(4000);
+        assert_eq!(stopwatch.elapsed(9000), 3000);
+        stopwatch.start_stop(9000);
+        stopwatch.lap(10_000);
+        assert!(stopwatch.laps().eq([(1, 2500), (2, 1500)]));
+
+        for now in 0..MAX_LAPS as u64 {
+            stopwatch.lap(11_000 + now);
+        }
+        assert_eq!(stopwatch.laps().count(), MAX_LAPS);
+        assert_eq!(stopwatch.laps().next(), Some((3, 1000)));
+
+        stopwatch.reset();
+        assert!(!stopwatch.is_running());
+        assert_eq!(stopwatch.elapsed(20_000), 0);
+    }
+
+    #[test]
+    fn stopwatch_duration() {
+        let mut buffer = String::new();
+        duration(&mut buffer, 65_279);
+        assert_eq!(buffer, "1:05.27");
+    }
+}
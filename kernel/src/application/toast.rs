@@ -0,0 +1,85 @@
+//! Toast
+//!
+//! A transient pop up, drawn over the current state to alert the user of a high priority notification
+
+use crate::application::font::FONT_6X12;
+use crate::application::layout::{self, TextLayout};
+use crate::application::FrameBuffer;
+use crate::system::notification::Id;
+use crate::system::{Host, System, Uptime};
+
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::raw::RawU16;
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{PrimitiveStyleBuilder, Rectangle};
+use embedded_graphics::text::{Baseline, Text};
+
+/// How long the toast is shown for, in milliseconds
+pub const TOAST_MILLIS: u64 = 5000;
+/// Lines of the body shown beneath the title
+const BODY_LINES: usize = 3;
+const MARGIN: i32 = 2;
+const PADDING: i32 = 3;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Toast {
+    id: Id,
+    /// The [`Uptime`] the toast is hidden at
+    until: u64,
+}
+
+impl Toast {
+    /// Alert the notification with `id`, from the uptime `now`
+    pub fn new(id: Id, now: u64) -> Self {
+        Self { id, until: now + TOAST_MILLIS }
+    }
+
+    /// The notification being alerted
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    /// Draw the toast in the middle of the display, returns false once it has expired or the notification has gone
+    pub fn render(&mut self, system: &mut System<impl Host>, display: &mut FrameBuffer) -> bool {
+        if system.uptime.millis() >= self.until {
+            return false;
+        }
+        let notification = match system.nm.find(self.id) {
+            Some(notification) => notification,
+            None => return false,
+        };
+
+        let size = display.bounding_box().size;
+        let line_height = FONT_6X12.character_size.height as i32;
+        let height = line_height * (1 + BODY_LINES as i32) + 2 * PADDING;
+        let area = Rectangle::new(
+            Point::new(MARGIN, (size.height as i32 - height) / 2),
+            Size::new(size.width - 2 * MARGIN as u32, height as u32),
+        );
+        let colour: Rgb565 = RawU16::from(0x02D4).into();
+        area.into_styled(
+            PrimitiveStyleBuilder::new()
+                .fill_color(Rgb565::BLACK)
+                .stroke_color(colour)
+                .stroke_width(1)
+                .build(),
+        )
+        .draw(display)
+        .ok();
+
+        let style = MonoTextStyle::new(&FONT_6X12, colour);
+        let origin = area.top_left + Point::new(PADDING, PADDING);
+        let width = area.size.width - 2 * PADDING as u32;
+        let layout = TextLayout::new(notification.body(), &FONT_6X12, width);
+        let title = notification.sender().unwrap_or_else(|| notification.title());
+        Text::with_baseline(layout::truncate(title, layout.columns()), origin, style, Baseline::Top)
+            .draw(display)
+            .ok();
+        for (idx, line) in layout.lines().take(BODY_LINES).enumerate() {
+            let position = origin + Point::new(0, (idx as i32 + 1) * line_height);
+            Text::with_baseline(line, position, style, Baseline::Top).draw(display).ok();
+        }
+        true
+    }
+}
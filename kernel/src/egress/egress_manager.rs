@@ -0,0 +1,231 @@
+//! EgressManager
+//!
+//! All data sent from the watch to the connected device is framed and queued here,
+//! ready to be drained by the host's transport.
+//!
+//! Replies use the binary frame format from [`crate::ingress::frame`], with a type of [`ACK`] or [`NAK`]
+//! and a payload of `[packet type, packet sequence number, code]`. Packets sent with the legacy protocol
+//! have no sequence number, so zero is used.
+
+use core::iter;
+
+use crc::crc16::{self, X25_TABLE};
+use heapless::spsc::Queue;
+
+use crate::application::application_manager::{self, Progress};
+use crate::ingress::frame::{self, CRC_LEN, HEADER_LEN, SOH, VERSION};
+
+/// Frame type of a positive acknowledgement (ascii ACK)
+pub const ACK: u8 = 6;
+/// Frame type of a negative acknowledgement (ascii NAK)
+pub const NAK: u8 = 21;
+/// Frame type of an upload progress report, the payload is `[received (u32 LE), total (u32 LE)]`
+pub const PROGRESS: u8 = b'P';
+/// Frame type of a notification action being taken, the payload is `[action id, notification key...]`
+pub const ACTION: u8 = b'R';
+/// Frame type of the result of a getter syscall, the payload is the syscall followed by its values, each
+/// preceded by a unit separator
+pub const VALUE: u8 = b'V';
+
+/// The reason a packet was acknowledged or rejected
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Code {
+    Ok = 0,
+    /// The packet type was not recognised
+    UnknownType = 1,
+    /// The packet contents could not be parsed
+    ParseError = 2,
+    /// The syscall is not supported
+    UnknownSyscall = 3,
+    /// The application failed its checksum
+    ChecksumFailed = 4,
+    /// The frame failed its integrity check
+    FrameCorrupted = 5,
+    /// The frame version is not supported
+    UnsupportedVersion = 6,
+    /// The packet does not fit in memory
+    NoMemory = 7,
+    /// A chunk was sent before the chunks preceding it
+    OutOfOrder = 8,
+    /// A chunk was sent without beginning an upload
+    NoUpload = 9,
+    /// The application manager cannot do that right now
+    InvalidState = 10,
+    /// The application does not begin with a valid header
+    InvalidHeader = 11,
+    /// The application was built for a different version of the kernel's ABI
+    IncompatibleAbi = 12,
+}
+
+impl From<application_manager::Error> for Code {
+    fn from(e: application_manager::Error) -> Self {
+        match e {
+            application_manager::Error::ChecksumFailed => Code::ChecksumFailed,
+            application_manager::Error::NoMemory => Code::NoMemory,
+            application_manager::Error::OutOfOrder => Code::OutOfOrder,
+            application_manager::Error::NoUpload => Code::NoUpload,
+            application_manager::Error::InvalidHeader => Code::InvalidHeader,
+            application_manager::Error::IncompatibleAbi => Code::IncompatibleAbi,
+            application_manager::Error::Executing
+            | application_manager::Error::NoApplication
+            | application_manager::Error::InvalidServiceFn
+            | application_manager::Error::InvalidInputFn => Code::InvalidState,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Error {
+    /// The outgoing queue does not have room for the frame
+    QueueFull,
+    /// The frame could not be encoded
+    Frame(frame::Error),
+}
+
+pub struct EgressManager {
+    rb: Queue<u8, 512>,
+    seq: u8,
+    /// The packet type, sequence number and code of the last ACK or NAK
+    last_reply: Option<(u8, u8, Code)>,
+}
+
+impl Default for EgressManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EgressManager {
+    /// Constructs a new EgressManager
+    pub const fn new() -> Self {
+        Self {
+            rb: Queue::new(),
+            seq: 0,
+            last_reply: None,
+        }
+    }
+
+    /// Acknowledge a packet
+    pub fn ack(&mut self, packet_type: u8, seq: u8) {
+        self.reply(ACK, packet_type, seq, Code::Ok);
+    }
+
+    /// Reject a packet, with a reason
+    pub fn nak(&mut self, packet_type: u8, seq: u8, code: Code) {
+        self.reply(NAK, packet_type, seq, code);
+    }
+
+    /// Report the progress of a chunked upload
+    pub fn progress(&mut self, progress: Progress) {
+        let mut payload = [0u8; 8];
+        payload[..4].copy_from_slice(&(progress.received as u32).to_le_bytes());
+        payload[4..].copy_from_slice(&(progress.total as u32).to_le_bytes());
+        self.send(PROGRESS, &payload)
+            .unwrap_or_else(|e| error!("Failed to queue progress: {:?}", e));
+    }
+
+    /// Tell the phone an action was taken on the notification with `key`
+    pub fn action(&mut self, key: &[u8], action: u8) {
+        let key = &key[..key.len().min(frame::MAX_PAYLOAD - 1)];
+        self.send_parts(ACTION, &[&[action], key])
+            .unwrap_or_else(|e| error!("Failed to queue action: {:?}", e));
+    }
+
+    /// Take the packet type, sequence number and code of the last ACK or NAK queued, if any since the last take
+    pub fn take_last_reply(&mut self) -> Option<(u8, u8, Code)> {
+        self.last_reply.take()
+    }
+
+    fn reply(&mut self, frame_type: u8, packet_type: u8, seq: u8, code: Code) {
+        self.last_reply = Some((packet_type, seq, code));
+        self.send(frame_type, &[packet_type, seq, code as u8])
+            .unwrap_or_else(|e| error!("Failed to queue reply: {:?}", e));
+    }
+
+    /// Frame `payload` and queue it for transmission
+    ///
+    /// Frames are queued whole or not at all.
+    pub fn send(&mut self, frame_type: u8, payload: &[u8]) -> Result<(), Error> {
+        self.send_parts(frame_type, &[payload])
+    }
+
+    /// Frame the concatenation of `parts`, encoding it straight into the queue
+    fn send_parts(&mut self, frame_type: u8, parts: &[&[u8]]) -> Result<(), Error> {
+        let len: usize = parts.iter().map(|part| part.len()).sum();
+        if len > frame::MAX_PAYLOAD {
+            return Err(Error::Frame(frame::Error::TooLong(len)));
+        }
+        if self.rb.capacity() - self.rb.len() < 1 + HEADER_LEN + len + CRC_LEN {
+            return Err(Error::QueueFull);
+        }
+        let len = (len as u16).to_le_bytes();
+        let header = [VERSION, frame_type, self.seq, len[0], len[1]];
+        let mut crc = 0;
+        // NOTE(unwrap): we checked there is enough room above
+        self.rb.enqueue(SOH).unwrap();
+        for part in iter::once(&header[..]).chain(parts.iter().copied()) {
+            crc = crc16::update(crc, &X25_TABLE, part);
+            for byte in part {
+                self.rb.enqueue(*byte).unwrap();
+            }
+        }
+        for byte in crc.to_le_bytes() {
+            self.rb.enqueue(byte).unwrap();
+        }
+        self.seq = self.seq.wrapping_add(1);
+        Ok(())
+    }
+
+    /// Drain queued bytes into `out`, returning how many were written
+    pub fn read(&mut self, out: &mut [u8]) -> usize {
+        let mut count = 0;
+        for byte in out.iter_mut() {
+            match self.rb.dequeue() {
+                Some(b) => {
+                    *byte = b;
+                    count += 1;
+                }
+                None => break,
+            }
+        }
+        count
+    }
+
+    /// Is there anything waiting to be sent?
+    pub fn is_empty(&self) -> bool {
+        self.rb.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ingress::frame::FrameDecoder;
+
+    #[test]
+    fn egress_nak_is_framed() {
+        let mut em = EgressManager::new();
+        em.nak(b'A', 3, Code::ChecksumFailed);
+        let mut out = [0u8; 64];
+        let len = em.read(&mut out);
+        assert!(em.is_empty());
+
+        let mut decoder = FrameDecoder::new();
+        let complete = out[..len].iter().map(|b| decoder.decode(*b)).last();
+        assert_eq!(complete, Some(Ok(true)));
+        assert_eq!(decoder.frame_type(), NAK);
+        assert_eq!(decoder.payload(), &[b'A', 3, Code::ChecksumFailed as u8]);
+    }
+
+    #[test]
+    fn egress_full_queue_rejects_whole_frames() {
+        let mut em = EgressManager::new();
+        while em.send(ACK, &[0u8; 64]).is_ok() {}
+        assert_eq!(em.send(ACK, &[0u8; 64]), Err(Error::QueueFull));
+        let mut out = [0u8; 1024];
+        let len = em.read(&mut out);
+        // every queued frame is complete
+        assert_eq!(len % (1 + frame::HEADER_LEN + 64 + frame::CRC_LEN), 0);
+    }
+}
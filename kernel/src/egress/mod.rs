@@ -0,0 +1 @@
+pub mod egress_manager;
@@ -11,11 +11,31 @@ pub enum Type {
     Notification,
     Syscall,
     Application,
+    /// Begin, or resume, a chunked application upload
+    Upload,
+    /// A chunk of an application upload
+    Chunk,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Error {
+    /// The buffer has no room left
+    BufferFull,
+}
+
+/// How binary data, i.e application bytes, is encoded within a packet
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Encoding {
+    /// Two ascii hex nibbles per byte
+    Hex,
+    /// Raw bytes, with control bytes escaped, see [`crate::ingress::escape`]
+    Escaped,
 }
 
 #[derive(Copy, Clone)]
 pub struct Buffer {
     pub btype: Type,
+    pub encoding: Encoding,
     pub payload: [u8; BUFF_SIZE],
     pub payload_idx: usize,
 }
@@ -25,6 +45,7 @@ impl Default for Buffer {
     fn default() -> Buffer {
         Buffer {
             btype: Type::Unknown,
+            encoding: Encoding::Hex,
             payload: [0u8; BUFF_SIZE],
             payload_idx: 0,
         }
@@ -36,6 +57,7 @@ impl Buffer {
     pub fn new(rx_buffer: [u8; BUFF_SIZE]) -> Self {
         Buffer {
             btype: Type::Unknown,
+            encoding: Encoding::Hex,
             payload: rx_buffer,
             payload_idx: 0,
         }
@@ -47,9 +69,11 @@ impl Buffer {
     }
 
     /// Writes a byte into the buffer
-    pub fn write(&mut self, byte: u8) {
-        self.payload[self.payload_idx] = byte;
+    pub fn write(&mut self, byte: u8) -> Result<(), Error> {
+        let slot = self.payload.get_mut(self.payload_idx).ok_or(Error::BufferFull)?;
+        *slot = byte;
         self.payload_idx += 1;
+        Ok(())
     }
 
     // Resets the index of the buffer, does not blank the memory
@@ -57,22 +81,42 @@ impl Buffer {
         self.payload_idx = 0;
     }
 
-    /// Buffer as &str
-    pub fn as_str(&self) -> &str {
-        unsafe { core::str::from_utf8_unchecked(&self.payload[0..self.payload_idx]) }
+    /// Buffer as &str, if it is valid UTF-8
+    pub fn as_str(&self) -> Result<&str, core::str::Utf8Error> {
+        core::str::from_utf8(&self.payload[0..self.payload_idx])
     }
 
     pub fn as_slice(&self) -> &[u8] {
         &self.payload[0..self.payload_idx]
     }
 
+    /// The protocol type byte for this buffer, zero if unknown
+    pub fn type_byte(&self) -> u8 {
+        match (self.btype, self.encoding) {
+            (Type::Notification, _) => b'N',
+            (Type::Syscall, _) => b'S',
+            (Type::Application, Encoding::Hex) => b'A',
+            (Type::Application, Encoding::Escaped) => b'a',
+            (Type::Upload, _) => b'U',
+            (Type::Chunk, Encoding::Hex) => b'C',
+            (Type::Chunk, Encoding::Escaped) => b'c',
+            (Type::Unknown, _) => 0,
+        }
+    }
+
     /// Based on the type byte, determine the type of the incoming payload
+    ///
+    /// Lowercase application and chunk types carry escaped binary rather than hex nibbles.
     pub fn determine_type(&mut self, type_byte: u8) -> Type {
-        self.btype = match type_byte {
-            b'N' => Type::Notification, /* NOTIFICATION i.e FB Msg */
-            b'S' => Type::Syscall,
-            b'A' => Type::Application,  /* Load Application */
-            _ => Type::Unknown,
+        (self.btype, self.encoding) = match type_byte {
+            b'N' => (Type::Notification, Encoding::Hex), /* NOTIFICATION i.e FB Msg */
+            b'S' => (Type::Syscall, Encoding::Hex),
+            b'A' => (Type::Application, Encoding::Hex),  /* Load Application */
+            b'a' => (Type::Application, Encoding::Escaped),
+            b'U' => (Type::Upload, Encoding::Hex),
+            b'C' => (Type::Chunk, Encoding::Hex),
+            b'c' => (Type::Chunk, Encoding::Escaped),
+            _ => (Type::Unknown, Encoding::Hex),
         };
         self.btype
     }
@@ -99,3 +143,18 @@ impl core::fmt::Display for Buffer {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn buffer_full() {
+        let mut buffer = Buffer::default();
+        for _ in 0..BUFF_SIZE {
+            assert_eq!(buffer.write(b'a'), Ok(()));
+        }
+        assert_eq!(buffer.write(b'b'), Err(Error::BufferFull));
+        assert_eq!(buffer.as_slice().len(), BUFF_SIZE);
+    }
+}
@@ -0,0 +1,86 @@
+//! Escape
+//!
+//! Byte stuffing for binary payloads. Any byte that would be mistaken for a protocol control byte is
+//! sent as [`ESC`] followed by the byte XOR [`ESC_XOR`], everything else is sent as is. Compared to hex nibbles
+//! this roughly halves the size of an application upload.
+
+/// Escape character (ascii ESC)
+pub const ESC: u8 = 27;
+/// Escaped bytes are XOR'd with this value
+pub const ESC_XOR: u8 = 0x20;
+
+/// SOH, STX, ETX, ESC and US
+const RESERVED: [u8; 5] = [1, 2, 3, ESC, 31];
+
+/// Does `byte` need escaping?
+pub fn is_reserved(byte: u8) -> bool {
+    RESERVED.contains(&byte)
+}
+
+/// Escape `data` into `out`, returning the number of bytes written or `None` if `out` is too small
+pub fn encode(data: &[u8], out: &mut [u8]) -> Option<usize> {
+    let mut idx = 0;
+    for &byte in data {
+        if is_reserved(byte) {
+            *out.get_mut(idx)? = ESC;
+            *out.get_mut(idx + 1)? = byte ^ ESC_XOR;
+            idx += 2;
+        } else {
+            *out.get_mut(idx)? = byte;
+            idx += 1;
+        }
+    }
+    Some(idx)
+}
+
+/// Incrementally removes the escaping from a stream of bytes
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Decoder {
+    escaped: bool,
+}
+
+impl Decoder {
+    pub const fn new() -> Self {
+        Self { escaped: false }
+    }
+
+    /// Feed a byte into the decoder, returning the original byte once available
+    pub fn decode(&mut self, byte: u8) -> Option<u8> {
+        if self.escaped {
+            self.escaped = false;
+            Some(byte ^ ESC_XOR)
+        } else if byte == ESC {
+            self.escaped = true;
+            None
+        } else {
+            Some(byte)
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.escaped = false;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn escape_round_trip() {
+        let data: [u8; 256] = core::array::from_fn(|i| i as u8);
+        let mut out = [0u8; 512];
+        let len = encode(&data, &mut out).unwrap();
+        assert_eq!(len, data.len() + RESERVED.len());
+        assert!(!out[..len].iter().any(|b| *b != ESC && is_reserved(*b)));
+
+        let mut decoder = Decoder::new();
+        let decoded: std::vec::Vec<u8> = out[..len].iter().filter_map(|b| decoder.decode(*b)).collect();
+        assert_eq!(&decoded[..], &data[..]);
+    }
+
+    #[test]
+    fn escape_out_of_space() {
+        assert_eq!(encode(&[1, 2, 3], &mut [0u8; 5]), None);
+    }
+}
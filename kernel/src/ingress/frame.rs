@@ -0,0 +1,226 @@
+//! Frame
+//!
+//! Versioned binary framing of the ingress protocol. Each frame carries its length, a sequence number
+//! and a CRC16 (X25), so corrupted or truncated packets can be detected.
+//!
+//! ```text
+//! SOH -> VERSION -> TYPE -> SEQ -> LEN (u16 LE) -> PAYLOAD -> CRC (u16 LE)
+//!        ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+//!        covered by the CRC
+//! ```
+//!
+//! The payload is the same as the legacy protocol, i.e everything between the type byte and the ETX.
+
+use crc::crc16::checksum_x25;
+
+use crate::system::notification::BUFF_SIZE;
+
+/// Start of a binary frame
+pub const SOH: u8 = 1;
+/// The current frame format version
+pub const VERSION: u8 = 1;
+/// Version, type, sequence number and length
+pub const HEADER_LEN: usize = 5;
+pub const CRC_LEN: usize = 2;
+pub const MAX_PAYLOAD: usize = BUFF_SIZE;
+/// The largest possible frame, including the SOH
+pub const MAX_FRAME_LEN: usize = 1 + HEADER_LEN + MAX_PAYLOAD + CRC_LEN;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Error {
+    /// The frame was encoded with a version we don't understand
+    UnsupportedVersion(u8),
+    /// The length field is larger than we can store
+    TooLong(usize),
+    /// The frame failed its integrity check
+    ChecksumFailed,
+    /// The output buffer is too small to encode the frame into
+    NoMemory,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum State {
+    /// Waiting for a SOH
+    Idle,
+    Header,
+    Payload,
+    Crc,
+}
+
+/// Incrementally decodes a single frame at a time
+pub struct FrameDecoder {
+    state: State,
+    /// header followed by the payload, this is what the CRC is calculated over
+    raw: [u8; HEADER_LEN + MAX_PAYLOAD],
+    raw_idx: usize,
+    crc: [u8; CRC_LEN],
+    crc_idx: usize,
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameDecoder {
+    pub const fn new() -> Self {
+        Self {
+            state: State::Idle,
+            raw: [0u8; HEADER_LEN + MAX_PAYLOAD],
+            raw_idx: 0,
+            crc: [0u8; CRC_LEN],
+            crc_idx: 0,
+        }
+    }
+
+    /// Are we part way through decoding a frame?
+    pub fn in_progress(&self) -> bool {
+        self.state != State::Idle
+    }
+
+    /// Abandon the current frame
+    pub fn reset(&mut self) {
+        self.state = State::Idle;
+        self.raw_idx = 0;
+        self.crc_idx = 0;
+    }
+
+    /// Feed a byte into the decoder
+    ///
+    /// Returns `Ok(true)` once a complete and valid frame is available.
+    /// On error the decoder resets itself, ready for the next SOH.
+    pub fn decode(&mut self, byte: u8) -> Result<bool, Error> {
+        match self.state {
+            State::Idle => {
+                if byte == SOH {
+                    self.reset();
+                    self.state = State::Header;
+                }
+            }
+            State::Header => {
+                self.raw[self.raw_idx] = byte;
+                self.raw_idx += 1;
+                if self.raw_idx == 1 && byte != VERSION {
+                    self.reset();
+                    return Err(Error::UnsupportedVersion(byte));
+                }
+                if self.raw_idx == HEADER_LEN {
+                    let len = self.len();
+                    if len > MAX_PAYLOAD {
+                        self.reset();
+                        return Err(Error::TooLong(len));
+                    }
+                    self.state = if len == 0 { State::Crc } else { State::Payload };
+                }
+            }
+            State::Payload => {
+                self.raw[self.raw_idx] = byte;
+                self.raw_idx += 1;
+                if self.raw_idx == HEADER_LEN + self.len() {
+                    self.state = State::Crc;
+                }
+            }
+            State::Crc => {
+                self.crc[self.crc_idx] = byte;
+                self.crc_idx += 1;
+                if self.crc_idx == CRC_LEN {
+                    self.state = State::Idle;
+                    let expected = u16::from_le_bytes(self.crc);
+                    if checksum_x25(&self.raw[..self.raw_idx]) != expected {
+                        return Err(Error::ChecksumFailed);
+                    }
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// The type byte of the last frame
+    pub fn frame_type(&self) -> u8 {
+        self.raw[1]
+    }
+
+    /// The sequence number of the last frame
+    pub fn seq(&self) -> u8 {
+        self.raw[2]
+    }
+
+    /// The payload length of the last frame
+    pub fn len(&self) -> usize {
+        u16::from_le_bytes([self.raw[3], self.raw[4]]) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The payload of the last frame
+    pub fn payload(&self) -> &[u8] {
+        &self.raw[HEADER_LEN..HEADER_LEN + self.len()]
+    }
+}
+
+/// Encode a frame into `out`, returning the number of bytes written
+pub fn encode(frame_type: u8, seq: u8, payload: &[u8], out: &mut [u8]) -> Result<usize, Error> {
+    if payload.len() > MAX_PAYLOAD {
+        return Err(Error::TooLong(payload.len()));
+    }
+    let total = 1 + HEADER_LEN + payload.len() + CRC_LEN;
+    if out.len() < total {
+        return Err(Error::NoMemory);
+    }
+    let len = (payload.len() as u16).to_le_bytes();
+    out[0] = SOH;
+    out[1..1 + HEADER_LEN].copy_from_slice(&[VERSION, frame_type, seq, len[0], len[1]]);
+    out[1 + HEADER_LEN..1 + HEADER_LEN + payload.len()].copy_from_slice(payload);
+    let crc = checksum_x25(&out[1..1 + HEADER_LEN + payload.len()]).to_le_bytes();
+    out[total - CRC_LEN..total].copy_from_slice(&crc);
+    Ok(total)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn decode_all(decoder: &mut FrameDecoder, bytes: &[u8]) -> Result<bool, Error> {
+        let mut complete = false;
+        for &byte in bytes {
+            complete = decoder.decode(byte)?;
+        }
+        Ok(complete)
+    }
+
+    #[test]
+    fn frame_round_trip() {
+        let mut out = [0u8; MAX_FRAME_LEN];
+        let len = encode(b'S', 7, b"\x1fT12:00:00", &mut out).unwrap();
+        let mut decoder = FrameDecoder::new();
+        assert_eq!(decode_all(&mut decoder, &out[..len]), Ok(true));
+        assert_eq!(decoder.frame_type(), b'S');
+        assert_eq!(decoder.seq(), 7);
+        assert_eq!(decoder.payload(), b"\x1fT12:00:00");
+        assert!(!decoder.in_progress());
+    }
+
+    #[test]
+    fn frame_corruption_detected() {
+        let mut out = [0u8; MAX_FRAME_LEN];
+        let len = encode(b'N', 0, b"\x1fsource\x1ftitle\x1fbody", &mut out).unwrap();
+        out[10] ^= 0x20;
+        let mut decoder = FrameDecoder::new();
+        assert_eq!(decode_all(&mut decoder, &out[..len]), Err(Error::ChecksumFailed));
+    }
+
+    #[test]
+    fn frame_bad_header() {
+        let mut decoder = FrameDecoder::new();
+        assert_eq!(decode_all(&mut decoder, &[SOH, 9]), Err(Error::UnsupportedVersion(9)));
+        let too_long = (MAX_PAYLOAD as u16 + 1).to_le_bytes();
+        assert_eq!(
+            decode_all(&mut decoder, &[SOH, VERSION, b'N', 0, too_long[0], too_long[1]]),
+            Err(Error::TooLong(MAX_PAYLOAD + 1))
+        );
+    }
+}
@@ -2,9 +2,12 @@
 //!
 //! All communicated date is run through here, parsed, then executed.
 
-use crate::ingress::buffer::{Buffer, Type};
-use crate::system::syscall::Syscall;
-use crate::system::{System, Host};
+use crate::egress::egress_manager::Code;
+use crate::ingress::buffer::{Buffer, Encoding, Type};
+use crate::ingress::escape;
+use crate::ingress::frame::{self, FrameDecoder, SOH};
+use crate::system::syscall::{self, Syscall};
+use crate::system::{Clock, Host, Statistics, System};
 use core::str::FromStr;
 use heapless::spsc::Queue;
 use simple_hex::hex_byte_to_byte;
@@ -24,30 +27,58 @@ enum State {
     ApplicationChecksum,
     /// Store the application in ram
     ApplicationStore,
+    /// Store a chunk of an application upload in ram
+    ChunkData,
+}
 
-    /// Notification Source - what generated the push notification
-    NotificationSource,
-    /// Notification title
-    NotificationTitle,
-    /// Notification body
-    NotificationBody,
+/// Whether the sender should hold off, see [`IngressManager::flow_control`]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum FlowControl {
+    /// There is room for more data
+    Ready,
+    /// The ring buffer is filling up, the sender should pause until it has been processed
+    Throttle,
 }
 
+const RB_SIZE: usize = 512;
+/// Throttle the sender once the ring buffer is this full
+pub const HIGH_WATERMARK: usize = RB_SIZE * 3 / 4;
+/// Release the sender once the ring buffer has drained to this
+pub const LOW_WATERMARK: usize = RB_SIZE / 4;
+
 const STX: u8 = 2;
 const ETX: u8 = 3;
-const PAYLOAD: u8 = 31; // Unit Separator
+pub(crate) const PAYLOAD: u8 = 31; // Unit Separator
 
 pub struct IngressManager {
-    rb: Queue<u8, 512>,
+    rb: Queue<u8, RB_SIZE>,
     state: State,
+    flow: FlowControl,
+    /// Bytes dropped because the ring buffer was full
+    dropped: u32,
 
     hex_chars: [u8; 2],
     hex_idx: usize,
-
-    nsi: [usize; 3],
-    nsi_idx: usize,
+    escape: escape::Decoder,
 
     buffer: Buffer,
+    /// The packet did not fit in the buffer
+    overflow: bool,
+
+    frame: FrameDecoder,
+    last_seq: Option<u8>,
+    /// The code the frame with `last_seq` was ACK'd (`Code::Ok`) or NAK'd with
+    last_reply: Option<Code>,
+    /// Sequence number of the packet being parsed, zero for legacy packets
+    packet_seq: u8,
+    /// Checksum of the chunk being received, or why the chunk was rejected
+    chunk: Result<u32, Code>,
+}
+
+impl Default for IngressManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl IngressManager {
@@ -56,12 +87,20 @@ impl IngressManager {
         IngressManager {
             rb: Queue::new(),
             state: State::Init,
+            flow: FlowControl::Ready,
+            dropped: 0,
             hex_chars: [0u8; 2],
             hex_idx: 0,
-            nsi: [0usize; 3], // notification section pointers
-            nsi_idx: 0,
+            escape: escape::Decoder::new(),
 
             buffer: Buffer::default(),
+            overflow: false,
+
+            frame: FrameDecoder::new(),
+            last_seq: None,
+            last_reply: None,
+            packet_seq: 0,
+            chunk: Err(Code::ParseError),
         }
     }
 
@@ -70,205 +109,330 @@ impl IngressManager {
     /// be abstracted over the communication medium,
     /// in theory if we setup usb serial, we could have two ingress managers
     /// working in harmony
-    pub fn write(&mut self, data: &[u8]) {
+    ///
+    /// Returns the number of bytes accepted, anything that doesn't fit is dropped and counted.
+    pub fn write(&mut self, data: &[u8]) -> usize {
+        let mut accepted = 0;
         for byte in data {
-            match self.rb.enqueue(*byte) {
-                Ok(_) => {}
-                Err(e) => panic!("Ring buffer overflow by {:?} bytes", e),
+            if self.rb.enqueue(*byte).is_err() {
+                break;
             }
+            accepted += 1;
+        }
+        if accepted < data.len() {
+            self.dropped = self.dropped.wrapping_add((data.len() - accepted) as u32);
+        }
+        if self.rb.len() >= HIGH_WATERMARK {
+            self.flow = FlowControl::Throttle;
         }
+        accepted
+    }
+
+    /// Should the transport throttle the sender?
+    ///
+    /// Becomes [`FlowControl::Throttle`] once the ring buffer reaches [`HIGH_WATERMARK`], and only returns to
+    /// [`FlowControl::Ready`] once it has been processed down to [`LOW_WATERMARK`].
+    pub fn flow_control(&self) -> FlowControl {
+        self.flow
+    }
+
+    /// Total number of bytes dropped because the ring buffer was full
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    /// Abandon any partially received packet or frame, i.e when the connection drops
+    pub fn reset(&mut self) {
+        while self.rb.dequeue().is_some() {}
+        self.frame.reset();
+        self.state = State::Wait;
     }
 
     /// Processs the internal ringbuffer's bytes and execute if the payload is complete
     pub fn process(&mut self, system: &mut System<impl Host>) {
+        if self.dropped > 0 {
+            system.stats.ingress_dropped(self.dropped);
+        }
+        while let Some(byte) = self.rb.dequeue() {
+            if self.frame.in_progress() || byte == SOH {
+                self.process_frame_byte(byte, system);
+            } else {
+                if byte == STX {
+                    self.packet_seq = 0;
+                }
+                self.process_byte(byte, system);
+            }
+        }
+        if self.rb.len() <= LOW_WATERMARK {
+            self.flow = FlowControl::Ready;
+        }
+    }
+
+    /// Run a byte through the binary frame decoder, executing the frame once it is complete and valid
+    fn process_frame_byte(&mut self, byte: u8, system: &mut System<impl Host>) {
+        if byte == SOH && !self.frame.in_progress() && self.state != State::Wait {
+            warn!("Partial buffer detected: {:?}", self.buffer);
+            self.state = State::Wait;
+        }
+        match self.frame.decode(byte) {
+            Ok(true) => {
+                let seq = self.frame.seq();
+                if self.last_seq == Some(seq) {
+                    warn!("Dropping duplicate frame with sequence number {}", seq);
+                    // our reply may have been lost, so send it again
+                    match self.last_reply {
+                        Some(Code::Ok) => system.em.ack(self.frame.frame_type(), seq),
+                        Some(code) => system.em.nak(self.frame.frame_type(), seq, code),
+                        None => {}
+                    }
+                    return;
+                }
+                if let Some(last) = self.last_seq {
+                    if seq != last.wrapping_add(1) {
+                        warn!("Frame sequence jumped from {} to {}", last, seq);
+                    }
+                }
+                self.last_seq = Some(seq);
+                self.packet_seq = seq;
+
+                // a valid frame carries a legacy payload, so replay it through the legacy state machine
+                system.em.take_last_reply();
+                self.process_byte(STX, system);
+                self.process_byte(self.frame.frame_type(), system);
+                for idx in 0..self.frame.len() {
+                    let byte = self.frame.payload()[idx];
+                    self.process_byte(byte, system);
+                }
+                self.process_byte(ETX, system);
+                // remembered, so a retransmission gets the same reply
+                self.last_reply = system.em.take_last_reply()
+                    .filter(|&(_, reply_seq, _)| reply_seq == seq)
+                    .map(|(_, _, code)| code);
+            }
+            Ok(false) => {}
+            Err(e) => {
+                error!("Dropping corrupted frame: {:?}", e);
+                let code = match e {
+                    frame::Error::UnsupportedVersion(_) => Code::UnsupportedVersion,
+                    frame::Error::TooLong(_) | frame::Error::NoMemory => Code::NoMemory,
+                    frame::Error::ChecksumFailed => Code::FrameCorrupted,
+                };
+                match e {
+                    // the type and sequence number come after the version, so weren't read
+                    frame::Error::UnsupportedVersion(_) => system.em.nak(0, 0, code),
+                    _ => system.em.nak(self.frame.frame_type(), self.frame.seq(), code),
+                }
+            }
+        }
+    }
+
+    /// Run a byte through the legacy protocol state machine
+    fn process_byte(&mut self, byte: u8, system: &mut System<impl Host>) {
         let buffer = &mut self.buffer; // lifetime gynmastics, move the field out of self
-        if !self.rb.is_empty() {
-            while let Some(byte) = self.rb.dequeue() {
-                match byte {
-                    STX => {
-                        if self.state != State::Wait {
-                            warn!("Partial buffer detected: {:?}", buffer);
-                        }
-                        /* Start of packet */
-                        self.hex_idx = 0;
-                        self.nsi_idx = 0;
-                        buffer.clear();
-                        self.state = State::Init; // activate processing
+        match byte {
+            STX => {
+                if self.state != State::Wait {
+                    warn!("Partial buffer detected: {:?}", buffer);
+                }
+                /* Start of packet */
+                self.hex_idx = 0;
+                self.escape.reset();
+                self.overflow = false;
+                self.chunk = Err(Code::ParseError);
+                buffer.clear();
+                self.state = State::Init; // activate processing
+            }
+            ETX => {
+                /* End of packet */
+                /* Finalize messge then reset state machine ready for next msg*/
+                self.state = State::Wait;
+                if self.overflow {
+                    if buffer.btype == Type::Notification {
+                        warn!("Notification too large, truncated to {} bytes", buffer.payload_idx);
+                    } else {
+                        error!("Dropping oversized packet: {:?}", buffer.btype);
+                        system.em.nak(buffer.type_byte(), self.packet_seq, Code::NoMemory);
+                        return;
                     }
-                    ETX => {
-                        /* End of packet */
-                        /* Finalize messge then reset state machine ready for next msg*/
-                        self.state = State::Wait;
-                        match buffer.btype {
-                            Type::Unknown => {
-                                // if the type cannot be determined abort, and wait until next STX
-                            }
-                            Type::Application => {
-                                system.am.verify().unwrap_or_else(|e| {
-                                    error!("Failed to verify application: {:?}", e)
-                                });
-                            }
-                            Type::Notification => {
-                                info!(
-                                    "Adding notification from: {:?}, with section indexes {:?}",
-                                    buffer, self.nsi
-                                );
-                                self.nsi[2] = self.nsi_idx;
-                                let nscopy = self.nsi;
-                                system.nm.add(buffer, &nscopy).unwrap_or_else(|e| {
-                                    error!("Failed to add notification: {:?}", e)
-                                });
-                            }
-                            Type::Syscall => {
-                                info!("Parsing syscall from: {:?}", buffer);
-                                match Syscall::from_str(buffer.as_str()) {
-                                    Ok(syscall) => syscall.execute(system),
-                                    Err(e) => error!("Failed to parse syscall {:?}", e),
-                                }
+                }
+                match buffer.btype {
+                    Type::Unknown => {
+                        // if the type cannot be determined abort, and wait until next STX
+                    }
+                    Type::Application => match system.am.verify() {
+                        Ok(_) => system.em.ack(buffer.type_byte(), self.packet_seq),
+                        Err(e) => {
+                            error!("Failed to verify application: {:?}", e);
+                            system.em.nak(buffer.type_byte(), self.packet_seq, Code::from(e));
+                        }
+                    },
+                    Type::Notification => {
+                        info!("Adding notification from: {:?}", buffer);
+                        match system.nm.add(buffer, system.clock.get_date_time(), system.utc_offset) {
+                            Ok(_) => system.em.ack(buffer.type_byte(), self.packet_seq),
+                            Err(e) => {
+                                error!("Failed to add notification: {:?}", e);
+                                system.em.nak(buffer.type_byte(), self.packet_seq, Code::ParseError);
                             }
                         }
                     }
-                    PAYLOAD => {
-                        match buffer.btype {
-                            Type::Unknown => {
-                                warn!("Dropping buffer of unknown type {:?}", buffer.btype);
-                                self.state = State::Wait
-                            }
-                            Type::Application => {
-                                if self.state == State::ApplicationChecksum {
-                                    // We've parsed the checksum, now we write the data into ram
-                                    self.state = State::ApplicationStore;
-                                } else {
-                                    self.state = State::ApplicationChecksum;
-                                    // reset before we load the new application
-                                    system.am.kill().unwrap_or_else(|e| {
-                                        warn!("Failed to kill running app: {:?}", e)
-                                    });
+                    Type::Upload => {
+                        let upload = buffer.as_str().ok().and_then(parse_hex_pair).ok_or(Code::ParseError).and_then(|(total, cs)| {
+                            system.am.begin_upload(total as usize, cs).map_err(Code::from)
+                        });
+                        match upload {
+                            Ok(offset) => {
+                                info!("Upload will start from offset {}", offset);
+                                system.em.ack(buffer.type_byte(), self.packet_seq);
+                                if let Some(progress) = system.am.progress() {
+                                    system.em.progress(progress);
                                 }
                             }
-                            Type::Notification => {
-                                if self.state == State::NotificationSource {
-                                    // we've parsed the app source
-                                    self.nsi[0] = self.nsi_idx;
-                                    self.state = State::NotificationTitle;
-                                } else if self.state == State::NotificationTitle {
-                                    // weve parsed the title
-                                    self.nsi[1] = self.nsi_idx;
-                                    self.state = State::NotificationBody;
-                                } else {
-                                    self.state = State::NotificationSource; // new parse
-                                }
+                            Err(code) => {
+                                error!("Failed to begin upload: {:?}", code);
+                                system.em.nak(buffer.type_byte(), self.packet_seq, code);
                             }
-                            _ => self.state = State::Payload,
                         }
                     }
-                    _ => {
-                        /* Run through byte state machine */
-                        match self.state {
-                            State::Init => {
-                                buffer.determine_type(byte);
-                                info!("New buffer of type {:?}", buffer.btype);
-                                if let Type::Unknown = buffer.btype {
-                                    error!("Buffer type is unknown. Going back to wait state.");
-                                    self.state = State::Wait
-                                }
+                    Type::Chunk => {
+                        let result = self.chunk.and_then(|cs| system.am.end_chunk(cs).map_err(Code::from));
+                        match result {
+                            Ok(_) => system.em.ack(buffer.type_byte(), self.packet_seq),
+                            Err(code) => {
+                                error!("Failed to store chunk: {:?}", code);
+                                system.em.nak(buffer.type_byte(), self.packet_seq, code);
                             }
-                            State::Payload => {
-                                buffer.write(byte);
+                        }
+                    }
+                    Type::Syscall => {
+                        info!("Parsing syscall from: {:?}", buffer);
+                        let result = buffer
+                            .as_str()
+                            .map_err(|_| syscall::Error::ParseError)
+                            .and_then(Syscall::from_str)
+                            .and_then(|syscall| syscall.execute(system));
+                        match result {
+                            Ok(()) => {
+                                system.em.ack(buffer.type_byte(), self.packet_seq);
                             }
-                            State::ApplicationChecksum | State::ApplicationStore => {
+                            Err(e) => {
+                                error!("Failed to execute syscall {:?}", e);
+                                let code = match e {
+                                    syscall::Error::UnknownSyscall => Code::UnknownSyscall,
+                                    syscall::Error::ParseError => Code::ParseError,
+                                    syscall::Error::NoMemory => Code::NoMemory,
+                                };
+                                system.em.nak(buffer.type_byte(), self.packet_seq, code);
+                            }
+                        }
+                    }
+                }
+            }
+            PAYLOAD => {
+                match buffer.btype {
+                    Type::Unknown => {
+                        warn!("Dropping buffer of unknown type {:?}", buffer.btype);
+                        self.state = State::Wait
+                    }
+                    Type::Application => {
+                        if self.state == State::ApplicationChecksum {
+                            // We've parsed the checksum, now we write the data into ram
+                            self.state = State::ApplicationStore;
+                        } else {
+                            self.state = State::ApplicationChecksum;
+                            // reset before we load the new application
+                            system.am.kill().unwrap_or_else(|e| {
+                                warn!("Failed to kill running app: {:?}", e)
+                            });
+                        }
+                    }
+                    Type::Chunk => {
+                        if self.state == State::Payload {
+                            // We've parsed the chunk header, the rest is data
+                            self.chunk = buffer.as_str().ok().and_then(parse_hex_pair)
+                                .ok_or(Code::ParseError)
+                                .and_then(|(offset, cs)| {
+                                    system.am.begin_chunk(offset as usize).map_err(Code::from)?;
+                                    Ok(cs)
+                                });
+                            self.state = if self.chunk.is_ok() { State::ChunkData } else { State::Wait };
+                        } else {
+                            self.state = State::Payload;
+                        }
+                    }
+                    Type::Notification => {
+                        // the notification parses its own fields, so keep the separators
+                        self.state = State::Payload;
+                        if buffer.write(byte).is_err() {
+                            self.overflow = true;
+                        }
+                    }
+                    _ => self.state = State::Payload,
+                }
+            }
+            _ => {
+                /* Run through byte state machine */
+                match self.state {
+                    State::Init => {
+                        buffer.determine_type(byte);
+                        info!("New buffer of type {:?}", buffer.btype);
+                        if let Type::Unknown = buffer.btype {
+                            error!("Buffer type is unknown. Going back to wait state.");
+                            system.em.nak(byte, self.packet_seq, Code::UnknownType);
+                            self.state = State::Wait
+                        }
+                    }
+                    State::Payload => {
+                        if buffer.write(byte).is_err() {
+                            self.overflow = true;
+                        }
+                    }
+                    State::ApplicationChecksum | State::ApplicationStore | State::ChunkData => {
+                        let decoded = match buffer.encoding {
+                            Encoding::Escaped => Ok(self.escape.decode(byte)),
+                            Encoding::Hex => {
                                 self.hex_chars[self.hex_idx] = byte;
                                 self.hex_idx += 1;
                                 if self.hex_idx > 1 {
                                     self.hex_idx = 0;
-                                    match self.state {
-                                        State::ApplicationChecksum => {
-                                            match hex_byte_to_byte(
-                                                self.hex_chars[0],
-                                                self.hex_chars[1],
-                                            ) {
-                                                Ok(byte) => {
-                                                    system
-                                                        .am
-                                                        .write_checksum_byte(byte)
-                                                        .unwrap_or_else(|e| {
-                                                            error!(
-                                                                "Failed to write checksum: {:?}",
-                                                                e
-                                                            )
-                                                        });
-                                                }
-                                                Err(err) => {
-                                                    error!(
-                                                        "Failed to parse hex bytes to byte {:?}",
-                                                        err
-                                                    );
-                                                    self.state = State::Wait; // abort
-                                                }
-                                            }
-                                        }
-                                        State::ApplicationStore => {
-                                            match hex_byte_to_byte(
-                                                self.hex_chars[0],
-                                                self.hex_chars[1],
-                                            ) {
-                                                Ok(byte) => {
-                                                    system
-                                                        .am
-                                                        .write_ram_byte(byte)
-                                                        .unwrap_or_else(|e| {
-                                                            error!(
-                                                                "Failed to write to ram: {:?}",
-                                                                e
-                                                            )
-                                                        });
-                                                }
-                                                Err(err) => {
-                                                    error!(
-                                                        "Failed to parse hex bytes to byte {:?}",
-                                                        err
-                                                    );
-                                                    self.state = State::Wait; // abort
-                                                }
-                                            }
-                                        }
-                                        _ => unreachable!(),
-                                    }
+                                    hex_byte_to_byte(self.hex_chars[0], self.hex_chars[1]).map(Some)
+                                } else {
+                                    Ok(None)
                                 }
                             }
-                            State::NotificationBody
-                            | State::NotificationTitle
-                            | State::NotificationSource => {
-                                self.nsi_idx += 1;
-                                buffer.write(byte);
+                        };
+                        match decoded {
+                            Ok(Some(byte)) => {
+                                let result = if self.state == State::ApplicationChecksum {
+                                    system.am.write_checksum_byte(byte)
+                                } else {
+                                    system.am.write_ram_byte(byte)
+                                };
+                                result.unwrap_or_else(|e| {
+                                    error!("Failed to write application byte: {:?}", e)
+                                });
                             }
-                            State::Wait => {
-                                // do nothing, useless bytes
+                            Ok(None) => {}
+                            Err(err) => {
+                                error!("Failed to parse hex bytes to byte {:?}", err);
+                                self.state = State::Wait; // abort
                             }
                         }
                     }
+                    State::Wait => {
+                        // do nothing, useless bytes
+                    }
                 }
             }
         }
     }
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-
-    #[test]
-    fn ingress_syscall() {
-        let mut imgr = IngressManager::new();
-        let mut data = vec![STX, b'S', PAYLOAD];
-        for byte in "T00:00:00".bytes() {
-            data.push(byte);
-        }
-        data.push(ETX);
-        imgr.write(&data);
-        todo!("dummy system for tests");
-        // imgr.process(); 
-
-        // assert_eq!(imgr.state, State::Wait);
-    }
+/// Parse two hex numbers separated by a colon, i.e `4000:1A2B3C4D`
+fn parse_hex_pair(s: &str) -> Option<(u32, u32)> {
+    let (first, second) = s.split_once(':')?;
+    Some((
+        u32::from_str_radix(first, 16).ok()?,
+        u32::from_str_radix(second, 16).ok()?,
+    ))
 }
@@ -1,3 +1,4 @@
 pub mod buffer;
+pub mod escape;
+pub mod frame;
 pub mod ingress_manager;
-
@@ -11,6 +11,10 @@ extern crate log;
 #[macro_use]
 extern crate std;
 
+/// The version of the kernel
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
 pub mod application;
+pub mod egress;
 pub mod ingress;
 pub mod system;
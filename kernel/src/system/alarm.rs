@@ -0,0 +1,417 @@
+//! Alarm
+//!
+//! Alarms ring at a local time of day, either once or on chosen days of the week. Timers count down from when
+//! they are started. Both are checked every systick by [`System::process`](super::System::process), the one
+//! that went off is [`AlarmManager::ringing`] until it is snoozed or dismissed.
+
+use heapless::Vec;
+use time::{Date, Duration, PrimitiveDateTime, Time, Weekday};
+
+/// The number of alarms that can be set
+pub const MAX_ALARMS: usize = 4;
+/// The number of timers that can run at once
+pub const MAX_TIMERS: usize = 2;
+/// How long a snoozed alert waits before ringing again
+pub const SNOOZE: Duration = Duration::minutes(9);
+/// The number of alerts that can be snoozed at once
+pub const MAX_SNOOZED: usize = MAX_ALARMS + MAX_TIMERS;
+/// The size of the serialized alarms, see [`AlarmManager::serialize`]
+pub const SERIALIZED_LEN: usize = MAX_ALARMS * 4;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Error {
+    /// There is no room for another timer or snoozed alert
+    Full,
+    /// There is no alarm with that index
+    InvalidIndex,
+}
+
+/// A set of days of the week, Monday is bit 0 through to Sunday as bit 6
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Days(u8);
+
+impl Days {
+    pub const NONE: Days = Days(0);
+    pub const WEEKDAYS: Days = Days(0x1F);
+    pub const WEEKENDS: Days = Days(0x60);
+    pub const EVERY_DAY: Days = Days(0x7F);
+
+    /// The days from their bits, `None` if any bit above Sunday is set
+    pub const fn from_bits(bits: u8) -> Option<Days> {
+        if bits & !Days::EVERY_DAY.0 == 0 {
+            Some(Days(bits))
+        } else {
+            None
+        }
+    }
+
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(self, day: Weekday) -> bool {
+        self.0 & (1 << day.number_days_from_monday()) != 0
+    }
+
+    /// These days, and `day`
+    pub fn with(self, day: Weekday) -> Days {
+        Days(self.0 | (1 << day.number_days_from_monday()))
+    }
+}
+
+/// An alarm, to the minute
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Alarm {
+    time: Time,
+    /// The days to repeat on, if empty the alarm rings once and disables itself
+    pub days: Days,
+    pub enabled: bool,
+}
+
+impl Alarm {
+    /// An enabled alarm, ringing at `time` on `days`. The seconds of `time` are ignored
+    pub fn new(time: Time, days: Days) -> Self {
+        Self {
+            time: Time::from_hms(time.hour(), time.minute(), 0).unwrap_or(time),
+            days,
+            enabled: true,
+        }
+    }
+
+    /// The local time the alarm rings at
+    pub fn time(&self) -> Time {
+        self.time
+    }
+
+    /// Does the alarm ring in the minute of `now`?
+    fn rings_at(&self, now: PrimitiveDateTime) -> bool {
+        self.enabled
+            && (now.hour(), now.minute()) == (self.time.hour(), self.time.minute())
+            && (self.days.is_empty() || self.days.contains(now.weekday()))
+    }
+}
+
+/// A countdown
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Timer {
+    duration: Duration,
+    /// When the timer goes off, by the UTC clock
+    deadline: PrimitiveDateTime,
+}
+
+impl Timer {
+    /// How long the timer was started with
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// The time left at `now`, zero once it has gone off
+    pub fn remaining(&self, now: PrimitiveDateTime) -> Duration {
+        (self.deadline - now).max(Duration::ZERO)
+    }
+}
+
+/// What is ringing
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Alert {
+    /// The alarm set for this time
+    Alarm(Time),
+    /// A timer started with this duration
+    Timer(Duration),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AlarmManager {
+    alarms: [Option<Alarm>; MAX_ALARMS],
+    timers: Vec<Timer, MAX_TIMERS>,
+    /// Snoozed alerts and when to ring them again, by the UTC clock
+    snoozed: Vec<(Alert, PrimitiveDateTime), MAX_SNOOZED>,
+    ringing: Option<Alert>,
+    /// The local minute alarms were last checked in, so they only ring once in their minute
+    checked: Option<(Date, u8, u8)>,
+    /// Bitmask of the alarms set since the last check, which are checked even if their minute already was
+    unchecked: u8,
+}
+
+impl AlarmManager {
+    pub const fn new() -> Self {
+        Self {
+            alarms: [None; MAX_ALARMS],
+            timers: Vec::new(),
+            snoozed: Vec::new(),
+            ringing: None,
+            checked: None,
+            unchecked: 0,
+        }
+    }
+
+    /// The alarm at `index`, if one is set
+    pub fn alarm(&self, index: usize) -> Option<Alarm> {
+        self.alarms.get(index).copied().flatten()
+    }
+
+    /// Set or, with `None`, remove the alarm at `index`
+    ///
+    /// An alarm set for the current minute rings on the next check.
+    pub fn set_alarm(&mut self, index: usize, alarm: Option<Alarm>) -> Result<(), Error> {
+        *self.alarms.get_mut(index).ok_or(Error::InvalidIndex)? = alarm;
+        self.unchecked |= 1 << index;
+        Ok(())
+    }
+
+    /// Iterate over the set alarms and their index
+    pub fn alarms(&self) -> impl Iterator<Item = (usize, &Alarm)> {
+        self.alarms.iter().enumerate().filter_map(|(index, alarm)| Some((index, alarm.as_ref()?)))
+    }
+
+    /// Start a timer going off `duration` after `now`, by the UTC clock
+    pub fn start_timer(&mut self, now: PrimitiveDateTime, duration: Duration) -> Result<(), Error> {
+        let deadline = now.saturating_add(duration);
+        self.timers.push(Timer { duration, deadline }).map_err(|_| Error::Full)
+    }
+
+    /// Cancel all timers, including snoozed ones
+    pub fn cancel_timers(&mut self) {
+        self.timers.clear();
+        self.snoozed.retain(|(alert, _)| !matches!(alert, Alert::Timer(_)));
+    }
+
+    /// Iterate over the running timers
+    pub fn timers(&self) -> impl Iterator<Item = &Timer> {
+        self.timers.iter()
+    }
+
+    /// The alert that is ringing, if any
+    pub fn ringing(&self) -> Option<Alert> {
+        self.ringing
+    }
+
+    /// Stop the ringing alert, ringing it again after [`SNOOZE`]
+    ///
+    /// If [`MAX_SNOOZED`] alerts are already snoozed, the alert keeps ringing rather than dropping one.
+    pub fn snooze(&mut self, now: PrimitiveDateTime) -> Result<(), Error> {
+        if let Some(alert) = self.ringing {
+            self.snoozed.push((alert, now.saturating_add(SNOOZE))).map_err(|_| Error::Full)?;
+            self.ringing = None;
+        }
+        Ok(())
+    }
+
+    /// Stop the ringing alert
+    pub fn dismiss(&mut self) {
+        self.ringing = None;
+    }
+
+    /// Check the alarms against the `local` time and the timers against the `utc` clock, returns true if an
+    /// alert started ringing
+    ///
+    /// If another alert goes off whilst one is ringing, it replaces it.
+    pub fn check(&mut self, local: PrimitiveDateTime, utc: PrimitiveDateTime) -> bool {
+        let mut alert = None;
+
+        let minute = (local.date(), local.hour(), local.minute());
+        let unchecked = if self.checked != Some(minute) { u8::MAX } else { self.unchecked };
+        self.checked = Some(minute);
+        self.unchecked = 0;
+        for (index, alarm) in self.alarms.iter_mut().enumerate() {
+            let alarm = match alarm {
+                Some(alarm) if unchecked & (1 << index) != 0 => alarm,
+                _ => continue,
+            };
+            if alarm.rings_at(local) {
+                alert = Some(Alert::Alarm(alarm.time));
+                // one shot alarms are kept, but disabled, so they can easily be set again
+                alarm.enabled = !alarm.days.is_empty();
+            }
+        }
+
+        if let Some(index) = self.timers.iter().position(|timer| timer.deadline <= utc) {
+            alert = Some(Alert::Timer(self.timers.swap_remove(index).duration));
+        }
+
+        if let Some(index) = self.snoozed.iter().position(|(_, deadline)| *deadline <= utc) {
+            alert = Some(self.snoozed.swap_remove(index).0);
+        }
+
+        match alert {
+            Some(alert) => {
+                info!("Ringing {:?}", alert);
+                self.ringing = Some(alert);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Write the alarms into `out` for storage, returning the length. Timers are not stored
+    ///
+    /// `out` must be at least [`SERIALIZED_LEN`] bytes.
+    pub fn serialize(&self, out: &mut [u8]) -> usize {
+        for (alarm, out) in self.alarms.iter().zip(out.chunks_exact_mut(4)) {
+            match alarm {
+                Some(alarm) => out.copy_from_slice(&[
+                    1 | (alarm.enabled as u8) << 1,
+                    alarm.time.hour(),
+                    alarm.time.minute(),
+                    alarm.days.bits(),
+                ]),
+                None => out.copy_from_slice(&[0; 4]),
+            }
+        }
+        SERIALIZED_LEN
+    }
+
+    /// Restore the alarms written by [`AlarmManager::serialize`], returns false if `data` is invalid
+    pub fn deserialize(&mut self, data: &[u8]) -> bool {
+        if data.len() != SERIALIZED_LEN {
+            return false;
+        }
+        let mut alarms = [None; MAX_ALARMS];
+        for (alarm, data) in alarms.iter_mut().zip(data.chunks_exact(4)) {
+            if data[0] & 1 == 0 {
+                continue;
+            }
+            let (time, days) = match (Time::from_hms(data[1], data[2], 0), Days::from_bits(data[3])) {
+                (Ok(time), Some(days)) => (time, days),
+                _ => return false,
+            };
+            *alarm = Some(Alarm {
+                time,
+                days,
+                enabled: data[0] & 2 != 0,
+            });
+        }
+        self.alarms = alarms;
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use time::Month;
+
+    /// Monday the 2nd of December 2019
+    fn monday(hour: u8, minute: u8, second: u8) -> PrimitiveDateTime {
+        PrimitiveDateTime::new(
+            Date::from_calendar_date(2019, Month::December, 2).unwrap(),
+            Time::from_hms(hour, minute, second).unwrap(),
+        )
+    }
+
+    fn hm(hour: u8, minute: u8) -> Time {
+        Time::from_hms(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn alarm_rings_once_in_its_minute() {
+        let mut am = AlarmManager::new();
+        am.set_alarm(0, Some(Alarm::new(hm(7, 30), Days::NONE))).unwrap();
+        assert!(!am.check(monday(7, 29, 59), monday(7, 29, 59)));
+        assert!(am.check(monday(7, 30, 0), monday(7, 30, 0)));
+        assert_eq!(am.ringing(), Some(Alert::Alarm(hm(7, 30))));
+        am.dismiss();
+        assert!(!am.check(monday(7, 30, 30), monday(7, 30, 30)));
+        // one shot alarms disable themselves
+        assert!(!am.alarm(0).unwrap().enabled);
+        assert!(!am.check(monday(7, 30, 0) + Duration::days(1), monday(7, 30, 0) + Duration::days(1)));
+    }
+
+    #[test]
+    fn alarm_repeats_on_its_days() {
+        let mut am = AlarmManager::new();
+        am.set_alarm(1, Some(Alarm::new(hm(8, 0), Days::WEEKENDS))).unwrap();
+        assert!(!am.check(monday(8, 0, 0), monday(8, 0, 0)));
+        let saturday = monday(8, 0, 0) + Duration::days(5);
+        assert!(am.check(saturday, saturday));
+        am.dismiss();
+        let sunday = saturday + Duration::days(1);
+        assert!(am.check(sunday, sunday));
+        assert!(am.alarm(1).unwrap().enabled);
+        assert_eq!(am.set_alarm(MAX_ALARMS, None), Err(Error::InvalidIndex));
+    }
+
+    #[test]
+    fn timers_and_snooze() {
+        let mut am = AlarmManager::new();
+        let now = monday(12, 0, 0);
+        am.start_timer(now, Duration::minutes(5)).unwrap();
+        am.start_timer(now, Duration::minutes(1)).unwrap();
+        assert_eq!(am.start_timer(now, Duration::minutes(1)), Err(Error::Full));
+        assert_eq!(am.timers().map(|t| t.remaining(now + Duration::seconds(30))).max(), Some(Duration::seconds(270)));
+
+        assert!(!am.check(now, now + Duration::seconds(59)));
+        assert!(am.check(now, now + Duration::minutes(1)));
+        assert_eq!(am.ringing(), Some(Alert::Timer(Duration::minutes(1))));
+        am.snooze(now + Duration::minutes(1)).unwrap();
+        assert_eq!(am.ringing(), None);
+        assert!(am.check(now, now + Duration::minutes(5)));
+        am.dismiss();
+        assert!(am.check(now, now + Duration::minutes(1) + SNOOZE));
+        assert_eq!(am.ringing(), Some(Alert::Timer(Duration::minutes(1))));
+        assert_eq!(am.timers().count(), 0);
+    }
+
+    #[test]
+    fn snoozes_are_queued() {
+        let mut am = AlarmManager::new();
+        let now = monday(7, 30, 0);
+        am.set_alarm(0, Some(Alarm::new(hm(7, 30), Days::NONE))).unwrap();
+        am.start_timer(now, Duration::minutes(1)).unwrap();
+        assert!(am.check(now, now));
+        am.snooze(now).unwrap();
+        let later = now + Duration::minutes(1);
+        assert!(am.check(later, later));
+        am.snooze(later).unwrap();
+
+        // both snoozed alerts ring again
+        assert!(am.check(now + SNOOZE, now + SNOOZE));
+        assert_eq!(am.ringing(), Some(Alert::Alarm(hm(7, 30))));
+        am.dismiss();
+        assert!(!am.check(now + SNOOZE, now + SNOOZE));
+        assert!(am.check(later + SNOOZE, later + SNOOZE));
+        assert_eq!(am.ringing(), Some(Alert::Timer(Duration::minutes(1))));
+
+        // a full queue keeps the alert ringing rather than dropping a snoozed one
+        for _ in 0..MAX_SNOOZED {
+            am.ringing = Some(Alert::Timer(Duration::minutes(1)));
+            am.snooze(now).unwrap();
+        }
+        am.ringing = Some(Alert::Alarm(hm(7, 30)));
+        assert_eq!(am.snooze(now), Err(Error::Full));
+        assert_eq!(am.ringing(), Some(Alert::Alarm(hm(7, 30))));
+    }
+
+    #[test]
+    fn alarm_set_in_its_minute_rings() {
+        let mut am = AlarmManager::new();
+        am.set_alarm(0, Some(Alarm::new(hm(7, 30), Days::WEEKDAYS))).unwrap();
+        assert!(am.check(monday(7, 30, 0), monday(7, 30, 0)));
+        am.dismiss();
+        am.set_alarm(1, Some(Alarm::new(hm(7, 30), Days::NONE))).unwrap();
+        assert!(am.check(monday(7, 30, 20), monday(7, 30, 20)));
+        am.dismiss();
+        // without ringing the alarms that already rang in this minute again
+        assert!(!am.check(monday(7, 30, 40), monday(7, 30, 40)));
+    }
+
+    #[test]
+    fn alarms_serialize() {
+        let mut am = AlarmManager::new();
+        am.set_alarm(0, Some(Alarm::new(hm(7, 30), Days::WEEKDAYS))).unwrap();
+        am.set_alarm(2, Some(Alarm { enabled: false, ..Alarm::new(hm(22, 15), Days::NONE) })).unwrap();
+        let mut out = [0u8; SERIALIZED_LEN];
+        assert_eq!(am.serialize(&mut out), SERIALIZED_LEN);
+
+        let mut restored = AlarmManager::new();
+        assert!(restored.deserialize(&out));
+        assert!(restored.alarms().eq(am.alarms()));
+        assert!(!restored.deserialize(&out[..SERIALIZED_LEN - 1]));
+        out[3] = 0x80;
+        assert!(!restored.deserialize(&out));
+    }
+}
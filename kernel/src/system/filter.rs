@@ -0,0 +1,213 @@
+//! Filter
+//!
+//! Decides which notifications are allowed to alert the user. Muted sources and notifications arriving during
+//! quiet hours (do not disturb) are still stored, but never pop up or wake the display.
+
+use heapless::{String, Vec};
+use time::Time;
+
+/// The longest source that can be muted
+pub const SOURCE_LEN: usize = 32;
+/// The maximum number of muted sources
+pub const MAX_MUTED: usize = 8;
+/// The largest serialized filter, see [`NotificationFilter::serialize`]
+pub const SERIALIZED_LEN: usize = 1 + 6 + 1 + MAX_MUTED * (1 + SOURCE_LEN);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Error {
+    /// There is no room to mute another source
+    Full,
+    /// The source is longer than [`SOURCE_LEN`]
+    TooLong,
+}
+
+/// A daily period in which notifications don't alert
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct QuietHours {
+    pub start: Time,
+    pub end: Time,
+}
+
+impl QuietHours {
+    /// Is `time` within the quiet hours? The period may span midnight, i.e 22:00 until 07:00
+    pub fn contains(&self, time: Time) -> bool {
+        if self.start <= self.end {
+            time >= self.start && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NotificationFilter {
+    muted: Vec<String<SOURCE_LEN>, MAX_MUTED>,
+    quiet_hours: Option<QuietHours>,
+}
+
+impl NotificationFilter {
+    pub const fn new() -> Self {
+        Self {
+            muted: Vec::new(),
+            quiet_hours: None,
+        }
+    }
+
+    /// Stop notifications from `source` alerting
+    pub fn mute(&mut self, source: &str) -> Result<(), Error> {
+        if self.is_muted(source) {
+            return Ok(());
+        }
+        let mut muted = String::new();
+        muted.push_str(source).map_err(|_| Error::TooLong)?;
+        self.muted.push(muted).map_err(|_| Error::Full)
+    }
+
+    /// Allow notifications from `source` to alert again, returns false if it wasn't muted
+    pub fn unmute(&mut self, source: &str) -> bool {
+        match self.muted.iter().position(|muted| muted == source) {
+            Some(index) => {
+                self.muted.swap_remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Unmute all sources
+    pub fn clear(&mut self) {
+        self.muted.clear();
+    }
+
+    pub fn is_muted(&self, source: &str) -> bool {
+        self.muted.iter().any(|muted| muted == source)
+    }
+
+    /// Iterate over the muted sources
+    pub fn muted(&self) -> impl Iterator<Item = &str> {
+        self.muted.iter().map(|muted| muted.as_str())
+    }
+
+    /// Set the quiet hours, or `None` to disable them
+    pub fn set_quiet_hours(&mut self, quiet_hours: Option<QuietHours>) {
+        self.quiet_hours = quiet_hours;
+    }
+
+    pub fn quiet_hours(&self) -> Option<QuietHours> {
+        self.quiet_hours
+    }
+
+    /// Write the filter into `out` for storage, returning the length
+    ///
+    /// `out` must be at least [`SERIALIZED_LEN`] bytes.
+    pub fn serialize(&self, out: &mut [u8]) -> usize {
+        let mut len = 0;
+        let mut push = |bytes: &[u8]| {
+            out[len..len + bytes.len()].copy_from_slice(bytes);
+            len += bytes.len();
+        };
+        match self.quiet_hours {
+            Some(QuietHours { start, end }) => {
+                let ((sh, sm, ss), (eh, em, es)) = (start.as_hms(), end.as_hms());
+                push(&[1, sh, sm, ss, eh, em, es]);
+            }
+            None => push(&[0; 7]),
+        }
+        push(&[self.muted.len() as u8]);
+        for muted in &self.muted {
+            push(&[muted.len() as u8]);
+            push(muted.as_bytes());
+        }
+        len
+    }
+
+    /// Read back a filter written by [`NotificationFilter::serialize`]
+    pub fn deserialize(data: &[u8]) -> Option<NotificationFilter> {
+        let mut filter = NotificationFilter::new();
+        let (header, mut rest) = (data.get(..8)?, data.get(8..)?);
+        if header[0] != 0 {
+            filter.quiet_hours = Some(QuietHours {
+                start: Time::from_hms(header[1], header[2], header[3]).ok()?,
+                end: Time::from_hms(header[4], header[5], header[6]).ok()?,
+            });
+        }
+        for _ in 0..header[7] {
+            let len = *rest.first()? as usize;
+            let source = core::str::from_utf8(rest.get(1..1 + len)?).ok()?;
+            filter.mute(source).ok()?;
+            rest = &rest[1 + len..];
+        }
+        Some(filter)
+    }
+
+    /// Should a notification from `source`, arriving at `time`, alert the user?
+    pub fn allows(&self, source: &str, time: Time) -> bool {
+        let quiet = self.quiet_hours.is_some_and(|quiet| quiet.contains(time));
+        !quiet && !self.is_muted(source)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hm(hour: u8, minute: u8) -> Time {
+        Time::from_hms(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn filter_quiet_hours() {
+        let night = QuietHours { start: hm(22, 0), end: hm(7, 0) };
+        assert!(night.contains(hm(23, 30)));
+        assert!(night.contains(hm(0, 0)));
+        assert!(night.contains(hm(6, 59)));
+        assert!(!night.contains(hm(7, 0)));
+        assert!(!night.contains(hm(12, 0)));
+
+        let lunch = QuietHours { start: hm(12, 0), end: hm(13, 0) };
+        assert!(lunch.contains(hm(12, 30)));
+        assert!(!lunch.contains(hm(23, 0)));
+    }
+
+    #[test]
+    fn filter_muted_sources() {
+        let mut filter = NotificationFilter::new();
+        filter.mute("Slack").unwrap();
+        filter.mute("Slack").unwrap();
+        assert_eq!(filter.muted().count(), 1);
+        assert!(!filter.allows("Slack", hm(12, 0)));
+        assert!(filter.allows("Messenger", hm(12, 0)));
+
+        filter.set_quiet_hours(Some(QuietHours { start: hm(22, 0), end: hm(7, 0) }));
+        assert!(!filter.allows("Messenger", hm(23, 0)));
+
+        assert!(filter.unmute("Slack"));
+        assert!(!filter.unmute("Slack"));
+        for i in 0..MAX_MUTED {
+            filter.mute(&std::format!("{}", i)).unwrap();
+        }
+        assert_eq!(filter.mute("another"), Err(Error::Full));
+        assert_eq!(filter.mute(&"x".repeat(SOURCE_LEN + 1)), Err(Error::TooLong));
+        filter.clear();
+        assert_eq!(filter.muted().count(), 0);
+    }
+
+    #[test]
+    fn filter_serialize() {
+        let mut filter = NotificationFilter::new();
+        filter.set_quiet_hours(Some(QuietHours { start: hm(22, 0), end: hm(7, 0) }));
+        filter.mute("Slack").unwrap();
+        filter.mute("Zoë").unwrap();
+
+        let mut out = [0u8; SERIALIZED_LEN];
+        let len = filter.serialize(&mut out);
+        let restored = NotificationFilter::deserialize(&out[..len]).unwrap();
+        assert_eq!(restored.quiet_hours(), filter.quiet_hours());
+        assert!(restored.muted().eq(filter.muted()));
+        assert!(NotificationFilter::deserialize(&out[..len - 1]).is_none());
+
+        let len = NotificationFilter::new().serialize(&mut out);
+        assert_eq!(len, 8);
+        assert_eq!(NotificationFilter::deserialize(&out[..len]).unwrap().quiet_hours(), None);
+    }
+}
@@ -0,0 +1,538 @@
+//! Flash storage
+//!
+//! A [`Storage`] kept in a region of flash, for hosts that don't have a file system. The region is split into two
+//! banks of whole pages. Records are appended to the active bank, the newest record for a key replacing any before
+//! it, and once the bank is full its live records are copied to the other bank which then becomes active. The new
+//! bank's header is written last, so a reset part way through a copy loses nothing.
+//!
+//! Each bank starts with the magic `MWST` followed by a generation (`u32` LE), the bank with the highest generation
+//! is active. Each record is a header followed by its data, padded with `0xFF` to [`WRITE_SIZE`]. All fields are
+//! little endian.
+//!
+//! | Offset | Size | Field                                                  |
+//! |--------|------|--------------------------------------------------------|
+//! | 0      | 1    | Key tag, see [`Key::to_raw`]                           |
+//! | 1      | 1    | Kind, 0 for a record or 1 when the key was removed     |
+//! | 2      | 2    | Length of the data                                     |
+//! | 4      | 4    | Key value                                              |
+//! | 8      | 4    | CRC32 of the first 8 bytes and the data                |
+//! | 12     | 4    | Reserved, `0xFF`                                       |
+//!
+//! The log is only scanned when the storage is opened, which checks each record and keeps an index of the newest
+//! record for each key. A record with a bad checksum, left by a reset during a write, ends the log and the bank is
+//! compacted before it's written again.
+
+use crc::crc32::{self, IEEE_TABLE};
+use heapless::Vec;
+
+use super::storage::{Key, Storage};
+
+/// Flash is written in multiples of this many bytes, aligned to it
+pub const WRITE_SIZE: usize = 8;
+/// The most keys that can have a record stored at once
+pub const MAX_KEYS: usize = 16;
+
+const MAGIC: [u8; 4] = *b"MWST";
+const BANK_HEADER_LEN: usize = 8;
+const RECORD_HEADER_LEN: usize = 16;
+const KIND_RECORD: u8 = 0;
+const KIND_REMOVED: u8 = 1;
+const ERASED: u8 = 0xFF;
+
+/// Flash
+///
+/// Implement to give [`FlashStorage`] a region of flash. Offsets are from the start of the region, erased flash reads
+/// as `0xFF` and is only written once between erases.
+pub trait Flash {
+    type Error: core::fmt::Debug;
+
+    /// The size of an erasable page in bytes
+    const PAGE_SIZE: usize;
+
+    /// The size of the region in bytes, an even number of pages
+    fn capacity(&self) -> usize;
+
+    fn read(&mut self, offset: usize, buffer: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Write erased flash, `offset` and the length of `data` are multiples of [`WRITE_SIZE`]
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Erase the page starting at `offset`
+    fn erase(&mut self, offset: usize) -> Result<(), Self::Error>;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FlashStorageError<E> {
+    Flash(E),
+    /// The live records don't fit in a bank
+    Full,
+    /// Writing a new key when [`MAX_KEYS`] keys already have a record
+    TooManyKeys,
+    /// The record doesn't fit in the buffer it was read into
+    BufferTooSmall,
+}
+
+/// A record header read from the active bank
+#[derive(Debug, Copy, Clone)]
+struct Record {
+    key: Key,
+    removed: bool,
+    len: usize,
+    /// Offset of the next record
+    next: usize,
+}
+
+/// The newest record for a key, in the active bank
+#[derive(Debug, Copy, Clone)]
+struct Entry {
+    key: Key,
+    /// Offset of the record's header
+    offset: usize,
+    len: usize,
+}
+
+/// Storage kept in a region of [`Flash`], see the [module](self) docs for the layout
+pub struct FlashStorage<F: Flash> {
+    flash: F,
+    /// Offset of the active bank
+    bank: usize,
+    generation: u32,
+    /// Offset of the end of the log in the active bank
+    end: usize,
+    /// Whether something other than erased flash follows the log, which must be compacted before writing
+    dirty: bool,
+    /// The records that haven't been replaced or removed
+    index: Vec<Entry, MAX_KEYS>,
+}
+
+impl<F: Flash> FlashStorage<F> {
+    /// Open the storage in `flash`, formatting it if it doesn't hold any
+    pub fn new(flash: F) -> Result<Self, FlashStorageError<F::Error>> {
+        let mut storage =
+            Self { flash, bank: 0, generation: 0, end: BANK_HEADER_LEN, dirty: false, index: Vec::new() };
+        let bank_len = storage.bank_len();
+        let mut active = None;
+        for bank in [0, bank_len] {
+            let mut header = [0u8; BANK_HEADER_LEN];
+            storage.read_flash(bank, &mut header)?;
+            if header[..4] == MAGIC {
+                let generation = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+                if active.map_or(true, |(_, newest)| generation > newest) {
+                    active = Some((bank, generation));
+                }
+            }
+        }
+
+        match active {
+            Some((bank, generation)) => {
+                storage.bank = bank;
+                storage.generation = generation;
+                let mut offset = bank + BANK_HEADER_LEN;
+                while let Some(record) = storage.record(offset)? {
+                    let entry = (!record.removed).then_some(Entry { key: record.key, offset, len: record.len });
+                    if storage.update_index(record.key, entry).is_err() {
+                        // never written by this storage, which refuses new keys once full
+                        error!("More than {} keys in flash storage, formatting", MAX_KEYS);
+                        storage.format(0, 0)?;
+                        return Ok(storage);
+                    }
+                    offset = record.next;
+                }
+                storage.end = offset;
+                storage.dirty = !storage.is_erased(offset)?;
+            }
+            None => {
+                info!("Formatting flash storage");
+                storage.format(0, 0)?;
+            }
+        }
+        Ok(storage)
+    }
+
+    /// Release the flash
+    pub fn free(self) -> F {
+        self.flash
+    }
+
+    fn bank_len(&self) -> usize {
+        self.flash.capacity() / 2
+    }
+
+    /// The end of the active bank
+    fn bank_end(&self) -> usize {
+        self.bank + self.bank_len()
+    }
+
+    fn read_flash(&mut self, offset: usize, buffer: &mut [u8]) -> Result<(), FlashStorageError<F::Error>> {
+        self.flash.read(offset, buffer).map_err(FlashStorageError::Flash)
+    }
+
+    fn write_flash(&mut self, offset: usize, data: &[u8]) -> Result<(), FlashStorageError<F::Error>> {
+        self.flash.write(offset, data).map_err(FlashStorageError::Flash)
+    }
+
+    /// Erase a bank, leaving it empty
+    fn format(&mut self, bank: usize, generation: u32) -> Result<(), FlashStorageError<F::Error>> {
+        self.erase_bank(bank)?;
+        self.write_bank_header(bank, generation)?;
+        self.bank = bank;
+        self.generation = generation;
+        self.end = bank + BANK_HEADER_LEN;
+        self.dirty = false;
+        self.index.clear();
+        Ok(())
+    }
+
+    fn erase_bank(&mut self, bank: usize) -> Result<(), FlashStorageError<F::Error>> {
+        for page in (bank..bank + self.bank_len()).step_by(F::PAGE_SIZE) {
+            self.flash.erase(page).map_err(FlashStorageError::Flash)?;
+        }
+        Ok(())
+    }
+
+    fn write_bank_header(&mut self, bank: usize, generation: u32) -> Result<(), FlashStorageError<F::Error>> {
+        let mut header = [0u8; BANK_HEADER_LEN];
+        header[..4].copy_from_slice(&MAGIC);
+        header[4..].copy_from_slice(&generation.to_le_bytes());
+        self.write_flash(bank, &header)
+    }
+
+    /// Whether the record header at `offset` is erased, or there's no room for one
+    fn is_erased(&mut self, offset: usize) -> Result<bool, FlashStorageError<F::Error>> {
+        if offset + RECORD_HEADER_LEN > self.bank_end() {
+            return Ok(true);
+        }
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        self.read_flash(offset, &mut header)?;
+        Ok(header.iter().all(|&b| b == ERASED))
+    }
+
+    /// The valid record at `offset`, `None` at the end of the log
+    fn record(&mut self, offset: usize) -> Result<Option<Record>, FlashStorageError<F::Error>> {
+        if offset + RECORD_HEADER_LEN > self.bank_end() {
+            return Ok(None);
+        }
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        self.read_flash(offset, &mut header)?;
+        let value = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        let key = match Key::from_raw(header[0], value) {
+            Some(key) if header[1] == KIND_RECORD || header[1] == KIND_REMOVED => key,
+            _ => return Ok(None),
+        };
+        let len = u16::from_le_bytes([header[2], header[3]]) as usize;
+        let data = offset + RECORD_HEADER_LEN;
+        let next = data + padded(len);
+        if next > self.bank_end() {
+            return Ok(None);
+        }
+
+        let mut crc = crc32::update(0, &IEEE_TABLE, &header[..8]);
+        let mut chunk = [0u8; 64];
+        for start in (data..data + len).step_by(chunk.len()) {
+            let chunk = &mut chunk[..(data + len - start).min(64)];
+            self.read_flash(start, chunk)?;
+            crc = crc32::update(crc, &IEEE_TABLE, chunk);
+        }
+        if crc != u32::from_le_bytes([header[8], header[9], header[10], header[11]]) {
+            warn!("Corrupt record in flash storage at {}", offset);
+            return Ok(None);
+        }
+        Ok(Some(Record { key, removed: header[1] == KIND_REMOVED, len, next }))
+    }
+
+    fn position(&self, key: Key) -> Option<usize> {
+        self.index.iter().position(|entry| entry.key == key)
+    }
+
+    /// Replace the entry for `key`, removing it if `entry` is `None`
+    fn update_index(&mut self, key: Key, entry: Option<Entry>) -> Result<(), Entry> {
+        match (self.position(key), entry) {
+            (Some(index), Some(entry)) => self.index[index] = entry,
+            (Some(index), None) => {
+                self.index.swap_remove(index);
+            }
+            (None, Some(entry)) => self.index.push(entry)?,
+            (None, None) => {}
+        }
+        Ok(())
+    }
+
+    /// Copy the live records to the other bank, making it active
+    fn compact(&mut self) -> Result<(), FlashStorageError<F::Error>> {
+        let target = if self.bank == 0 { self.bank_len() } else { 0 };
+        let target_end = target + self.bank_len();
+        self.erase_bank(target)?;
+        let mut to = target + BANK_HEADER_LEN;
+        // the index is only moved once the new bank is active
+        let mut offsets: Vec<usize, MAX_KEYS> = Vec::new();
+        for index in 0..self.index.len() {
+            let entry = self.index[index];
+            let len = RECORD_HEADER_LEN + padded(entry.len);
+            if to + len > target_end {
+                return Err(FlashStorageError::Full);
+            }
+            offsets.push(to).ok();
+            let mut chunk = [0u8; 64];
+            for start in (entry.offset..entry.offset + len).step_by(chunk.len()) {
+                let chunk = &mut chunk[..(entry.offset + len - start).min(64)];
+                self.read_flash(start, chunk)?;
+                self.write_flash(to, chunk)?;
+                to += chunk.len();
+            }
+        }
+
+        let generation = self.generation.wrapping_add(1);
+        self.write_bank_header(target, generation)?;
+        debug!("Compacted flash storage, {} of {} bytes used", to - target, self.bank_len());
+        for (entry, offset) in self.index.iter_mut().zip(offsets) {
+            entry.offset = offset;
+        }
+        self.bank = target;
+        self.generation = generation;
+        self.end = to;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Append a record to the log, compacting first if there's no room, returning its offset
+    fn append(&mut self, key: Key, kind: u8, data: &[u8]) -> Result<usize, FlashStorageError<F::Error>> {
+        let len = RECORD_HEADER_LEN + padded(data.len());
+        if data.len() > u16::MAX as usize || BANK_HEADER_LEN + len > self.bank_len() {
+            return Err(FlashStorageError::Full);
+        }
+        if self.dirty || self.end + len > self.bank_end() {
+            self.compact()?;
+            if self.end + len > self.bank_end() {
+                return Err(FlashStorageError::Full);
+            }
+        }
+
+        let (tag, value) = key.to_raw();
+        let mut header = [ERASED; RECORD_HEADER_LEN];
+        header[0] = tag;
+        header[1] = kind;
+        header[2..4].copy_from_slice(&(data.len() as u16).to_le_bytes());
+        header[4..8].copy_from_slice(&value.to_le_bytes());
+        let crc = crc32::update(crc32::update(0, &IEEE_TABLE, &header[..8]), &IEEE_TABLE, data);
+        header[8..12].copy_from_slice(&crc.to_le_bytes());
+
+        // until the whole record is written, anything after the log must be compacted away
+        self.dirty = true;
+        let offset = self.end;
+        self.write_flash(offset, &header)?;
+        let whole = data.len() - data.len() % WRITE_SIZE;
+        self.write_flash(offset + RECORD_HEADER_LEN, &data[..whole])?;
+        if whole < data.len() {
+            let mut tail = [ERASED; WRITE_SIZE];
+            tail[..data.len() - whole].copy_from_slice(&data[whole..]);
+            self.write_flash(offset + RECORD_HEADER_LEN + whole, &tail)?;
+        }
+        self.end = offset + len;
+        self.dirty = false;
+        Ok(offset)
+    }
+}
+
+impl<F: Flash> Storage for FlashStorage<F> {
+    type Error = FlashStorageError<F::Error>;
+    type Keys = <Vec<Key, MAX_KEYS> as IntoIterator>::IntoIter;
+
+    fn keys(&mut self) -> Result<Self::Keys, Self::Error> {
+        Ok(self.index.iter().map(|entry| entry.key).collect::<Vec<_, MAX_KEYS>>().into_iter())
+    }
+
+    fn read(&mut self, key: Key, buffer: &mut [u8]) -> Result<Option<usize>, Self::Error> {
+        match self.position(key) {
+            Some(index) => {
+                let entry = self.index[index];
+                let out = buffer.get_mut(..entry.len).ok_or(FlashStorageError::BufferTooSmall)?;
+                self.read_flash(entry.offset + RECORD_HEADER_LEN, out)?;
+                Ok(Some(entry.len))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn write(&mut self, key: Key, data: &[u8]) -> Result<(), Self::Error> {
+        if self.position(key).is_none() && self.index.is_full() {
+            return Err(FlashStorageError::TooManyKeys);
+        }
+        let offset = self.append(key, KIND_RECORD, data)?;
+        // there's room, checked above
+        self.update_index(key, Some(Entry { key, offset, len: data.len() })).ok();
+        Ok(())
+    }
+
+    fn remove(&mut self, key: Key) -> Result<(), Self::Error> {
+        if self.position(key).is_some() {
+            self.append(key, KIND_REMOVED, &[])?;
+            self.update_index(key, None).ok();
+        }
+        Ok(())
+    }
+}
+
+/// `len` rounded up to a multiple of [`WRITE_SIZE`]
+fn padded(len: usize) -> usize {
+    len.div_ceil(WRITE_SIZE) * WRITE_SIZE
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::vec::Vec;
+
+    const PAGE_SIZE: usize = 256;
+
+    /// Flash in RAM that checks writes are aligned and only made to erased flash, failing once `budget` bytes
+    /// have been written
+    struct RamFlash {
+        data: Vec<u8>,
+        budget: usize,
+        reads: usize,
+    }
+
+    impl RamFlash {
+        fn new(pages: usize) -> Self {
+            Self { data: vec![0u8; pages * PAGE_SIZE], budget: usize::MAX, reads: 0 }
+        }
+    }
+
+    impl Flash for RamFlash {
+        type Error = ();
+        const PAGE_SIZE: usize = PAGE_SIZE;
+
+        fn capacity(&self) -> usize {
+            self.data.len()
+        }
+
+        fn read(&mut self, offset: usize, buffer: &mut [u8]) -> Result<(), ()> {
+            buffer.copy_from_slice(&self.data[offset..offset + buffer.len()]);
+            self.reads += 1;
+            Ok(())
+        }
+
+        fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), ()> {
+            assert_eq!(offset % WRITE_SIZE, 0);
+            assert_eq!(data.len() % WRITE_SIZE, 0);
+            for (i, &b) in data.iter().enumerate() {
+                if self.budget == 0 {
+                    return Err(());
+                }
+                self.budget -= 1;
+                assert_eq!(self.data[offset + i], ERASED, "write to unerased flash at {}", offset + i);
+                self.data[offset + i] = b;
+            }
+            Ok(())
+        }
+
+        fn erase(&mut self, offset: usize) -> Result<(), ()> {
+            assert_eq!(offset % PAGE_SIZE, 0);
+            self.data[offset..offset + PAGE_SIZE].fill(ERASED);
+            Ok(())
+        }
+    }
+
+    fn read(storage: &mut FlashStorage<RamFlash>, key: Key) -> Option<Vec<u8>> {
+        let mut buffer = [0u8; 128];
+        let len = storage.read(key, &mut buffer).unwrap()?;
+        Some(buffer[..len].to_vec())
+    }
+
+    fn keys(storage: &mut FlashStorage<RamFlash>) -> Vec<Key> {
+        let mut keys: Vec<Key> = storage.keys().unwrap().collect();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn flash_storage_round_trip() {
+        let mut storage = FlashStorage::new(RamFlash::new(4)).unwrap();
+        assert_eq!(read(&mut storage, Key::Settings), None);
+        storage.write(Key::Settings, &[1, 2, 3]).unwrap();
+        storage.write(Key::Notification(7), &[4; 20]).unwrap();
+        storage.write(Key::Settings, &[5, 6]).unwrap();
+        assert_eq!(read(&mut storage, Key::Settings), Some(vec![5, 6]));
+        assert_eq!(keys(&mut storage), [Key::Settings, Key::Notification(7)]);
+        // found from the index, without scanning the log
+        let reads = storage.flash.reads;
+        assert_eq!(read(&mut storage, Key::Notification(7)), Some(vec![4; 20]));
+        assert_eq!(storage.flash.reads, reads + 1);
+
+        storage.remove(Key::Notification(7)).unwrap();
+        storage.remove(Key::Alarms).unwrap();
+        assert_eq!(read(&mut storage, Key::Notification(7)), None);
+        assert_eq!(keys(&mut storage), [Key::Settings]);
+        let mut small = [0u8; 1];
+        assert_eq!(storage.read(Key::Settings, &mut small), Err(FlashStorageError::BufferTooSmall));
+
+        let mut reopened = FlashStorage::new(storage.free()).unwrap();
+        assert_eq!(read(&mut reopened, Key::Settings), Some(vec![5, 6]));
+        assert_eq!(keys(&mut reopened), [Key::Settings]);
+    }
+
+    #[test]
+    fn flash_storage_compacts() {
+        let mut storage = FlashStorage::new(RamFlash::new(4)).unwrap();
+        storage.write(Key::Alarms, &[9; 16]).unwrap();
+        // many times the size of a bank
+        for id in 0..100u32 {
+            storage.write(Key::Notification(id), &id.to_le_bytes()).unwrap();
+            storage.remove(Key::Notification(id.wrapping_sub(2))).unwrap();
+            storage.write(Key::Settings, &[id as u8; 5]).unwrap();
+        }
+        assert!(storage.generation > 0);
+        let mut reopened = FlashStorage::new(storage.free()).unwrap();
+        assert_eq!(keys(&mut reopened), [Key::Settings, Key::Alarms, Key::Notification(98), Key::Notification(99)]);
+        assert_eq!(read(&mut reopened, Key::Alarms), Some(vec![9; 16]));
+        assert_eq!(read(&mut reopened, Key::Notification(98)), Some(98u32.to_le_bytes().to_vec()));
+        assert_eq!(read(&mut reopened, Key::Settings), Some(vec![99; 5]));
+
+        // bigger than a bank
+        assert_eq!(reopened.write(Key::Settings, &[0; 2 * PAGE_SIZE]), Err(FlashStorageError::Full));
+    }
+
+    #[test]
+    fn flash_storage_limits_keys() {
+        let mut storage = FlashStorage::new(RamFlash::new(4)).unwrap();
+        for id in 0..MAX_KEYS as u32 {
+            storage.write(Key::Notification(id), &[]).unwrap();
+        }
+        let key = Key::Notification(MAX_KEYS as u32);
+        assert_eq!(storage.write(key, &[]), Err(FlashStorageError::TooManyKeys));
+        // existing keys can still be replaced, and removing one makes room
+        storage.write(Key::Notification(0), &[1]).unwrap();
+        storage.remove(Key::Notification(0)).unwrap();
+        storage.write(key, &[]).unwrap();
+        assert_eq!(keys(&mut storage).len(), MAX_KEYS);
+    }
+
+    #[test]
+    fn flash_storage_survives_torn_writes() {
+        let mut storage = FlashStorage::new(RamFlash::new(4)).unwrap();
+        storage.write(Key::Settings, &[1; 10]).unwrap();
+        // lose power part way through the record's data
+        storage.flash.budget = RECORD_HEADER_LEN + 8;
+        assert!(storage.write(Key::Alarms, &[2; 30]).is_err());
+
+        let mut flash = storage.free();
+        flash.budget = usize::MAX;
+        let mut reopened = FlashStorage::new(flash).unwrap();
+        assert!(reopened.dirty);
+        assert_eq!(keys(&mut reopened), [Key::Settings]);
+        reopened.write(Key::Alarms, &[3; 30]).unwrap();
+        assert_eq!(read(&mut reopened, Key::Settings), Some(vec![1; 10]));
+        assert_eq!(read(&mut reopened, Key::Alarms), Some(vec![3; 30]));
+
+        // lose power part way through compacting, before the new bank's header is written
+        let mut flash = reopened.free();
+        let mut storage = FlashStorage::new(flash).unwrap();
+        storage.dirty = true;
+        storage.flash.budget = 2 * RECORD_HEADER_LEN;
+        assert!(storage.write(Key::Alarms, &[4; 30]).is_err());
+        flash = storage.free();
+        flash.budget = usize::MAX;
+        let mut reopened = FlashStorage::new(flash).unwrap();
+        assert_eq!(read(&mut reopened, Key::Alarms), Some(vec![3; 30]));
+    }
+}
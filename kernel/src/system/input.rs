@@ -1,7 +1,7 @@
 //! Input
 //!
 //! Here we multiplex all the hardware inputs (3) to create a series of
-//! unique output combinations (7)
+//! unique output combinations (7), plus a long press of each input
 
 pub const LEFT: u8 = 1;
 pub const MIDDLE: u8 = 2;
@@ -12,6 +12,13 @@ pub const LEFT_RIGHT: u8 = LEFT | RIGHT;
 pub const ALL: u8 = LEFT | MIDDLE | RIGHT;
 pub const NONE: u8 = 0;
 
+/// How long, in milliseconds, a single input must be held to produce a long press instead of a short one
+pub const LONG_PRESS_MS: u64 = 800;
+
+/// An input
+///
+/// Combinations are produced as soon as they're touched, single inputs once released or, as a long press, once held
+/// for [`LONG_PRESS_MS`].
 #[repr(C)]
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum InputEvent {
@@ -22,6 +29,10 @@ pub enum InputEvent {
     Multi,
     LeftMiddle,
     RightMiddle,
+    /// Left held for [`LONG_PRESS_MS`], instead of an [`InputEvent::Left`]
+    LongLeft,
+    LongMiddle,
+    LongRight,
 }
 
 pub const MAX_PIN_IDX: u8 = 2;
@@ -39,6 +50,18 @@ pub struct InputManager {
     raw_vector: u8,
     last_vector: u8,
     count: usize,
+    /// The uptime the current input vector began
+    since: u64,
+    /// Whether the current input vector is a single input that hasn't produced an output yet
+    pending: bool,
+    /// A combination has been produced, no single input is produced until every input is released
+    combination: bool,
+}
+
+impl Default for InputManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl InputManager {
@@ -48,6 +71,9 @@ impl InputManager {
             raw_vector: 0,
             last_vector: 0,
             count: 0,
+            since: 0,
+            pending: false,
+            combination: false,
         }
     }
 
@@ -62,23 +88,43 @@ impl InputManager {
     }
 
     /// Based on the current state of the inputmanager's internal vector, produce an output
-    pub fn output(&mut self) -> Result<InputEvent, Error> {
+    ///
+    /// `now` is the [`Uptime`](super::Uptime), used to time long presses.
+    pub fn output(&mut self, now: u64) -> Result<InputEvent, Error> {
         if self.count > MAX_PIN_IDX as usize {
             self.count = 0;
             if self.raw_vector != self.last_vector {
+                self.since = now;
+                // a single input that's released produces its short press, unless it began a combination
+                let released = if self.pending { short_press(self.last_vector) } else { None };
+                self.pending = false;
                 let result = match self.raw_vector {
-                    ALL => Ok(InputEvent::Multi),
-                    LEFT_RIGHT => Ok(InputEvent::Dual),
-                    LEFT_MIDDLE => Ok(InputEvent::LeftMiddle),
-                    RIGHT_MIDDLE => Ok(InputEvent::RightMiddle),
-                    LEFT => Ok(InputEvent::Left),
-                    MIDDLE => Ok(InputEvent::Middle),
-                    RIGHT => Ok(InputEvent::Right),
-                    NONE => Err(Error::NoInput), // no input
+                    ALL | LEFT_RIGHT | LEFT_MIDDLE | RIGHT_MIDDLE => {
+                        self.combination = true;
+                        Ok(combination(self.raw_vector))
+                    }
+                    LEFT | MIDDLE | RIGHT => {
+                        // the inputs left held after a combination are part of it
+                        self.pending = !self.combination;
+                        released.ok_or(Error::NoInput)
+                    }
+                    NONE => {
+                        // no input
+                        self.combination = false;
+                        released.ok_or(Error::NoInput)
+                    }
                     _ => Err(Error::InvalidInputVector(self.raw_vector)),
                 };
                 self.last_vector = self.raw_vector;
                 result
+            } else if self.pending && now.saturating_sub(self.since) >= LONG_PRESS_MS {
+                self.pending = false;
+                match self.raw_vector {
+                    LEFT => Ok(InputEvent::LongLeft),
+                    MIDDLE => Ok(InputEvent::LongMiddle),
+                    RIGHT => Ok(InputEvent::LongRight),
+                    _ => Err(Error::NoInput),
+                }
             } else {
                 Err(Error::NoInput)
             }
@@ -91,3 +137,67 @@ impl InputManager {
         self.count
     }
 }
+
+/// The event of a combination input vector
+fn combination(vector: u8) -> InputEvent {
+    match vector {
+        ALL => InputEvent::Multi,
+        LEFT_RIGHT => InputEvent::Dual,
+        LEFT_MIDDLE => InputEvent::LeftMiddle,
+        _ => InputEvent::RightMiddle,
+    }
+}
+
+/// The short press of a single input vector
+fn short_press(vector: u8) -> Option<InputEvent> {
+    match vector {
+        LEFT => Some(InputEvent::Left),
+        MIDDLE => Some(InputEvent::Middle),
+        RIGHT => Some(InputEvent::Right),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Sample each input at `now`, returning the output
+    fn sample(im: &mut InputManager, vector: u8, now: u64) -> Result<InputEvent, Error> {
+        for pin in 0..=MAX_PIN_IDX {
+            im.update_input(vector & (1 << pin) != 0);
+        }
+        im.output(now)
+    }
+
+    #[test]
+    fn input_long_press() {
+        let mut im = InputManager::new();
+        // a short press is produced on release
+        assert_eq!(sample(&mut im, MIDDLE, 0), Err(Error::NoInput));
+        assert_eq!(sample(&mut im, NONE, LONG_PRESS_MS - 1), Ok(InputEvent::Middle));
+
+        // a long press instead of the short press, only once per press
+        assert_eq!(sample(&mut im, MIDDLE, 0), Err(Error::NoInput));
+        assert_eq!(sample(&mut im, MIDDLE, LONG_PRESS_MS - 1), Err(Error::NoInput));
+        assert_eq!(sample(&mut im, MIDDLE, LONG_PRESS_MS), Ok(InputEvent::LongMiddle));
+        assert_eq!(sample(&mut im, MIDDLE, 2 * LONG_PRESS_MS), Err(Error::NoInput));
+        assert_eq!(sample(&mut im, NONE, 2 * LONG_PRESS_MS), Err(Error::NoInput));
+
+        // combinations are produced at once and don't long press, or produce the inputs that began or ended them
+        assert_eq!(sample(&mut im, LEFT, 0), Err(Error::NoInput));
+        assert_eq!(sample(&mut im, LEFT_RIGHT, 0), Ok(InputEvent::Dual));
+        assert_eq!(sample(&mut im, LEFT_RIGHT, LONG_PRESS_MS), Err(Error::NoInput));
+        assert_eq!(sample(&mut im, RIGHT, LONG_PRESS_MS), Err(Error::NoInput));
+        assert_eq!(sample(&mut im, RIGHT, 2 * LONG_PRESS_MS), Err(Error::NoInput));
+        assert_eq!(sample(&mut im, NONE, 2 * LONG_PRESS_MS), Err(Error::NoInput));
+        assert_eq!(sample(&mut im, LEFT_MIDDLE, 0), Ok(InputEvent::LeftMiddle));
+        assert_eq!(sample(&mut im, MIDDLE, 0), Err(Error::NoInput));
+        assert_eq!(sample(&mut im, NONE, 0), Err(Error::NoInput));
+
+        // once everything is released single inputs work again
+        assert_eq!(sample(&mut im, RIGHT, 0), Err(Error::NoInput));
+        assert_eq!(sample(&mut im, NONE, 0), Ok(InputEvent::Right));
+        assert_eq!(im.output(0), Err(Error::Incomplete));
+    }
+}
@@ -1,22 +1,52 @@
-use time::{Date, Time};
+use time::{Date, Duration, PrimitiveDateTime, Time, UtcOffset};
 
 use crate::application::{application_manager::ApplicationManager, FrameBuffer};
+use crate::egress::egress_manager::EgressManager;
 
 use heapless::String;
 
-use self::{bms::BatteryManagement, notification::NotificationManager};
+use self::{alarm::AlarmManager, bms::BatteryManagement, notification::NotificationManager, storage::{Persisted, Storage}};
 
+pub mod alarm;
 pub mod bms;
+pub mod filter;
+pub mod flash;
 pub mod input;
 pub mod notification;
+pub mod storage;
 pub mod syscall;
 
+/// How long without input, in milliseconds, before the system is idle and the display dims
+pub const IDLE_TIMEOUT_MS: u64 = 15_000;
+
+/// Clock
+///
+/// The date and time kept by the [`Host`], in UTC. See [`System::local_date_time`] for the time shown to the user.
 pub trait Clock {
     fn get_time(&self) -> Time;
     fn set_time(&mut self, t: &Time);
 
     fn get_date(&self) -> Date;
     fn set_date(&mut self, t: &Date);
+
+    fn get_date_time(&self) -> PrimitiveDateTime {
+        PrimitiveDateTime::new(self.get_date(), self.get_time())
+    }
+
+    /// Set the date and time together, override if the clock can set both at once
+    fn set_date_time(&mut self, dt: &PrimitiveDateTime) {
+        self.set_date(&dt.date());
+        self.set_time(&dt.time());
+    }
+}
+
+/// Uptime
+///
+/// A monotonic millisecond counter kept by the [`Host`]. Unlike the [`Clock`] it never jumps when the time is set,
+/// so is used to measure intervals.
+pub trait Uptime {
+    /// Milliseconds since the host started
+    fn millis(&self) -> u64;
 }
 
 /// System
@@ -24,22 +54,73 @@ pub trait Clock {
 /// The [`System`] struct contains the interface to the [`Host`] system. The kernel uses this struct to operate.
 pub struct System<H: Host> {
     pub clock: H::TimeProvider,
+    pub uptime: H::UptimeProvider,
     pub bms: H::BatteryManager,
     pub stats: H::Statistics,
     pub nm: NotificationManager,
+    pub alarms: AlarmManager,
     pub am: ApplicationManager,
+    pub em: EgressManager,
+    pub storage: H::Storage,
+    /// The offset of local time from the UTC [`Clock`]
+    pub utc_offset: UtcOffset,
+    /// The [`Uptime`] of the last input, or the last time the display was woken
+    last_active: u64,
+    persisted: Persisted,
 }
 
 impl<H: Host> System<H> {
-    pub fn new(time: H::TimeProvider, bms: H::BatteryManager, stats: H::Statistics, am: ApplicationManager) -> Self {
-        Self {
+    /// Create the system, restoring anything saved in `storage`
+    pub fn new(
+        time: H::TimeProvider,
+        uptime: H::UptimeProvider,
+        bms: H::BatteryManager,
+        stats: H::Statistics,
+        am: ApplicationManager,
+        storage: H::Storage,
+    ) -> Self {
+        let last_active = uptime.millis();
+        let mut system = Self {
             clock: time,
+            uptime,
             bms,
             stats,
             am,
             nm: NotificationManager::new(),
+            alarms: AlarmManager::new(),
+            em: EgressManager::new(),
+            storage,
+            utc_offset: UtcOffset::UTC,
+            last_active,
+            persisted: Persisted::default(),
+        };
+        system.restore();
+        system
+    }
+
+    /// The local date and time, the clock in UTC adjusted by the [`System::utc_offset`]
+    pub fn local_date_time(&self) -> PrimitiveDateTime {
+        let utc = self.clock.get_date_time();
+        // NOTE: only out of range at the very limits of the calendar
+        utc.checked_add(Duration::seconds(self.utc_offset.whole_seconds() as i64)).unwrap_or(utc)
+    }
+
+    /// Housekeeping, called every systick: rings any alarms or timers that are due, waking the display
+    pub fn process(&mut self) {
+        if self.alarms.check(self.local_date_time(), self.clock.get_date_time()) {
+            self.wake();
         }
     }
+
+    /// Restart the idle timeout, on input or to show an alert
+    pub fn wake(&mut self) {
+        self.last_active = self.uptime.millis();
+    }
+
+    /// Has there been no input for [`IDLE_TIMEOUT_MS`]?
+    pub fn is_idle(&self) -> bool {
+        self.uptime.millis().saturating_sub(self.last_active) >= IDLE_TIMEOUT_MS
+    }
 }
 
 /// Host
@@ -49,8 +130,10 @@ impl<H: Host> System<H> {
 pub trait Host {
     type BatteryManager: BatteryManagement;
     type TimeProvider: Clock;
+    type UptimeProvider: Uptime;
     type Statistics: Statistics;
     type Display: Display;
+    type Storage: Storage;
 }
 
 /// Display
@@ -66,7 +149,6 @@ pub trait Statistics {
 
     fn stats(&self) -> Self::Statistics;
 
-    fn is_idle(&mut self) -> bool {
-        false
-    }
+    /// Called with the total number of bytes the ingress manager has dropped due to a full ring buffer
+    fn ingress_dropped(&mut self, _total: u32) {}
 }
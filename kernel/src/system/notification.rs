@@ -1,24 +1,114 @@
 //! Notification
 //! 
 //! Push notification parsing
+//!
+//! A notification packet is a list of fields, each preceded by a unit separator. A field may start with a tag
+//! byte identifying it (see [`Field`]), otherwise it is positional: the first untagged field is the source,
+//! then the title, then the body. This keeps the original `source, title, body` format working.
+//!
+//! ```text
+//! STX -> N -> (US -> [TAG] -> DATA)* -> ETX
+//! ```
 
-use crate::ingress::buffer::Buffer;
+use crate::ingress::buffer::{Buffer, Type};
+use crate::system::filter::NotificationFilter;
+use crate::ingress::ingress_manager::PAYLOAD;
+use time::{Date, Duration, PrimitiveDateTime, Time, UtcOffset};
 
 pub const BUFF_SIZE: usize = 512;
 pub const BUFF_COUNT: usize = 4;
 
+/// Identifies a notification for as long as it is stored
+pub type Id = u32;
+
+/// Bytes preceding the payload of a serialized notification: id, read, julian day and time
+const HEADER_LEN: usize = 4 + 1 + 4 + 3;
+/// The largest serialized notification, see [`Notification::serialize`]
+pub const SERIALIZED_LEN: usize = HEADER_LEN + BUFF_SIZE;
+
+/// The fields of a notification, the discriminant is the tag byte used in the protocol
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Field {
+    /// What generated the notification, i.e the app name
+    Source = 0x11,
+    Title = 0x12,
+    Body = 0x13,
+    /// Who the notification is from
+    Sender = 0x14,
+    /// Identifier of the app on the phone, i.e `com.facebook.orca`
+    AppId = 0x15,
+    /// `0` low, `1` normal or `2` high
+    Priority = 0x16,
+    /// Labels of the actions that can be taken, separated by [`ACTION_SEPARATOR`]
+    Actions = 0x17,
+    /// Opaque reference to the notification on the phone, sent back when an action is taken
+    Key = 0x18,
+}
+
+const FIELD_COUNT: usize = 8;
+
+/// Separates the labels in the [`Field::Actions`] field (ascii RS)
+pub const ACTION_SEPARATOR: u8 = 30;
+/// The maximum number of actions, any more are ignored
+pub const MAX_ACTIONS: usize = 3;
+
+/// The order of untagged fields
+const POSITIONAL: [Field; 3] = [Field::Source, Field::Title, Field::Body];
+
+impl Field {
+    /// The field for a tag byte
+    pub fn from_tag(tag: u8) -> Option<Field> {
+        match tag {
+            0x11 => Some(Field::Source),
+            0x12 => Some(Field::Title),
+            0x13 => Some(Field::Body),
+            0x14 => Some(Field::Sender),
+            0x15 => Some(Field::AppId),
+            0x16 => Some(Field::Priority),
+            0x17 => Some(Field::Actions),
+            0x18 => Some(Field::Key),
+            _ => None,
+        }
+    }
+
+    pub fn tag(self) -> u8 {
+        self as u8
+    }
+
+    fn index(self) -> usize {
+        (self as u8 - Field::Source as u8) as usize
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
 #[derive(Copy, Clone)]
 pub struct Notification {
-    section_indexes: [usize; 3],
+    id: Id,
+    read: bool,
+    /// When the notification was received
+    timestamp: PrimitiveDateTime,
+    /// The start and end of each field within the buffer
+    fields: [Option<(usize, usize)>; FIELD_COUNT],
     inner: Buffer,
 }
 
 impl Notification {
     pub const fn default() -> Notification {
         Notification {
-            section_indexes: [0usize; 3],
+            id: 0,
+            read: false,
+            timestamp: PrimitiveDateTime::new(Date::MIN, Time::MIDNIGHT),
+            fields: [None; FIELD_COUNT],
             inner: Buffer {
                 btype: crate::ingress::buffer::Type::Unknown,
+                encoding: crate::ingress::buffer::Encoding::Hex,
                 payload: [0u8; BUFF_SIZE],
                 payload_idx: 0,
             },
@@ -29,23 +119,161 @@ impl Notification {
         &self.inner.payload[..self.inner.payload_idx]
     }
 
-    pub fn from_buffer(buffer: &Buffer, idxs: &[usize; 3]) -> Result<Notification, NotificationError> {
-        Ok(Notification {
-            section_indexes: idxs.clone(),
-            inner: buffer.clone()
-        })
+    /// Parse a notification from the payload of a notification packet, unit separators included
+    ///
+    /// Anything that isn't valid UTF-8 is replaced with `?`.
+    pub fn from_buffer(buffer: &Buffer) -> Result<Notification, NotificationError> {
+        let mut notification = Notification {
+            fields: [None; FIELD_COUNT],
+            inner: *buffer,
+            ..Notification::default()
+        };
+        let payload = buffer.as_slice();
+        if payload.first() != Some(&PAYLOAD) {
+            return Err(NotificationError::Parsing);
+        }
+
+        let mut position = 0;
+        let mut start = 1;
+        for section in payload[1..].split(|b| *b == PAYLOAD) {
+            let end = start + section.len();
+            let field = match section.first().copied().and_then(Field::from_tag) {
+                Some(field) => Some((field, start + 1)),
+                None => {
+                    position += 1;
+                    POSITIONAL.get(position - 1).map(|field| (*field, start))
+                }
+            };
+            match field {
+                Some((field, start)) => {
+                    sanitize(&mut notification.inner.payload[start..end]);
+                    notification.fields[field.index()] = Some((start, end));
+                }
+                None => warn!("Ignoring extra notification field at {}", start),
+            }
+            start = end + 1;
+        }
+        Ok(notification)
+    }
+
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    /// When the notification was received
+    pub fn timestamp(&self) -> PrimitiveDateTime {
+        self.timestamp
+    }
+
+    /// Has the notification been opened?
+    pub fn is_read(&self) -> bool {
+        self.read
+    }
+
+    /// The contents of a field, if it was sent
+    pub fn field(&self, field: Field) -> Option<&str> {
+        let (start, end) = self.fields[field.index()]?;
+        // NOTE(ok): fields are sanitized when the notification is created
+        core::str::from_utf8(&self.inner.payload[start..end]).ok()
     }
 
     pub fn source(&self) -> &str {
-        unsafe { core::str::from_utf8_unchecked(&self.inner.payload[0..self.section_indexes[1]]) }
+        self.field(Field::Source).unwrap_or("")
     }
 
     pub fn title(&self) -> &str {
-        unsafe { core::str::from_utf8_unchecked(&self.inner.payload[self.section_indexes[0]..self.section_indexes[1]]) }
+        self.field(Field::Title).unwrap_or("")
     }
 
     pub fn body(&self) -> &str {
-        unsafe { core::str::from_utf8_unchecked(&self.inner.payload[self.section_indexes[1]..self.section_indexes[2]]) }
+        self.field(Field::Body).unwrap_or("")
+    }
+
+    pub fn sender(&self) -> Option<&str> {
+        self.field(Field::Sender)
+    }
+
+    pub fn app_id(&self) -> Option<&str> {
+        self.field(Field::AppId)
+    }
+
+    /// The labels of the actions that can be taken, an action's id is its index
+    ///
+    /// Actions are only offered with a [`Notification::key`], otherwise the phone couldn't tell which
+    /// notification they were taken on.
+    pub fn actions(&self) -> impl Iterator<Item = &str> {
+        self.field(Field::Actions)
+            .filter(|actions| !actions.is_empty() && self.key().is_some())
+            .into_iter()
+            .flat_map(|actions| actions.split(ACTION_SEPARATOR as char))
+            .take(MAX_ACTIONS)
+    }
+
+    /// The phone's key for the notification, sent back with any action taken on it
+    pub fn key(&self) -> Option<&str> {
+        self.field(Field::Key)
+    }
+
+    /// The priority of the notification, normal unless specified
+    pub fn priority(&self) -> Priority {
+        match self.field(Field::Priority) {
+            Some("0") => Priority::Low,
+            Some("2") => Priority::High,
+            _ => Priority::Normal,
+        }
+    }
+}
+
+impl Notification {
+    /// Write the notification into `out` for storage, returning the length
+    ///
+    /// `out` must be at least [`SERIALIZED_LEN`] bytes.
+    pub fn serialize(&self, out: &mut [u8]) -> usize {
+        let payload = self.buffer();
+        out[..4].copy_from_slice(&self.id.to_le_bytes());
+        out[4] = self.read as u8;
+        out[5..9].copy_from_slice(&self.timestamp.date().to_julian_day().to_le_bytes());
+        let (hour, minute, second) = self.timestamp.time().as_hms();
+        out[9..HEADER_LEN].copy_from_slice(&[hour, minute, second]);
+        out[HEADER_LEN..HEADER_LEN + payload.len()].copy_from_slice(payload);
+        HEADER_LEN + payload.len()
+    }
+
+    /// Read back a notification written by [`Notification::serialize`]
+    pub fn deserialize(data: &[u8]) -> Result<Notification, NotificationError> {
+        if data.len() < HEADER_LEN || data.len() > SERIALIZED_LEN {
+            return Err(NotificationError::Parsing);
+        }
+        let mut buffer = Buffer {
+            btype: Type::Notification,
+            ..Buffer::default()
+        };
+        for byte in &data[HEADER_LEN..] {
+            // NOTE(unwrap): the length was checked above
+            buffer.write(*byte).unwrap();
+        }
+        let mut notification = Notification::from_buffer(&buffer)?;
+        let mut word = [0u8; 4];
+        word.copy_from_slice(&data[..4]);
+        notification.id = Id::from_le_bytes(word);
+        notification.read = data[4] != 0;
+        word.copy_from_slice(&data[5..9]);
+        let date = Date::from_julian_day(i32::from_le_bytes(word)).map_err(|_| NotificationError::Parsing)?;
+        let time = Time::from_hms(data[9], data[10], data[11]).map_err(|_| NotificationError::Parsing)?;
+        notification.timestamp = PrimitiveDateTime::new(date, time);
+        Ok(notification)
+    }
+}
+
+/// Replace any invalid UTF-8 sequences with `?`, in place
+fn sanitize(bytes: &mut [u8]) {
+    let mut idx = 0;
+    while let Err(e) = core::str::from_utf8(&bytes[idx..]) {
+        let start = idx + e.valid_up_to();
+        // a sequence cut short by the end of the section has no error length
+        let len = e.error_len().unwrap_or(bytes.len() - start);
+        bytes[start..start + len].fill(b'?');
+        idx = start + len;
     }
 }
 
@@ -54,45 +282,341 @@ pub enum NotificationError {
     Parsing,
 }
 
+/// A ring of the most recent notifications
+///
+/// Once full, adding a notification evicts the oldest one.
 pub struct NotificationManager {
     pool: [Notification; BUFF_COUNT],
-    idx: usize,
+    /// Index of the oldest notification in the pool
+    head: usize,
+    len: usize,
+    next_id: Id,
+    /// The most recent high priority notification, that hasn't been alerted yet
+    alert: Option<Id>,
+    /// Decides which notifications may alert
+    pub filter: NotificationFilter,
+}
+
+impl Default for NotificationManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl NotificationManager {
     pub fn new() -> NotificationManager {
         NotificationManager {
             pool: [Notification::default(); BUFF_COUNT],
-            idx: 0,
+            head: 0,
+            len: 0,
+            next_id: 0,
+            alert: None,
+            filter: NotificationFilter::new(),
+        }
+    }
+
+    /// Number of stored notifications
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of notifications that haven't been read
+    pub fn unread(&self) -> usize {
+        self.iter().filter(|n| !n.is_read()).count()
+    }
+
+    /// The notification at `index`, oldest first
+    pub fn get(&self, index: usize) -> Option<&Notification> {
+        if index < self.len {
+            Some(&self.pool[self.slot(index)])
+        } else {
+            None
+        }
+    }
+
+    /// Find a notification by its id
+    pub fn find(&self, id: Id) -> Option<&Notification> {
+        self.iter().find(|n| n.id == id)
+    }
+
+    /// The notification at `index`, newest first
+    pub fn get_newest(&self, index: usize) -> Option<&Notification> {
+        self.iter().rev().nth(index)
+    }
+
+    /// Iterate over the notifications, oldest first
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Notification> + ExactSizeIterator {
+        (0..self.len).map(move |index| &self.pool[self.slot(index)])
+    }
+
+    // Parses a buffer for notification info, copying into the pool, returning its id
+    //
+    // The timestamp is in UTC, the offset to local time decides whether it arrived during quiet hours.
+    pub fn add(&mut self, buffer: &Buffer, timestamp: PrimitiveDateTime, utc_offset: UtcOffset) -> Result<Id, NotificationError> {
+        let mut notification = Notification::from_buffer(buffer)?;
+        notification.id = self.next_id;
+        notification.timestamp = timestamp;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.push(notification);
+        let local = timestamp.checked_add(Duration::seconds(utc_offset.whole_seconds() as i64)).unwrap_or(timestamp);
+        if notification.priority() == Priority::High && self.filter.allows(notification.source(), local.time()) {
+            self.alert = Some(notification.id);
         }
+        Ok(notification.id)
     }
 
-    /// takes a closure to execute on the buffer
-    pub fn peek_notification<F>(&mut self, index: usize, f: F)
-    where
-        F: FnOnce(&Notification),
-    {
-        let notification = &self.pool[index];
-        f(&notification);
+    /// Append a previously stored notification, as the newest
+    pub fn restore(&mut self, notification: Notification) {
+        // keep ids unique
+        self.next_id = self.next_id.max(notification.id.wrapping_add(1));
+        self.push(notification);
     }
 
-    pub fn idx(&self) -> usize {
-        self.idx
+    fn push(&mut self, notification: Notification) {
+        if self.len == BUFF_COUNT {
+            // evict the oldest
+            self.head = (self.head + 1) % BUFF_COUNT;
+            self.len -= 1;
+        }
+        let slot = self.slot(self.len);
+        self.pool[slot] = notification;
+        self.len += 1;
     }
 
-    // Parses a buffer for notification info, copying into the pool
-    pub fn add(&mut self, buffer: &Buffer, idxs: &[usize; 3]) -> Result<(), NotificationError> {
-        self.pool[self.idx] = Notification::from_buffer(buffer, idxs)?;
+    /// Take the high priority notification that should be alerted, if there is one
+    pub fn take_alert(&mut self) -> Option<Id> {
+        let id = self.alert.take()?;
+        // it may have been evicted or dismissed since
+        self.position(id).map(|_| id)
+    }
 
-        self.idx += 1;
-        if self.idx + 1 > self.pool.len() {
-            // TODO impl a cirucular buffer that track head and tail
-            /* buffer is full, wrap around */
+    /// Mark a notification as read, returns false if it doesn't exist
+    pub fn mark_read(&mut self, id: Id) -> bool {
+        match self.position(id) {
+            Some(index) => {
+                let slot = self.slot(index);
+                self.pool[slot].read = true;
+                true
+            }
+            None => false,
+        }
+    }
 
-            self.idx = 0;
+    /// Remove a notification, returns false if it doesn't exist
+    pub fn dismiss(&mut self, id: Id) -> bool {
+        let index = match self.position(id) {
+            Some(index) => index,
+            None => return false,
+        };
+        // close the gap, keeping the order
+        for i in index..self.len - 1 {
+            let (to, from) = (self.slot(i), self.slot(i + 1));
+            self.pool[to] = self.pool[from];
         }
-        Ok(())
+        self.len -= 1;
+        true
+    }
+
+    /// Remove all notifications
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+        self.alert = None;
+    }
+
+    fn position(&self, id: Id) -> Option<usize> {
+        self.iter().position(|n| n.id == id)
+    }
+
+    /// The pool index of the notification at `index`
+    fn slot(&self, index: usize) -> usize {
+        (self.head + index) % BUFF_COUNT
     }
 }
 
-// TODO testing of parsing
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const US: u8 = PAYLOAD;
+
+    fn buffer(data: &[u8]) -> Buffer {
+        let mut buffer = Buffer::default();
+        for byte in data {
+            buffer.write(*byte).unwrap();
+        }
+        buffer
+    }
+
+    /// The payload of a notification packet made of `fields`
+    fn packet(fields: &[&[u8]]) -> Buffer {
+        let mut data = std::vec::Vec::new();
+        for field in fields {
+            data.push(US);
+            data.extend_from_slice(field);
+        }
+        buffer(&data)
+    }
+
+    #[test]
+    fn notification_positional_fields() {
+        let n = Notification::from_buffer(&packet(&[b"Messenger", b"Scott", "H\u{e9}llo \u{1F600}".as_bytes()])).unwrap();
+        assert_eq!(n.source(), "Messenger");
+        assert_eq!(n.title(), "Scott");
+        assert_eq!(n.body(), "H\u{e9}llo \u{1F600}");
+        assert_eq!(n.sender(), None);
+        assert_eq!(n.priority(), Priority::Normal);
+        assert_eq!(n.actions().count(), 0);
+    }
+
+    #[test]
+    fn notification_tagged_fields() {
+        let n = Notification::from_buffer(&packet(&[
+            b"\x15com.facebook.orca",
+            b"\x13Hello world",
+            b"\x14Scott",
+            b"\x16\x32",
+            b"\x12Chat",
+        ]))
+        .unwrap();
+        assert_eq!(n.source(), "");
+        assert_eq!(n.title(), "Chat");
+        assert_eq!(n.body(), "Hello world");
+        assert_eq!(n.sender(), Some("Scott"));
+        assert_eq!(n.app_id(), Some("com.facebook.orca"));
+        assert_eq!(n.priority(), Priority::High);
+        assert_eq!(n.field(Field::Source), None);
+    }
+
+    #[test]
+    fn notification_mixed_fields() {
+        // tagged fields don't take a position
+        let n = Notification::from_buffer(&packet(&[b"Slack", b"\x160", b"Team", b"Standup", b"extra", b""])).unwrap();
+        assert_eq!((n.source(), n.title(), n.body()), ("Slack", "Team", "Standup"));
+        assert_eq!(n.priority(), Priority::Low);
+    }
+
+    #[test]
+    fn notification_actions() {
+        let n = Notification::from_buffer(&packet(&[b"Messenger", b"Scott", b"Pub?", b"\x17Yes\x1eNo\x1e\x1eLater\x1eMaybe", b"\x18k42"])).unwrap();
+        let actions: std::vec::Vec<&str> = n.actions().collect();
+        // empty labels keep their place, so ids match the sender's
+        assert_eq!(actions, ["Yes", "No", ""]);
+        assert_eq!(n.key(), Some("k42"));
+        let n = Notification::from_buffer(&packet(&[b"Messenger", b"\x17", b"\x18k42"])).unwrap();
+        assert_eq!(n.actions().count(), 0);
+        // without a key there's no way to send them
+        let n = Notification::from_buffer(&packet(&[b"Messenger", b"\x17Yes\x1eNo"])).unwrap();
+        assert_eq!(n.actions().count(), 0);
+    }
+
+    #[test]
+    fn notification_empty_fields() {
+        let n = Notification::from_buffer(&packet(&[b"", b"", b""])).unwrap();
+        assert_eq!(n.field(Field::Title), Some(""));
+        assert_eq!(n.body(), "");
+    }
+
+    #[test]
+    fn notification_invalid_utf8_replaced() {
+        // a lone continuation byte, and an emoji cut short
+        let n = Notification::from_buffer(&packet(&[b"", b"", b"ab\x80c\xF0\x9F\x98"])).unwrap();
+        assert_eq!(n.body(), "ab?c???");
+    }
+
+    #[test]
+    fn notification_without_fields() {
+        assert_eq!(Notification::from_buffer(&buffer(b"")).err(), Some(NotificationError::Parsing));
+        assert_eq!(Notification::from_buffer(&buffer(b"abc")).err(), Some(NotificationError::Parsing));
+    }
+
+    fn add(nm: &mut NotificationManager, title: &str) -> Id {
+        let now = PrimitiveDateTime::new(Date::MIN, Time::MIDNIGHT);
+        nm.add(&packet(&[b"src", title.as_bytes(), b"body"]), now, UtcOffset::UTC).unwrap()
+    }
+
+    fn titles(nm: &NotificationManager) -> std::vec::Vec<&str> {
+        nm.iter().map(|n| n.title()).collect()
+    }
+
+    #[test]
+    fn notification_manager_evicts_oldest() {
+        let mut nm = NotificationManager::new();
+        let ids: std::vec::Vec<Id> = ["a", "b", "c", "d", "e", "f"].iter().map(|t| add(&mut nm, t)).collect();
+        assert_eq!(ids, [0, 1, 2, 3, 4, 5]);
+        assert_eq!(nm.len(), BUFF_COUNT);
+        assert_eq!(titles(&nm), ["c", "d", "e", "f"]);
+        assert_eq!(nm.get(0).map(|n| n.id()), Some(2));
+        assert_eq!(nm.get_newest(0).map(|n| n.id()), Some(5));
+        assert!(nm.find(1).is_none());
+    }
+
+    #[test]
+    fn notification_manager_read_and_dismiss() {
+        let mut nm = NotificationManager::new();
+        for title in ["a", "b", "c", "d", "e"] {
+            add(&mut nm, title);
+        }
+        assert_eq!(nm.unread(), 4);
+        assert!(nm.mark_read(3));
+        assert!(nm.find(3).unwrap().is_read());
+        assert_eq!(nm.unread(), 3);
+
+        assert!(nm.dismiss(2));
+        assert!(!nm.dismiss(2));
+        assert_eq!(titles(&nm), ["b", "d", "e"]);
+        add(&mut nm, "f");
+        add(&mut nm, "g");
+        assert_eq!(titles(&nm), ["d", "e", "f", "g"]);
+        assert_eq!(nm.unread(), 3);
+
+        nm.clear();
+        assert!(nm.is_empty());
+        assert!(nm.get(0).is_none());
+    }
+
+    #[test]
+    fn notification_manager_alerts_high_priority() {
+        let mut nm = NotificationManager::new();
+        let now = PrimitiveDateTime::new(Date::MIN, Time::MIDNIGHT);
+        add(&mut nm, "normal");
+        assert_eq!(nm.take_alert(), None);
+        let id = nm.add(&packet(&[b"src", b"urgent", b"body", b"\x162"]), now, UtcOffset::UTC).unwrap();
+        assert_eq!(nm.take_alert(), Some(id));
+        // only alerted once
+        assert_eq!(nm.take_alert(), None);
+
+        let id = nm.add(&packet(&[b"src", b"urgent", b"body", b"\x162"]), now, UtcOffset::UTC).unwrap();
+        nm.dismiss(id);
+        assert_eq!(nm.take_alert(), None);
+
+        // filtered notifications are stored, but not alerted
+        nm.filter.mute("src").unwrap();
+        let id = nm.add(&packet(&[b"src", b"urgent", b"body", b"\x162"]), now, UtcOffset::UTC).unwrap();
+        assert_eq!(nm.take_alert(), None);
+        assert!(nm.find(id).is_some());
+    }
+
+    #[test]
+    fn notification_serialize() {
+        let mut nm = NotificationManager::new();
+        add(&mut nm, "a");
+        let id = add(&mut nm, "b");
+        nm.mark_read(id);
+
+        let mut out = [0u8; SERIALIZED_LEN];
+        let len = nm.get(1).unwrap().serialize(&mut out);
+        let n = Notification::deserialize(&out[..len]).unwrap();
+        assert_eq!((n.id(), n.is_read(), n.title()), (id, true, "b"));
+        assert_eq!(n.timestamp(), nm.get(1).unwrap().timestamp());
+        assert!(Notification::deserialize(&out[..4]).is_err());
+
+        let mut restored = NotificationManager::new();
+        restored.restore(n);
+        assert_eq!(add(&mut restored, "c"), id + 1);
+    }
+}
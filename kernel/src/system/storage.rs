@@ -0,0 +1,247 @@
+//! Storage
+//!
+//! Keeps the notifications, alarms and user settings across a reset. The [`Host`] provides a non volatile key/value
+//! [`Storage`], i.e a region of flash, and the kernel saves each record to it whenever it changes.
+//!
+//! Every record starts with a version byte, records with a different version are ignored when restoring.
+//! The settings record is the UTC offset in seconds (`i32` LE) followed by the notification filter. Notifications
+//! are stored under their [`Id`], so adding or dismissing one only writes that record.
+
+use crate::system::alarm;
+use crate::system::filter::{self, NotificationFilter};
+use crate::system::notification::{self, Id, Notification, BUFF_COUNT};
+use heapless::Vec;
+use time::UtcOffset;
+
+use super::{Host, System};
+
+/// The version of the stored records
+pub const VERSION: u8 = 2;
+/// The largest record, including its version
+pub const MAX_RECORD_LEN: usize = 1 + notification::SERIALIZED_LEN;
+
+/// Identifies a record in [`Storage`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Key {
+    /// User settings, the UTC offset and notification filter
+    Settings,
+    /// The alarms, see [`AlarmManager::serialize`](crate::system::alarm::AlarmManager::serialize)
+    Alarms,
+    /// A stored notification, by its id
+    Notification(Id),
+}
+
+impl Key {
+    /// The key as a tag and value, for storage that can't store the enum directly
+    pub fn to_raw(self) -> (u8, u32) {
+        match self {
+            Key::Settings => (0, 0),
+            Key::Alarms => (1, 0),
+            Key::Notification(id) => (2, id),
+        }
+    }
+
+    /// The key from a tag and value made by [`Key::to_raw`]
+    pub fn from_raw(tag: u8, value: u32) -> Option<Key> {
+        match (tag, value) {
+            (0, 0) => Some(Key::Settings),
+            (1, 0) => Some(Key::Alarms),
+            (2, id) => Some(Key::Notification(id)),
+            _ => None,
+        }
+    }
+}
+
+/// Storage
+///
+/// Implement to persist the kernel's records, keyed by a [`Key`].
+pub trait Storage {
+    type Error: core::fmt::Debug;
+    type Keys: Iterator<Item = Key>;
+
+    /// The keys that have a record stored under them
+    fn keys(&mut self) -> Result<Self::Keys, Self::Error>;
+
+    /// Read the record stored under `key` into `buffer`, returning its length or `None` if there isn't one
+    fn read(&mut self, key: Key, buffer: &mut [u8]) -> Result<Option<usize>, Self::Error>;
+
+    /// Store a record under `key`, replacing any existing record
+    fn write(&mut self, key: Key, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Remove the record stored under `key`, if there is one
+    fn remove(&mut self, key: Key) -> Result<(), Self::Error>;
+}
+
+/// Checksums of what was last written to storage, so only records that have changed are written
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Persisted {
+    settings: Option<u32>,
+    alarms: Option<u32>,
+    /// The id and checksum of each stored notification
+    notifications: Vec<(Id, u32), BUFF_COUNT>,
+}
+
+impl<H: Host> System<H> {
+    /// Load the notifications, alarms and settings saved by [`System::sync`]
+    pub fn restore(&mut self) {
+        let mut buffer = [0u8; MAX_RECORD_LEN];
+        if let Some(record) = read(&mut self.storage, Key::Settings, &mut buffer) {
+            let offset = record
+                .get(..4)
+                .and_then(|seconds| UtcOffset::from_whole_seconds(i32::from_le_bytes(seconds.try_into().ok()?)).ok());
+            let filter = record.get(4..).and_then(NotificationFilter::deserialize);
+            match (offset, filter) {
+                (Some(offset), Some(filter)) => {
+                    self.persisted.settings = Some(checksum(record));
+                    self.utc_offset = offset;
+                    self.nm.filter = filter;
+                }
+                _ => warn!("Ignoring corrupt settings"),
+            }
+        }
+        if let Some(record) = read(&mut self.storage, Key::Alarms, &mut buffer) {
+            if self.alarms.deserialize(record) {
+                self.persisted.alarms = Some(checksum(record));
+            } else {
+                warn!("Ignoring corrupt alarms");
+            }
+        }
+        for id in self.stored_notifications() {
+            let key = Key::Notification(id);
+            let notification = read(&mut self.storage, key, &mut buffer)
+                .map(|record| (Notification::deserialize(record), checksum(record)));
+            match notification {
+                Some((Ok(notification), crc)) if notification.id() == id => {
+                    // never more than BUFF_COUNT, see `stored_notifications`
+                    self.persisted.notifications.push((id, crc)).ok();
+                    self.nm.restore(notification);
+                }
+                _ => {
+                    warn!("Removing unreadable notification {:?}", key);
+                    remove(&mut self.storage, key);
+                }
+            }
+        }
+        info!("Restored {} notifications", self.nm.len());
+    }
+
+    /// Save any notifications, alarms and settings that have changed since they were last saved
+    pub fn sync(&mut self) {
+        let mut buffer = [0u8; MAX_RECORD_LEN];
+        buffer[0] = VERSION;
+        buffer[1..5].copy_from_slice(&self.utc_offset.whole_seconds().to_le_bytes());
+        let len = self.nm.filter.serialize(&mut buffer[5..5 + filter::SERIALIZED_LEN]);
+        let settings = Some(&buffer[..5 + len]);
+        save(&mut self.storage, Key::Settings, settings, &mut self.persisted.settings);
+
+        // there's nothing worth storing until an alarm is set
+        let alarms = self.alarms.alarms().next().is_some().then(|| {
+            let len = self.alarms.serialize(&mut buffer[1..1 + alarm::SERIALIZED_LEN]);
+            &buffer[..1 + len]
+        });
+        save(&mut self.storage, Key::Alarms, alarms, &mut self.persisted.alarms);
+
+        // forget dismissed and evicted notifications, retrying at the next sync if that fails
+        let (nm, storage) = (&self.nm, &mut self.storage);
+        self.persisted
+            .notifications
+            .retain(|&(id, _)| nm.find(id).is_some() || !remove(storage, Key::Notification(id)));
+
+        for notification in self.nm.iter() {
+            let id = notification.id();
+            let position = self.persisted.notifications.iter().position(|&(stored, _)| stored == id);
+            let mut crc = position.map(|index| self.persisted.notifications[index].1);
+            let len = notification.serialize(&mut buffer[1..]);
+            save(&mut self.storage, Key::Notification(id), Some(&buffer[..1 + len]), &mut crc);
+            match (position, crc) {
+                (Some(index), Some(crc)) => self.persisted.notifications[index].1 = crc,
+                (None, Some(crc)) => self
+                    .persisted
+                    .notifications
+                    .push((id, crc))
+                    .unwrap_or_else(|_| warn!("Not tracking notification {}, waiting on removals", id)),
+                _ => {}
+            }
+        }
+    }
+
+    /// The ids of the newest [`BUFF_COUNT`] stored notifications, oldest first, removing any older ones
+    fn stored_notifications(&mut self) -> Vec<Id, BUFF_COUNT> {
+        let keys = match self.storage.keys() {
+            Ok(keys) => keys,
+            Err(e) => {
+                error!("Failed to list stored records: {:?}", e);
+                return Vec::new();
+            }
+        };
+        let mut ids: Vec<Id, BUFF_COUNT> = Vec::new();
+        for key in keys {
+            let id = match key {
+                Key::Notification(id) => id,
+                _ => continue,
+            };
+            if ids.is_full() {
+                if id < ids[0] {
+                    remove(&mut self.storage, key);
+                    continue;
+                }
+                let oldest = ids.remove(0);
+                remove(&mut self.storage, Key::Notification(oldest));
+            }
+            let index = ids.iter().position(|&other| other > id).unwrap_or(ids.len());
+            // there's room, one was just removed if it was full
+            ids.insert(index, id).ok();
+        }
+        ids
+    }
+}
+
+/// Read a record, without its version
+fn read<'a>(storage: &mut impl Storage, key: Key, buffer: &'a mut [u8]) -> Option<&'a [u8]> {
+    match storage.read(key, buffer) {
+        Ok(Some(len)) => match buffer.get(..len) {
+            Some([VERSION, record @ ..]) => Some(record),
+            _ => {
+                warn!("Ignoring {:?}, unsupported version", key);
+                None
+            }
+        },
+        Ok(None) => None,
+        Err(e) => {
+            error!("Failed to read {:?}: {:?}", key, e);
+            None
+        }
+    }
+}
+
+/// Write `record` if it has changed, or remove it if it's `None`
+fn save(storage: &mut impl Storage, key: Key, record: Option<&[u8]>, persisted: &mut Option<u32>) {
+    // the version isn't part of the checksum, matching `read`
+    let crc = record.map(|record| checksum(&record[1..]));
+    if crc == *persisted {
+        return;
+    }
+    let result = match record {
+        Some(record) => storage.write(key, record),
+        None => storage.remove(key),
+    };
+    match result {
+        Ok(()) => *persisted = crc,
+        Err(e) => error!("Failed to save {:?}: {:?}", key, e),
+    }
+}
+
+/// Remove a record, returning whether it succeeded
+fn remove(storage: &mut impl Storage, key: Key) -> bool {
+    match storage.remove(key) {
+        Ok(()) => true,
+        Err(e) => {
+            error!("Failed to remove {:?}: {:?}", key, e);
+            false
+        }
+    }
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    crc::crc32::checksum_ieee(data)
+}
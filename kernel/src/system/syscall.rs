@@ -3,12 +3,19 @@
 //! All possible system calls via the serial interface will be parsed and executed here
 
 
+use core::fmt::{Display, Write};
 use core::str::FromStr;
 
+use heapless::String;
+use time::{Date, Duration, PrimitiveDateTime, Time, UtcOffset, Weekday};
 
-use time::{Date, Time};
-
-use crate::system::Clock;
+use crate::egress::egress_manager::{EgressManager, VALUE};
+use crate::ingress::frame::MAX_PAYLOAD;
+use crate::ingress::ingress_manager::PAYLOAD;
+use crate::system::{Clock, Statistics};
+use crate::system::alarm::{self, Alarm, Days, MAX_ALARMS};
+use crate::system::bms::{BatteryManagement, State};
+use crate::system::filter::{self, QuietHours, SOURCE_LEN};
 
 use super::{System, Host};
 
@@ -16,19 +23,71 @@ use super::{System, Host};
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Error {
     ParseError,
-    UnknownSyscall
+    UnknownSyscall,
+    /// There is no room to carry out the syscall
+    NoMemory,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Syscall {
     /// Set the date - example: 
     /// "D0/12/02/2019"
     ///  day in week, date, month, year                         
+    ///
+    /// The day in week is 1 (Monday) to 7 (Sunday) and must match the date, or 0 to leave it unchecked.
     Date(Date),
     /// Set the time - example:
     /// "T12:21:11"
     /// hours, minutes, seconds
+    ///
+    /// Like [`Syscall::Date`], this sets the clock directly so should be sent in UTC.
     Time(Time),
+    /// Set the local date and time together, in ISO-8601 - example:
+    /// "I2019-12-02T12:21:11+01:00"
+    /// An offset of `Z` means UTC, without an offset the current [`System::utc_offset`] is kept.
+    DateTime(PrimitiveDateTime, Option<UtcOffset>),
+    /// Set the offset of local time from UTC, i.e after travelling - example:
+    /// "Z+01:00"
+    /// "Z" alone is UTC
+    UtcOffset(UtcOffset),
+    /// Stop notifications from a source alerting - example:
+    /// "F+Messenger"
+    Mute(String<SOURCE_LEN>),
+    /// Allow notifications from a source to alert again - example:
+    /// "F-Messenger"
+    Unmute(String<SOURCE_LEN>),
+    /// Unmute all sources - example:
+    /// "F"
+    ClearMuted,
+    /// Set the daily do not disturb period, or disable it if empty - example:
+    /// "Q22:00:00-07:00:00"
+    /// start time, end time
+    QuietHours(Option<QuietHours>),
+    /// Set an alarm, in local time, or remove it - example:
+    /// "A0=07:30:00/12345"
+    /// index, time, the days to repeat on from 1 (Monday) to 7 (Sunday)
+    ///
+    /// Without any days the alarm rings once, "A0-" removes the alarm.
+    Alarm(u8, Option<Alarm>),
+    /// Start a countdown timer, or cancel all timers if empty - example:
+    /// "C00:05:00"
+    /// hours, minutes, seconds
+    Timer(Option<Duration>),
+    /// Reply with the date and time - "GT"
+    /// i.e "2019-12-02T12:21:11"
+    GetTime,
+    /// Reply with the battery's state of charge and state - "GB"
+    /// i.e "87", "Charging"
+    GetBattery,
+    /// Reply with the kernel version - "GV"
+    /// i.e "2.0.0"
+    GetVersion,
+    /// Reply with whether an application is loaded and running, the RAM it uses and the progress of any
+    /// upload - "GA"
+    /// i.e "1", "0", "1024", "1024/1024"
+    GetAppStatus,
+    /// Reply with the host's statistics - "GS"
+    GetStats,
 }
 
 impl FromStr for Syscall {
@@ -37,11 +96,34 @@ impl FromStr for Syscall {
     /// Converts a string to a syscall
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         // first charater is the type
-        let t = s.as_bytes()[0];
-        let s: &str = &s[1..]; // remove first byte after we have the type
+        let t = *s.as_bytes().first().ok_or(Error::ParseError)?;
+        let s: &str = s.get(1..).ok_or(Error::UnknownSyscall)?; // remove first byte after we have the type
         match t {
             b'D' => Ok(Syscall::Date(Syscall::date_from_str(s)?)),
             b'T' => Ok(Syscall::Time(Syscall::time_from_str(s)?)),
+            b'I' => {
+                let (dt, offset) = Syscall::date_time_from_str(s)?;
+                Ok(Syscall::DateTime(dt, offset))
+            }
+            b'Z' => match s {
+                "" => Ok(Syscall::UtcOffset(UtcOffset::UTC)),
+                s => Ok(Syscall::UtcOffset(Syscall::utc_offset_from_str(s)?)),
+            },
+            b'F' => Syscall::filter_from_str(s),
+            b'Q' => Ok(Syscall::QuietHours(Syscall::quiet_hours_from_str(s)?)),
+            b'A' => {
+                let (index, alarm) = Syscall::alarm_from_str(s)?;
+                Ok(Syscall::Alarm(index, alarm))
+            }
+            b'C' => Ok(Syscall::Timer(Syscall::timer_from_str(s)?)),
+            b'G' => match s {
+                "T" => Ok(Syscall::GetTime),
+                "B" => Ok(Syscall::GetBattery),
+                "V" => Ok(Syscall::GetVersion),
+                "A" => Ok(Syscall::GetAppStatus),
+                "S" => Ok(Syscall::GetStats),
+                _ => Err(Error::UnknownSyscall),
+            },
             _ => Err(Error::UnknownSyscall)
         }
     }
@@ -49,7 +131,7 @@ impl FromStr for Syscall {
 
 impl Syscall {
 
-    pub fn execute(self, system: &mut System<impl Host>) {
+    pub fn execute(self, system: &mut System<impl Host>) -> Result<(), Error> {
         match self {
             Syscall::Date(date) => {
                 info!("Setting the date to {:?}", date);
@@ -59,82 +141,386 @@ impl Syscall {
                 info!("Setting the time to {:?}", time);
                 system.clock.set_time(&time);
             },
+            Syscall::DateTime(dt, offset) => {
+                info!("Setting the date and time to {:?} {:?}", dt, offset);
+                let offset = offset.unwrap_or(system.utc_offset);
+                let utc = dt
+                    .checked_sub(Duration::seconds(offset.whole_seconds() as i64))
+                    .ok_or(Error::ParseError)?;
+                system.utc_offset = offset;
+                system.clock.set_date_time(&utc);
+            },
+            Syscall::UtcOffset(offset) => {
+                info!("Setting the UTC offset to {:?}", offset);
+                system.utc_offset = offset;
+            },
+            Syscall::Mute(source) => {
+                info!("Muting {}", source);
+                system.nm.filter.mute(&source).map_err(|e| match e {
+                    filter::Error::Full => Error::NoMemory,
+                    filter::Error::TooLong => Error::ParseError,
+                })?;
+            },
+            Syscall::Unmute(source) => {
+                info!("Unmuting {}", source);
+                system.nm.filter.unmute(&source);
+            },
+            Syscall::ClearMuted => {
+                info!("Unmuting all sources");
+                system.nm.filter.clear();
+            },
+            Syscall::QuietHours(quiet_hours) => {
+                info!("Setting the quiet hours to {:?}", quiet_hours);
+                system.nm.filter.set_quiet_hours(quiet_hours);
+            },
+            Syscall::Alarm(index, alarm) => {
+                info!("Setting alarm {} to {:?}", index, alarm);
+                system.alarms.set_alarm(index as usize, alarm).map_err(|_| Error::ParseError)?;
+            },
+            Syscall::Timer(Some(duration)) => {
+                info!("Starting a timer for {:?}", duration);
+                let now = system.clock.get_date_time();
+                system.alarms.start_timer(now, duration).map_err(|e| match e {
+                    alarm::Error::Full => Error::NoMemory,
+                    alarm::Error::InvalidIndex => Error::ParseError,
+                })?;
+            },
+            Syscall::Timer(None) => {
+                info!("Cancelling all timers");
+                system.alarms.cancel_timers();
+            },
+            Syscall::GetTime => {
+                let now = system.local_date_time();
+                let (date, time) = (now.date(), now.time());
+                let (hours, minutes, _) = system.utc_offset.as_hms();
+                reply(&mut system.em, "GT", [format_args!(
+                    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}{:02}:{:02}",
+                    date.year(),
+                    date.month() as u8,
+                    date.day(),
+                    time.hour(),
+                    time.minute(),
+                    time.second(),
+                    if system.utc_offset.is_negative() { '-' } else { '+' },
+                    hours.unsigned_abs(),
+                    minutes.unsigned_abs()
+                )])?;
+            },
+            Syscall::GetBattery => {
+                let state = match system.bms.state() {
+                    State::Draining => "Draining",
+                    State::Charging => "Charging",
+                    State::Charged => "Charged",
+                };
+                let soc = system.bms.soc();
+                reply(&mut system.em, "GB", [&soc as &dyn Display, &state])?;
+            },
+            Syscall::GetVersion => {
+                reply(&mut system.em, "GV", [crate::VERSION])?;
+            },
+            Syscall::GetAppStatus => {
+                let status = system.am.status();
+                let mut progress: String<24> = String::new();
+                if let Some(p) = system.am.progress() {
+                    write!(progress, "{}/{}", p.received, p.total).ok();
+                }
+                reply(
+                    &mut system.em,
+                    "GA",
+                    [&(status.is_loaded as u8) as &dyn Display, &(status.is_running as u8), &status.ram_used, &progress],
+                )?;
+            },
+            Syscall::GetStats => {
+                reply(&mut system.em, "GS", system.stats.stats())?;
+            },
+        }
+        Ok(())
+    }
+
+    fn filter_from_str(s: &str) -> Result<Syscall, Error> {
+        match s.as_bytes().first() {
+            None => Ok(Syscall::ClearMuted),
+            Some(b'+') => Ok(Syscall::Mute(Syscall::source_from_str(&s[1..])?)),
+            Some(b'-') => Ok(Syscall::Unmute(Syscall::source_from_str(&s[1..])?)),
+            Some(_) => Err(Error::ParseError),
+        }
+    }
+
+    /// Parse an alarm, i.e "0=07:30:00/67", or its removal, i.e "0-"
+    pub fn alarm_from_str(s: &str) -> Result<(u8, Option<Alarm>), Error> {
+        let index = match s.as_bytes().first() {
+            Some(&digit @ b'0'..=b'9') if ((digit - b'0') as usize) < MAX_ALARMS => digit - b'0',
+            _ => return Err(Error::ParseError),
+        };
+        match &s[1..] {
+            "-" => Ok((index, None)),
+            s => {
+                let s = s.strip_prefix('=').ok_or(Error::ParseError)?;
+                let (time, days) = s.split_once('/').unwrap_or((s, ""));
+                let days = days.bytes().try_fold(Days::NONE, |days, day| {
+                    let day = match day {
+                        b'1' => Weekday::Monday,
+                        b'2' => Weekday::Tuesday,
+                        b'3' => Weekday::Wednesday,
+                        b'4' => Weekday::Thursday,
+                        b'5' => Weekday::Friday,
+                        b'6' => Weekday::Saturday,
+                        b'7' => Weekday::Sunday,
+                        _ => return Err(Error::ParseError),
+                    };
+                    Ok(days.with(day))
+                })?;
+                Ok((index, Some(Alarm::new(Syscall::time_from_str(time)?, days))))
+            }
+        }
+    }
+
+    /// Parse the duration of a timer, i.e "00:05:00", or nothing to cancel the timers
+    pub fn timer_from_str(s: &str) -> Result<Option<Duration>, Error> {
+        if s.is_empty() {
+            return Ok(None);
+        }
+        let duration = Syscall::time_from_str(s)? - Time::MIDNIGHT;
+        if duration.is_zero() {
+            return Err(Error::ParseError);
+        }
+        Ok(Some(duration))
+    }
+
+    fn source_from_str(s: &str) -> Result<String<SOURCE_LEN>, Error> {
+        if s.is_empty() {
+            return Err(Error::ParseError);
+        }
+        String::from_str(s).map_err(|_| Error::ParseError)
+    }
+
+    pub fn quiet_hours_from_str(s: &str) -> Result<Option<QuietHours>, Error> {
+        if s.is_empty() {
+            return Ok(None);
         }
+        let (start, end) = s.split_once('-').ok_or(Error::ParseError)?;
+        Ok(Some(QuietHours {
+            start: Syscall::time_from_str(start)?,
+            end: Syscall::time_from_str(end)?,
+        }))
     }
 
     pub fn date_from_str(s: &str) -> Result<Date, Error> {
         let mut vals = [0i32; 4];
-        for (idx, number) in s.split('/').enumerate() {
+        let mut fields = s.split('/');
+        for val in vals.iter_mut() {
+            let number = fields.next().ok_or(Error::ParseError)?;
             match number.parse() {
-                Ok(val) => vals[idx] = val,
+                Ok(v) => *val = v,
                 Err(e) => {
                     error!("Failed to convert {} into a integer due to {:?}", number, e);
                     return Err(Error::ParseError)
                 }
             }
         }
-        // vals[0] // TODO day in week
-        Ok(Date::from_calendar_date(vals[3], (vals[2] as u8).try_into().map_err(|_| Error::ParseError)?, vals[1] as u8).map_err(|_| Error::ParseError)?)
+        if fields.next().is_some() {
+            return Err(Error::ParseError);
+        }
+        let month = u8::try_from(vals[2]).ok().and_then(|m| m.try_into().ok()).ok_or(Error::ParseError)?;
+        let day = u8::try_from(vals[1]).map_err(|_| Error::ParseError)?;
+        let date = Date::from_calendar_date(vals[3], month, day).map_err(|_| Error::ParseError)?;
+        match vals[0] {
+            0 => Ok(date),
+            weekday if weekday == date.weekday().number_from_monday() as i32 => Ok(date),
+            weekday => {
+                error!("{} is not day {} of the week", date, weekday);
+                Err(Error::ParseError)
+            }
+        }
+    }
+
+    /// Parse an ISO-8601 date and time, i.e "2019-12-02T12:21:11"
+    pub fn date_time_from_str(s: &str) -> Result<(PrimitiveDateTime, Option<UtcOffset>), Error> {
+        let (date, time) = s.split_once('T').ok_or(Error::ParseError)?;
+        let (time, offset) = match time.find(['Z', '+', '-']) {
+            Some(idx) => (&time[..idx], Some(Syscall::utc_offset_from_str(&time[idx..])?)),
+            None => (time, None),
+        };
+        let mut fields = date.split('-');
+        let mut field = || fields.next().and_then(|f| f.parse::<u16>().ok()).ok_or(Error::ParseError);
+        let (year, month, day) = (field()?, field()?, field()?);
+        if fields.next().is_some() {
+            return Err(Error::ParseError);
+        }
+        let month = u8::try_from(month).ok().and_then(|m| m.try_into().ok()).ok_or(Error::ParseError)?;
+        let day = u8::try_from(day).map_err(|_| Error::ParseError)?;
+        let date = Date::from_calendar_date(year as i32, month, day).map_err(|_| Error::ParseError)?;
+        Ok((PrimitiveDateTime::new(date, Syscall::time_from_str(time)?), offset))
+    }
+
+    /// Parse an ISO-8601 UTC offset, i.e "+01:00", "-05:30" or "Z"
+    pub fn utc_offset_from_str(s: &str) -> Result<UtcOffset, Error> {
+        let sign = match s.as_bytes().first() {
+            Some(b'Z') if s.len() == 1 => return Ok(UtcOffset::UTC),
+            Some(b'+') => 1,
+            Some(b'-') => -1,
+            _ => return Err(Error::ParseError),
+        };
+        let (hours, minutes) = s[1..].split_once(':').ok_or(Error::ParseError)?;
+        let hours: i8 = hours.parse().map_err(|_| Error::ParseError)?;
+        let minutes: i8 = minutes.parse().map_err(|_| Error::ParseError)?;
+        if hours < 0 || minutes < 0 {
+            return Err(Error::ParseError);
+        }
+        UtcOffset::from_hms(sign * hours, sign * minutes, 0).map_err(|_| Error::ParseError)
     }
 
     pub fn time_from_str(s: &str) -> Result<Time, Error> {
         let mut vals = [0u8; 3];
-        for (idx, number) in s.split(':').enumerate() {
+        let mut fields = s.split(':');
+        for val in vals.iter_mut() {
+            let number = fields.next().ok_or(Error::ParseError)?;
             match number.parse() {
-                Ok(val) => vals[idx] = val,
+                Ok(v) => *val = v,
                 Err(e) => {
                     error!("Failed to convert {} into a integer due to {:?}", number, e);
                     return Err(Error::ParseError)
                 }
             }
         }
-        Ok(Time::from_hms(vals[0], vals[1], vals[2]).map_err(|_| Error::ParseError)?)
+        if fields.next().is_some() {
+            return Err(Error::ParseError);
+        }
+        Time::from_hms(vals[0], vals[1], vals[2]).map_err(|_| Error::ParseError)
+    }
+}
+
+/// Send the result of a getter, `name` followed by each of the `values`
+fn reply<T: Display>(em: &mut EgressManager, name: &str, values: impl IntoIterator<Item = T>) -> Result<(), Error> {
+    let mut payload: String<MAX_PAYLOAD> = String::new();
+    write!(payload, "{}", name).map_err(|_| Error::NoMemory)?;
+    for value in values {
+        write!(payload, "{}{}", PAYLOAD as char, value).map_err(|_| Error::NoMemory)?;
     }
+    em.send(VALUE, payload.as_bytes()).map_err(|_| Error::NoMemory)
 }
 
+#[cfg(test)]
+mod test {
+    use super::*;
+    use time::Month;
+
+    #[test]
+    fn syscall_date_works() {
+        let actual = Date::from_calendar_date(2019, Month::April, 1).unwrap();
+        assert_eq!(Syscall::from_str("D01/01/04/2019"), Ok(Syscall::Date(actual)));
+        assert_ne!(Syscall::from_str("D02/02/04/2019"), Ok(Syscall::Date(actual)));
+    }
+
+    #[test]
+    fn syscall_date_weekday() {
+        // the 2nd of December 2019 was a Monday
+        let actual = Date::from_calendar_date(2019, Month::December, 2).unwrap();
+        assert_eq!(Syscall::from_str("D0/02/12/2019"), Ok(Syscall::Date(actual)));
+        assert_eq!(Syscall::from_str("D1/02/12/2019"), Ok(Syscall::Date(actual)));
+        assert_eq!(Syscall::from_str("D3/02/12/2019"), Err(Error::ParseError));
+        assert_eq!(Syscall::from_str("D8/02/12/2019"), Err(Error::ParseError));
+    }
 
-// #[cfg(test)]
-// mod test {
-//     use super::*;
-//     #[test]
-//     fn syscall_date_works() {
-//         let actual = Date::new(1.day(), 1.date(), 4.month(), 2019.year());
-
-//         let working = Syscall::from_str("D01/01/04/2019").unwrap();
-//         match working {
-//             Syscall::Date(d) => {
-//                 assert_eq!(actual, d);
-//             }
-//             _ => panic!("wrong syscall type")
-//         }
-
-//         let wrong = Syscall::from_str("D02/01/04/2019").unwrap();
-//         match wrong {
-//             Syscall::Date(d) => {
-//                 assert_ne!(actual, d);
-//             }
-//             _ => panic!("wrong syscall type")
-//         }
-//     }
-
-//     #[test]
-//     fn syscall_time_works() {
-//         let actual = Time::new(0.hours(), 0.minutes(), 0.seconds(), false);
-
-//         let working = Syscall::from_str("T00:00:00").unwrap();
-//         match working {
-//             Syscall::Time(t) => {
-//                 assert_eq!(actual, t);
-//             }
-//             _ => panic!("wrong syscall type")
-//         }
-
-//         let working = Syscall::from_str("T01:00:00").unwrap();
-//         match working {
-//             Syscall::Time(t) => {
-//                 assert_ne!(actual, t);
-//             }
-//             _ => panic!("wrong syscall type")
-//         }
-//     }
-// }
\ No newline at end of file
+    #[test]
+    fn syscall_iso_date_time_works() {
+        let actual = PrimitiveDateTime::new(
+            Date::from_calendar_date(2019, Month::December, 2).unwrap(),
+            Time::from_hms(12, 21, 11).unwrap(),
+        );
+        assert_eq!(Syscall::from_str("I2019-12-02T12:21:11"), Ok(Syscall::DateTime(actual, None)));
+        assert_eq!(Syscall::from_str("I2019-12-02T12:21:11Z"), Ok(Syscall::DateTime(actual, Some(UtcOffset::UTC))));
+        let offset = UtcOffset::from_hms(-5, -30, 0).unwrap();
+        assert_eq!(Syscall::from_str("I2019-12-02T12:21:11-05:30"), Ok(Syscall::DateTime(actual, Some(offset))));
+        assert_eq!(Syscall::from_str("I2019-12-02T12:21:11+05"), Err(Error::ParseError));
+        assert_eq!(Syscall::from_str("I2019-12-02"), Err(Error::ParseError));
+        assert_eq!(Syscall::from_str("I2019-12-02T12:21"), Err(Error::ParseError));
+        assert_eq!(Syscall::from_str("I2019-13-02T12:21:11"), Err(Error::ParseError));
+        assert_eq!(Syscall::from_str("I2019-02-30T12:21:11"), Err(Error::ParseError));
+        assert_eq!(Syscall::from_str("I2019-12-02-01T12:21:11"), Err(Error::ParseError));
+    }
+
+    #[test]
+    fn syscall_utc_offset_works() {
+        assert_eq!(Syscall::from_str("Z"), Ok(Syscall::UtcOffset(UtcOffset::UTC)));
+        assert_eq!(Syscall::from_str("ZZ"), Ok(Syscall::UtcOffset(UtcOffset::UTC)));
+        assert_eq!(Syscall::from_str("Z+01:00"), Ok(Syscall::UtcOffset(UtcOffset::from_hms(1, 0, 0).unwrap())));
+        assert_eq!(Syscall::from_str("Z-09:30"), Ok(Syscall::UtcOffset(UtcOffset::from_hms(-9, -30, 0).unwrap())));
+        assert_eq!(Syscall::from_str("Z+-1:00"), Err(Error::ParseError));
+        assert_eq!(Syscall::from_str("Z+24:00"), Err(Error::ParseError));
+        assert_eq!(Syscall::from_str("Z+01:60"), Err(Error::ParseError));
+        assert_eq!(Syscall::from_str("Z01:00"), Err(Error::ParseError));
+    }
+
+    #[test]
+    fn syscall_time_works() {
+        assert_eq!(Syscall::from_str("T00:00:00"), Ok(Syscall::Time(Time::MIDNIGHT)));
+        assert_eq!(Syscall::from_str("T01:00:00"), Ok(Syscall::Time(Time::from_hms(1, 0, 0).unwrap())));
+    }
+
+    #[test]
+    fn syscall_malformed_input() {
+        assert_eq!(Syscall::from_str(""), Err(Error::ParseError));
+        assert_eq!(Syscall::from_str("X"), Err(Error::UnknownSyscall));
+        assert_eq!(Syscall::from_str("\u{e9}"), Err(Error::UnknownSyscall));
+        assert_eq!(Syscall::from_str("D"), Err(Error::ParseError));
+        assert_eq!(Syscall::date_from_str("1/2/3/4/5/6"), Err(Error::ParseError));
+        assert_eq!(Syscall::date_from_str("0/1/2"), Err(Error::ParseError));
+        assert_eq!(Syscall::date_from_str("0/1/300/2019"), Err(Error::ParseError));
+        assert_eq!(Syscall::date_from_str("0/-1/1/2019"), Err(Error::ParseError));
+        assert_eq!(Syscall::time_from_str("1:2:3:4"), Err(Error::ParseError));
+        assert_eq!(Syscall::time_from_str("1:2"), Err(Error::ParseError));
+    }
+
+    #[test]
+    fn syscall_filter_works() {
+        assert_eq!(Syscall::from_str("F+Messenger"), Ok(Syscall::Mute(String::from("Messenger"))));
+        assert_eq!(Syscall::from_str("F-Messenger"), Ok(Syscall::Unmute(String::from("Messenger"))));
+        assert_eq!(Syscall::from_str("F"), Ok(Syscall::ClearMuted));
+        assert_eq!(Syscall::from_str("F+"), Err(Error::ParseError));
+        assert_eq!(Syscall::from_str("FMessenger"), Err(Error::ParseError));
+        assert_eq!(Syscall::from_str(&std::format!("F+{}", "x".repeat(SOURCE_LEN + 1))), Err(Error::ParseError));
+    }
+
+    #[test]
+    fn syscall_alarm_works() {
+        let time = Time::from_hms(7, 30, 0).unwrap();
+        let weekend = Days::NONE.with(Weekday::Saturday).with(Weekday::Sunday);
+        assert_eq!(Syscall::from_str("A0=07:30:00"), Ok(Syscall::Alarm(0, Some(Alarm::new(time, Days::NONE)))));
+        assert_eq!(Syscall::from_str("A3=07:30:59/67"), Ok(Syscall::Alarm(3, Some(Alarm::new(time, weekend)))));
+        assert_eq!(Syscall::from_str("A1-"), Ok(Syscall::Alarm(1, None)));
+        assert_eq!(Syscall::from_str("A"), Err(Error::ParseError));
+        assert_eq!(Syscall::from_str("A4=07:30:00"), Err(Error::ParseError));
+        assert_eq!(Syscall::from_str("A0=07:30:00/8"), Err(Error::ParseError));
+        assert_eq!(Syscall::from_str("A0=07:30"), Err(Error::ParseError));
+        assert_eq!(Syscall::from_str("A007:30:00"), Err(Error::ParseError));
+    }
+
+    #[test]
+    fn syscall_timer_works() {
+        assert_eq!(Syscall::from_str("C00:05:00"), Ok(Syscall::Timer(Some(Duration::minutes(5)))));
+        assert_eq!(Syscall::from_str("C"), Ok(Syscall::Timer(None)));
+        assert_eq!(Syscall::from_str("C00:00:00"), Err(Error::ParseError));
+        assert_eq!(Syscall::from_str("C5"), Err(Error::ParseError));
+    }
+
+    #[test]
+    fn syscall_getters() {
+        assert_eq!(Syscall::from_str("GT"), Ok(Syscall::GetTime));
+        assert_eq!(Syscall::from_str("GS"), Ok(Syscall::GetStats));
+        assert_eq!(Syscall::from_str("G"), Err(Error::UnknownSyscall));
+        assert_eq!(Syscall::from_str("GTT"), Err(Error::UnknownSyscall));
+    }
+
+    #[test]
+    fn syscall_quiet_hours_works() {
+        let quiet = QuietHours {
+            start: Time::from_hms(22, 0, 0).unwrap(),
+            end: Time::from_hms(7, 30, 0).unwrap(),
+        };
+        assert_eq!(Syscall::from_str("Q22:00:00-07:30:00"), Ok(Syscall::QuietHours(Some(quiet))));
+        assert_eq!(Syscall::from_str("Q"), Ok(Syscall::QuietHours(None)));
+        assert_eq!(Syscall::from_str("Q22:00:00"), Err(Error::ParseError));
+        assert_eq!(Syscall::from_str("Q22:00:00-25:00:00"), Err(Error::ParseError));
+    }
+}